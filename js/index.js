@@ -66,21 +66,21 @@ window.BasicAudioPlayer = class {
 }
 
 const EMPTY = 255;
-window.draw_layer = function (context, image, sprite_width, sprite_height, data, width, height) {
+window.draw_layer = function (context, image, sprite_width, sprite_height, dest_width, dest_height, data, width, height) {
     const cells = new Uint8Array(imports.bg.memory.buffer, data, width * height * 2);
     for (var row = 0; row < height; row++) {
-        let dest_y = row * sprite_height;
+        let dest_y = row * dest_height;
 
         for (var col = 0; col < width; col++) {
             let index = (row * width + col) * 2;
-            let source_x = cells[index] * sprite_width;
-            let source_y = cells[index + 1] * sprite_height;
-
-            if (source_x === EMPTY && source_y === EMPTY) {
+            if (cells[index] === EMPTY && cells[index + 1] === EMPTY) {
                 continue;
             }
 
-            let dest_x = col * sprite_width;
+            let source_x = cells[index] * sprite_width;
+            let source_y = cells[index + 1] * sprite_height;
+
+            let dest_x = col * dest_width;
 
             context.drawImage(
                 image, 
@@ -90,8 +90,8 @@ window.draw_layer = function (context, image, sprite_width, sprite_height, data,
                 sprite_height, 
                 dest_x,
                 dest_y,
-                sprite_width, 
-                sprite_height);
+                dest_width, 
+                dest_height);
         }
     }
 }
@@ -154,10 +154,31 @@ function init (rust) {
         let rect = canvas.getBoundingClientRect();
         let x = evt.clientX - rect.left;
         let y = evt.clientY - rect.top;
-        app.left_click(x / SCALE, y / SCALE);
+        app.left_click(x, y);
+    });
+    canvas.addEventListener("mousemove", evt => {
+        let rect = canvas.getBoundingClientRect();
+        let x = evt.clientX - rect.left;
+        let y = evt.clientY - rect.top;
+        app.mouse_move(x, y);
     });
 
-    context.scale(SCALE, SCALE);
+    // the canvas shrinks to fit small windows, but never grows past the size
+    // it is given in the page.
+    const max_width = canvas.width;
+    const max_height = canvas.height;
+    const border = canvas.offsetWidth - canvas.clientWidth;
+    let fit_canvas = () => {
+        canvas.width = Math.max(1, Math.min(max_width, window.innerWidth - border));
+        canvas.height = Math.max(1, Math.min(max_height, window.innerHeight - border));
+        // resizing the canvas resets its settings.
+        context.imageSmoothingEnabled = false;
+        app.resize(canvas.width, canvas.height);
+    };
+
+    app.set_scale(SCALE);
+    fit_canvas();
+    window.addEventListener("resize", fit_canvas);
 
     let blocks = document.getElementById("blocks");
     let sprites = document.getElementById("sprites");
@@ -1,17 +1,35 @@
 use std::collections::HashMap;
 
 mod transition;
+mod button;
 mod return_btn;
 mod brief;
+mod instrumented;
+mod confirm_dialog;
 
 pub use transition::Transition;
+pub use button::{Button, IconSheet};
 pub use return_btn::ReturnButton;
 pub use brief::Brief;
+pub use instrumented::{Instrumented, InteractionStats};
+pub use confirm_dialog::ConfirmDialog;
 
+use crate::level::cell::Signals;
+use crate::level::SuccessState;
 use crate::point::Point;
 use crate::util::with_saved_context;
 use crate::{Assets, Context2D, KeyboardState, SpriteSheet};
 
+/// The keys that undo a move. Z changes the palette's direction while a
+/// level is being edited, so it only undoes during play.
+pub fn undo_key_codes(is_editing: bool) -> &'static [&'static str] {
+    if is_editing {
+        &["KeyU", "Backslash"]
+    } else {
+        &["KeyU", "KeyZ", "Backslash"]
+    }
+}
+
 pub trait Component {
     type DrawArgs;
 
@@ -19,6 +37,9 @@ pub trait Component {
     fn step(&mut self, _dt: f64, _keyboard_state: &KeyboardState) -> NextScene {
         NextScene::Continue
     }
+    // advances animations by `dt` milliseconds. called every frame before
+    // `step`, and must not read input or change scene.
+    fn animate(&mut self, _dt: f64) {}
     fn draw(&self, context: &Context2D, assets: &Assets, args: Self::DrawArgs);
 
     // performs a click event on a given component. returns true if the event
@@ -26,6 +47,8 @@ pub trait Component {
     fn click(&mut self, _point: Point<i32>) -> bool {
         false
     }
+    // tells the component where the mouse is.
+    fn hover(&mut self, _point: Point<i32>) {}
     /// Default behaviour assumes an AABB
     fn in_boundary(&self, point: Point<i32>) -> bool {
         self.bounding_rect().inside(point)
@@ -64,19 +87,256 @@ pub trait Component {
     fn jumped_into(&mut self, object: Object) {
         self.called_into(object)
     }
+
+    /// Passes on the signal channels set from outside the game.
+    fn set_signals(&mut self, _signals: Signals) {}
+    /// Switches to another player's saves, loading whatever they have saved.
+    fn set_profile(&mut self, _profile: &str) {}
+    /// Sets how fast moves animate, where 2.0 is twice as fast as normal.
+    fn set_animation_speed(&mut self, _speed: f64) {}
+    /// Turns off transitions and other movement that isn't needed to play.
+    fn set_reduced_motion(&mut self, _reduces_motion: bool) {}
+    /// Lays the component out again for a view of the given size, in game
+    /// pixels, measured from the component's own top left.
+    fn on_resize(&mut self, _dimensions: Point<i32>) {}
+
+    /// Whether the level is won, lost or still going, if this is a level.
+    fn success_state(&self) -> Option<SuccessState> {
+        None
+    }
+    /// The index of the test being run and the number of tests, if this
+    /// level has tests.
+    fn test_progress(&self) -> Option<(usize, usize)> {
+        None
+    }
+    /// How the player has interacted with this scene, if it is recorded.
+    fn interaction_stats(&self) -> Option<InteractionStats> {
+        None
+    }
+    /// The keys that undo a move in this scene, see `undo_key_codes`.
+    fn undo_keys(&self) -> &'static [&'static str] {
+        undo_key_codes(false)
+    }
+
+    /// Plays a solution written as a move list from the start of the level.
+    fn apply_solution(&mut self, _solution: &str) -> Result<(), String> {
+        Err("There is no level to apply a solution to".to_owned())
+    }
+
+    /// The RON string of the editable state of this component, if it has any.
+    fn serialize_state(&self) -> Option<String> {
+        None
+    }
+
+    /// Panics with a description of the problem if an invariant of this
+    /// component has been broken.
+    #[cfg(debug_assertions)]
+    fn check_invariants(&self) {
+        self.bounding_rect().check_invariants();
+    }
+}
+
+/// A component that adds something around another component. Every
+/// `Wrapper` is a `Component` that passes each event on to `inner`, so an
+/// implementation only needs to override the methods it handles itself.
+pub trait Wrapper {
+    type Inner: Component;
+
+    fn inner(&self) -> &Self::Inner;
+    fn inner_mut(&mut self) -> &mut Self::Inner;
+
+    fn bounding_rect(&self) -> Rect {
+        self.inner().bounding_rect()
+    }
+    fn step(&mut self, dt: f64, keyboard_state: &KeyboardState) -> NextScene {
+        self.inner_mut().step(dt, keyboard_state)
+    }
+    fn animate(&mut self, dt: f64) {
+        self.inner_mut().animate(dt)
+    }
+    fn draw(&self, context: &Context2D, assets: &Assets, args: WrappedDrawArgs<Self>) {
+        self.inner().draw(context, assets, args)
+    }
+    fn click(&mut self, point: Point<i32>) -> bool {
+        self.inner_mut().click(point)
+    }
+    fn hover(&mut self, point: Point<i32>) {
+        self.inner_mut().hover(point)
+    }
+    fn in_boundary(&self, point: Point<i32>) -> bool {
+        self.inner().in_boundary(point)
+    }
+    fn returned_into(&mut self, object: Object) {
+        self.inner_mut().returned_into(object)
+    }
+    fn called_into(&mut self, object: Object) {
+        self.inner_mut().called_into(object)
+    }
+    fn jumped_into(&mut self, object: Object) {
+        self.inner_mut().jumped_into(object)
+    }
+    fn set_signals(&mut self, signals: Signals) {
+        self.inner_mut().set_signals(signals)
+    }
+    fn set_profile(&mut self, profile: &str) {
+        self.inner_mut().set_profile(profile)
+    }
+    fn set_animation_speed(&mut self, speed: f64) {
+        self.inner_mut().set_animation_speed(speed)
+    }
+    fn set_reduced_motion(&mut self, reduces_motion: bool) {
+        self.inner_mut().set_reduced_motion(reduces_motion)
+    }
+    fn on_resize(&mut self, dimensions: Point<i32>) {
+        self.inner_mut().on_resize(dimensions)
+    }
+    fn success_state(&self) -> Option<SuccessState> {
+        self.inner().success_state()
+    }
+    fn test_progress(&self) -> Option<(usize, usize)> {
+        self.inner().test_progress()
+    }
+    fn interaction_stats(&self) -> Option<InteractionStats> {
+        self.inner().interaction_stats()
+    }
+    fn undo_keys(&self) -> &'static [&'static str] {
+        self.inner().undo_keys()
+    }
+    fn apply_solution(&mut self, solution: &str) -> Result<(), String> {
+        self.inner_mut().apply_solution(solution)
+    }
+    fn serialize_state(&self) -> Option<String> {
+        self.inner().serialize_state()
+    }
+    #[cfg(debug_assertions)]
+    fn check_invariants(&self) {
+        self.inner().check_invariants()
+    }
+}
+type WrappedDrawArgs<W> = <<W as Wrapper>::Inner as Component>::DrawArgs;
+impl<W: Wrapper> Component for W {
+    type DrawArgs = WrappedDrawArgs<W>;
+    fn bounding_rect(&self) -> Rect {
+        Wrapper::bounding_rect(self)
+    }
+    fn step(&mut self, dt: f64, keyboard_state: &KeyboardState) -> NextScene {
+        Wrapper::step(self, dt, keyboard_state)
+    }
+    fn animate(&mut self, dt: f64) {
+        Wrapper::animate(self, dt)
+    }
+    fn draw(&self, context: &Context2D, assets: &Assets, args: Self::DrawArgs) {
+        Wrapper::draw(self, context, assets, args)
+    }
+    fn click(&mut self, point: Point<i32>) -> bool {
+        Wrapper::click(self, point)
+    }
+    fn hover(&mut self, point: Point<i32>) {
+        Wrapper::hover(self, point)
+    }
+    fn in_boundary(&self, point: Point<i32>) -> bool {
+        Wrapper::in_boundary(self, point)
+    }
+    fn returned_into(&mut self, object: Object) {
+        Wrapper::returned_into(self, object)
+    }
+    fn called_into(&mut self, object: Object) {
+        Wrapper::called_into(self, object)
+    }
+    fn jumped_into(&mut self, object: Object) {
+        Wrapper::jumped_into(self, object)
+    }
+    fn set_signals(&mut self, signals: Signals) {
+        Wrapper::set_signals(self, signals)
+    }
+    fn set_profile(&mut self, profile: &str) {
+        Wrapper::set_profile(self, profile)
+    }
+    fn set_animation_speed(&mut self, speed: f64) {
+        Wrapper::set_animation_speed(self, speed)
+    }
+    fn set_reduced_motion(&mut self, reduces_motion: bool) {
+        Wrapper::set_reduced_motion(self, reduces_motion)
+    }
+    fn on_resize(&mut self, dimensions: Point<i32>) {
+        Wrapper::on_resize(self, dimensions)
+    }
+    fn success_state(&self) -> Option<SuccessState> {
+        Wrapper::success_state(self)
+    }
+    fn test_progress(&self) -> Option<(usize, usize)> {
+        Wrapper::test_progress(self)
+    }
+    fn interaction_stats(&self) -> Option<InteractionStats> {
+        Wrapper::interaction_stats(self)
+    }
+    fn undo_keys(&self) -> &'static [&'static str] {
+        Wrapper::undo_keys(self)
+    }
+    fn apply_solution(&mut self, solution: &str) -> Result<(), String> {
+        Wrapper::apply_solution(self, solution)
+    }
+    fn serialize_state(&self) -> Option<String> {
+        Wrapper::serialize_state(self)
+    }
+    #[cfg(debug_assertions)]
+    fn check_invariants(&self) {
+        Wrapper::check_invariants(self)
+    }
 }
 
 // A generic data object, kind of like JSON.
 #[must_use]
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Object {
     Null,
     Bool(bool),
     Int(i64),
+    Float(f64),
     Str(String),
     Array(Vec<Object>),
     Map(HashMap<String, Object>),
 }
+/// Levels return a map when they are finished: "solved" says whether the
+/// level was solved, and "moves" and "time" say how it went, if known.
+impl Object {
+    pub fn completion(solved: bool, moves: i64) -> Self {
+        Object::Map(HashMap::new())
+            .with("solved", Object::Bool(solved))
+            .with("moves", Object::Int(moves))
+    }
+    /// The same object with `key` set, if it is a map.
+    pub fn with(mut self, key: &str, value: Object) -> Self {
+        if let Object::Map(map) = &mut self {
+            map.insert(key.to_owned(), value);
+        }
+        self
+    }
+    pub fn get(&self, key: &str) -> Option<&Object> {
+        match self {
+            Object::Map(map) => map.get(key),
+            _ => None,
+        }
+    }
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key) {
+            Some(Object::Bool(value)) => Some(*value),
+            _ => None,
+        }
+    }
+    pub fn get_int(&self, key: &str) -> Option<i64> {
+        match self.get(key) {
+            Some(Object::Int(value)) => Some(*value),
+            _ => None,
+        }
+    }
+    pub fn get_float(&self, key: &str) -> Option<f64> {
+        match self.get(key) {
+            Some(Object::Float(value)) => Some(*value),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum NextScene {
@@ -144,6 +404,14 @@ impl Rect {
         let new_top = bottom - new_dimensions.y();
         Rect::new(Point(self.top_left.x(), new_top), new_dimensions)
     }
+    #[cfg(debug_assertions)]
+    pub fn check_invariants(&self) {
+        assert!(
+            self.dimensions.x() >= 0 && self.dimensions.y() >= 0,
+            "Rect has negative dimensions: {:?}",
+            self
+        );
+    }
     pub fn inside(&self, point: Point<i32>) -> bool {
         let Rect {
             top_left,
@@ -211,14 +479,17 @@ impl<T> std::ops::DerefMut for Translation<T> {
         &mut self.component
     }
 }
-impl<T: Component> Component for Translation<T> {
-    type DrawArgs = T::DrawArgs;
+impl<T: Component> Wrapper for Translation<T> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.component
+    }
+    fn inner_mut(&mut self) -> &mut T {
+        &mut self.component
+    }
     fn bounding_rect(&self) -> Rect {
         self.component.bounding_rect().translate(self.translation)
     }
-    fn step(&mut self, dt: f64,  keyboard_state: &KeyboardState) -> NextScene {
-        self.component.step(dt, keyboard_state)
-    }
     fn click(&mut self, point: Point<i32>) -> bool {
         if !Component::in_boundary(self, point) {
             return false;
@@ -231,7 +502,7 @@ impl<T: Component> Component for Translation<T> {
         let local_point = point - self.translation;
         self.component.in_boundary(local_point)
     }
-    fn draw(&self, context: &Context2D, assets: &Assets, args: Self::DrawArgs) {
+    fn draw(&self, context: &Context2D, assets: &Assets, args: T::DrawArgs) {
         with_saved_context(context, || {
             context
                 .translate(self.translation.x().into(), self.translation.y().into())
@@ -239,13 +510,72 @@ impl<T: Component> Component for Translation<T> {
             self.component.draw(context, assets, args);
         });
     }
-    fn returned_into(&mut self, object: Object) {
-        self.component.returned_into(object)
+    fn hover(&mut self, point: Point<i32>) {
+        let local_point = self.get_local_point(point);
+        self.component.hover(local_point)
     }
-    fn called_into(&mut self, object: Object) {
-        self.component.called_into(object)
+    fn on_resize(&mut self, dimensions: Point<i32>) {
+        self.component.on_resize(dimensions - self.translation)
     }
-    fn jumped_into(&mut self, object: Object) {
-        self.component.jumped_into(object)
+}
+
+#[cfg(test)]
+mod tests {
+    // not a glob, as `Wrapper`'s methods share their names with `Component`'s.
+    use super::{Assets, Component, Context2D, Object, Point, Rect, SuccessState, Translation};
+
+    // remembers what reaches it.
+    #[derive(Default)]
+    struct Probe {
+        clicked_at: Option<Point<i32>>,
+        speed: f64,
+    }
+    impl Component for Probe {
+        type DrawArgs = ();
+        fn bounding_rect(&self) -> Rect {
+            Rect::new(Point(0, 0), Point(10, 10))
+        }
+        fn draw(&self, _context: &Context2D, _assets: &Assets, _args: ()) {}
+        fn click(&mut self, point: Point<i32>) -> bool {
+            self.clicked_at = Some(point);
+            true
+        }
+        fn set_animation_speed(&mut self, speed: f64) {
+            self.speed = speed;
+        }
+        fn success_state(&self) -> Option<SuccessState> {
+            Some(SuccessState::Running)
+        }
+    }
+
+    #[test]
+    fn wrappers_forward_what_they_do_not_handle() {
+        let mut translation = Translation::new(Point(20, 30), Probe::default());
+        assert_eq!(translation.bounding_rect().top_left, Point(20, 30));
+        assert!(!translation.click(Point(5, 5)));
+        assert!(translation.click(Point(25, 31)));
+        assert_eq!(translation.clicked_at, Some(Point(5, 1)));
+
+        translation.set_animation_speed(2.0);
+        assert_eq!(translation.speed, 2.0);
+        assert!(matches!(translation.success_state(), Some(SuccessState::Running)));
+    }
+
+    #[test]
+    fn completions_read_back() {
+        let object = Object::completion(true, 12).with("time", Object::Float(3.5));
+        assert_eq!(object.get_bool("solved"), Some(true));
+        assert_eq!(object.get_int("moves"), Some(12));
+        assert_eq!(object.get_float("time"), Some(3.5));
+        assert_eq!(Object::completion(false, 0).get_bool("solved"), Some(false));
+    }
+
+    #[test]
+    fn missing_or_mistyped_keys_read_as_none() {
+        let object = Object::completion(true, 12);
+        assert_eq!(object.get_float("time"), None);
+        assert_eq!(object.get_bool("moves"), None);
+        assert_eq!(Object::Bool(true).get_bool("solved"), None);
+        assert_eq!(Object::Bool(true).with("solved", Object::Bool(true)), Object::Bool(true));
     }
 }
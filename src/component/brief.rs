@@ -1,23 +1,24 @@
 use crate::{Context2D, Assets, Point, util, KeyboardState};
-use crate::component::{Component, Rect, NextScene};
+use crate::component::{Button, Component, IconSheet, Rect, NextScene};
 
 #[derive(Clone, Debug)]
 pub struct Brief<T> {
-    description: &'static str,
+    description: String,
     is_expanded: bool,
     scene: T,
 }
 impl<T: Component> Brief<T> {
-    pub fn new(description: &'static str, scene: T) -> Self {
+    pub fn new(description: &str, scene: T) -> Self {
         Brief {
-            description,
+            description: description.to_owned(),
             is_expanded: false,
             scene,
         }
     }
 
-    fn get_button_rect(&self) -> Rect {
-        self.bounding_rect().shrink_bottom_right(Rect::TWO_BY_TWO)
+    fn button(&self) -> Button {
+        let rect = self.bounding_rect().shrink_bottom_right(Rect::TWO_BY_TWO);
+        Button::new(rect, Self::PAPER_ICON, IconSheet::Misc)
     }
 
     const BG_PAPER: Rect = Rect::indexed(Point(1, 0), Rect::FOUR_BY_TWO);
@@ -26,33 +27,33 @@ impl<T: Component> Brief<T> {
     const DESCRIPTION_TOP: f64 = 105.0;
     const LEFT_MARGIN: f64 = 70.0;
     const LINE_HEIGHT: f64 = 16.0;
+    const MAX_LINE_WIDTH: usize = 27;
 }
-impl<T: Component> Component for Brief<T> {
-    type DrawArgs = T::DrawArgs;
-    fn draw(&self, context: &Context2D, assets: &Assets, args: Self::DrawArgs) {
+impl<T: Component> super::Wrapper for Brief<T> {
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.scene
+    }
+    fn inner_mut(&mut self) -> &mut T {
+        &mut self.scene
+    }
+    fn draw(&self, context: &Context2D, assets: &Assets, args: T::DrawArgs) {
         if !self.is_expanded {
             self.scene.draw(context, assets, args);
 
-            let bottom_left = self.get_button_rect();
-            assets.misc.draw_with_rect(context, &Self::PAPER_ICON, &bottom_left);
+            self.button().draw(context, assets, ());
             return;
         }
 
-        let bounding_rect = self.bounding_rect();
+        let bounding_rect = self.scene.bounding_rect();
         let centre = f64::from(bounding_rect.centre().x());
 
         util::with_saved_context(context, || {
             assets.misc.draw_with_rect(context, &Self::BG_PAPER, &bounding_rect);
 
-            let black = wasm_bindgen::JsValue::from_str("black");
-
             context.set_font("25px KongText");
             context.set_text_align("center");
-            context.set_fill_style(&black);
-
-            context
-                .fill_text("Brief:", centre, Self::TOP_MARGIN)
-                .unwrap();
+            util::draw_text_outlined(context, "Brief:", centre, Self::TOP_MARGIN, "black", "white");
 
             context.set_font("15px KongText");
             context.set_text_align("left");
@@ -60,10 +61,8 @@ impl<T: Component> Component for Brief<T> {
             let left_margin = f64::from(bounding_rect.top_left.x()) + Self::LEFT_MARGIN;
             let mut baseline = f64::from(bounding_rect.top_left.y()) + Self::DESCRIPTION_TOP;
         
-            for line in self.description.lines() {
-                context
-                    .fill_text(line, left_margin, baseline)
-                    .unwrap();
+            for line in util::word_wrap(&self.description, Self::MAX_LINE_WIDTH) {
+                util::draw_text_outlined(context, line, left_margin, baseline, "black", "white");
         
                 baseline += Self::LINE_HEIGHT;
             }
@@ -75,16 +74,13 @@ impl<T: Component> Component for Brief<T> {
             return true;
         }
 
-        if self.get_button_rect().inside(point) {
+        if self.button().click(point) {
             self.is_expanded = true;
             return true;
         }
 
         self.scene.click(point)
     }
-    fn bounding_rect(&self) -> Rect {
-        self.scene.bounding_rect()
-    }
     fn step(&mut self, dt: f64, keyboard: &KeyboardState) -> NextScene {
         if self.is_expanded {
             if keyboard.is_pressed("Space") || keyboard.is_pressed("Enter") {
@@ -95,9 +91,6 @@ impl<T: Component> Component for Brief<T> {
         
         self.scene.step(dt, keyboard)
     }
-    fn returned_into(&mut self, object: super::Object) {
-        self.scene.returned_into(object)
-    }
     fn called_into(&mut self, object: super::Object) {
         self.is_expanded = true;
         self.scene.called_into(object)
@@ -106,4 +99,4 @@ impl<T: Component> Component for Brief<T> {
         self.is_expanded = true;
         self.scene.jumped_into(object)
     }
-}
\ No newline at end of file
+}
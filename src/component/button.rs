@@ -0,0 +1,57 @@
+use crate::{Assets, Context2D, point::Point, util};
+
+use super::Rect;
+
+/// The sprite sheet a button's icon is drawn from.
+#[derive(Clone, Copy, Debug)]
+pub enum IconSheet {
+    Blocks,
+    Misc,
+}
+
+/// A clickable icon. Buttons are cheap to copy, so owners usually build them
+/// where they are needed rather than storing them.
+#[derive(Clone, Copy, Debug)]
+pub struct Button {
+    rect: Rect,
+    icon: Rect,
+    sheet: IconSheet,
+    pressed: bool,
+}
+impl Button {
+    const PRESSED_COLOUR: &'static str = "rgba(0, 0, 0, 0.3)";
+
+    pub const fn new(rect: Rect, icon: Rect, sheet: IconSheet) -> Self {
+        Button {
+            rect,
+            icon,
+            sheet,
+            pressed: false,
+        }
+    }
+    /// The same button, drawn as pressed or not.
+    pub fn pressed(self, pressed: bool) -> Self {
+        Button { pressed, ..self }
+    }
+}
+impl super::Component for Button {
+    type DrawArgs = ();
+    fn bounding_rect(&self) -> Rect {
+        self.rect
+    }
+    fn draw(&self, context: &Context2D, assets: &Assets, _args: ()) {
+        let sheet = match self.sheet {
+            IconSheet::Blocks => &assets.blocks,
+            IconSheet::Misc => &assets.misc,
+        };
+        sheet.draw_with_rect(context, &self.icon, &self.rect);
+
+        if self.pressed {
+            util::with_saved_context(context, || self.fill_bg(context, Self::PRESSED_COLOUR));
+        }
+    }
+    fn click(&mut self, point: Point<i32>) -> bool {
+        self.pressed = self.in_boundary(point);
+        self.pressed
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{Context2D, Assets, KeyboardState, point::Point, util};
+
+use super::{NextScene, Object, Rect};
+
+/// A yes or no question drawn over the current scene. While open it takes
+/// every click, and its answer is returned from `step` as an
+/// `Object::Bool`, once.
+#[derive(Clone, Debug)]
+pub struct ConfirmDialog {
+    message: &'static str,
+    bounding_rect: Rect,
+    answer: Option<bool>,
+}
+impl ConfirmDialog {
+    const DIMENSIONS: Point<i32> = Point(320, 112);
+    const BUTTON_DIMS: Point<i32> = Point(64, 24);
+    const MARGIN: Point<i32> = Point(16, 16);
+    const LINE_HEIGHT: f64 = 16.0;
+    const BG_COLOUR: &'static str = "rgba(40, 40, 40, 0.9)";
+    const TEXT_COLOUR: &'static str = "white";
+
+    pub fn new(message: &'static str, bounding_rect: Rect) -> Self {
+        ConfirmDialog {
+            message,
+            bounding_rect,
+            answer: None,
+        }
+    }
+    /// A dialog of the standard size in the middle of `within`.
+    pub fn centred_in(message: &'static str, within: Rect) -> Self {
+        let top_left = within.centre() - Point(Self::DIMENSIONS.x() / 2, Self::DIMENSIONS.y() / 2);
+        ConfirmDialog::new(message, Rect::new(top_left, Self::DIMENSIONS))
+    }
+    /// The answer given since this was last called, if any.
+    pub fn take_answer(&mut self) -> Option<bool> {
+        self.answer.take()
+    }
+    fn yes_button(&self) -> Rect {
+        self.bounding_rect
+            .expand(Point(0, 0) - Self::MARGIN)
+            .shrink_bottom_left(Self::BUTTON_DIMS)
+    }
+    fn no_button(&self) -> Rect {
+        self.bounding_rect
+            .expand(Point(0, 0) - Self::MARGIN)
+            .shrink_bottom_right(Self::BUTTON_DIMS)
+    }
+    fn draw_button(context: &Context2D, rect: &Rect, text: &str) {
+        context.set_stroke_style_str(Self::TEXT_COLOUR);
+        context.stroke_rect(
+            rect.top_left.x().into(),
+            rect.top_left.y().into(),
+            rect.dimensions.x().into(),
+            rect.dimensions.y().into(),
+        );
+
+        let centre = rect.centre();
+        context
+            .fill_text(text, centre.x().into(), f64::from(centre.y()) + 5.0)
+            .unwrap();
+    }
+}
+impl super::Component for ConfirmDialog {
+    type DrawArgs = ();
+    fn bounding_rect(&self) -> Rect {
+        self.bounding_rect
+    }
+    fn step(&mut self, _dt: f64, keyboard: &KeyboardState) -> NextScene {
+        if keyboard.is_pressed("KeyY") || keyboard.is_pressed("Enter") {
+            self.answer = Some(true);
+        } else if keyboard.is_pressed("KeyN") || keyboard.is_pressed("Escape") {
+            self.answer = Some(false);
+        }
+
+        match self.take_answer() {
+            Some(answer) => NextScene::Return(Object::Bool(answer)),
+            None => NextScene::Continue,
+        }
+    }
+    fn draw(&self, context: &Context2D, _assets: &Assets, _args: ()) {
+        util::with_saved_context(context, || {
+            self.fill_bg(context, Self::BG_COLOUR);
+
+            context.set_font("12px KongText");
+            context.set_text_align("center");
+            context.set_fill_style_str(Self::TEXT_COLOUR);
+
+            let centre = f64::from(self.bounding_rect.centre().x());
+            let mut baseline = f64::from(self.bounding_rect.top_left.y() + Self::MARGIN.y())
+                + Self::LINE_HEIGHT;
+            for line in self.message.lines() {
+                context.fill_text(line, centre, baseline).unwrap();
+                baseline += Self::LINE_HEIGHT;
+            }
+
+            Self::draw_button(context, &self.yes_button(), "Yes");
+            Self::draw_button(context, &self.no_button(), "No");
+        })
+    }
+    // modal: clicks that miss the buttons are swallowed.
+    fn click(&mut self, point: Point<i32>) -> bool {
+        if self.yes_button().inside(point) {
+            self.answer = Some(true);
+        } else if self.no_button().inside(point) {
+            self.answer = Some(false);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::Component;
+
+    fn dialog() -> ConfirmDialog {
+        ConfirmDialog::new("Sure?", Rect::new(Point(100, 100), Point(320, 112)))
+    }
+
+    #[test]
+    fn yes_and_no_give_their_answer_once() {
+        let mut dialog = dialog();
+        assert!(dialog.click(dialog.yes_button().centre()));
+        assert_eq!(dialog.take_answer(), Some(true));
+        assert_eq!(dialog.take_answer(), None);
+
+        assert!(dialog.click(dialog.no_button().centre()));
+        assert_eq!(dialog.take_answer(), Some(false));
+    }
+
+    #[test]
+    fn clicks_off_the_buttons_are_swallowed() {
+        let mut dialog = dialog();
+        assert!(dialog.click(Point(0, 0)));
+        assert!(dialog.click(dialog.bounding_rect().centre()));
+        assert_eq!(dialog.take_answer(), None);
+    }
+
+    #[test]
+    fn centred_dialogs_sit_in_the_middle() {
+        let within = Rect::new(Point(0, 0), Point(512, 256));
+        let dialog = ConfirmDialog::centred_in("Sure?", within);
+        assert_eq!(dialog.bounding_rect().centre(), within.centre());
+    }
+}
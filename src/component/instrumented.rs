@@ -0,0 +1,145 @@
+use serde::Serialize;
+
+use crate::{KeyboardState, point};
+
+use super::NextScene;
+
+/// Keys that count towards `key_presses`, along with the inner scene's undo
+/// keys.
+const TRACKED_KEYS: &[&str] = &[
+    "ArrowUp", "ArrowRight", "ArrowDown", "ArrowLeft",
+    "KeyW", "KeyD", "KeyS", "KeyA",
+    "Space", "Enter",
+    "KeyR", "Escape",
+];
+/// Keys that restart a level.
+const RESTART_KEYS: &[&str] = &["KeyR", "Escape"];
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct InteractionStats {
+    pub clicks: u32,
+    pub key_presses: u32,
+    pub restarts: u32,
+    pub time_spent: f64,
+}
+
+/// Records how the player interacts with the inner scene. Every event is
+/// forwarded unchanged.
+pub struct Instrumented<T> {
+    scene: T,
+    stats: InteractionStats,
+}
+impl<T> Instrumented<T> {
+    pub fn new(scene: T) -> Self {
+        Instrumented {
+            scene,
+            stats: InteractionStats::default(),
+        }
+    }
+    // counts the tracked keys that `is_pressed` reports this frame.
+    fn record_keys<F: Fn(&str) -> bool>(&mut self, undo_keys: &[&str], is_pressed: F) {
+        for code in TRACKED_KEYS.iter().chain(undo_keys) {
+            if is_pressed(code) {
+                self.stats.key_presses += 1;
+            }
+        }
+        if RESTART_KEYS.iter().any(|code| is_pressed(code)) {
+            self.stats.restarts += 1;
+        }
+    }
+}
+
+impl<T> super::Wrapper for Instrumented<T>
+where
+    T: super::Component,
+{
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.scene
+    }
+    fn inner_mut(&mut self) -> &mut T {
+        &mut self.scene
+    }
+    fn step(&mut self, dt: f64, keyboard: &KeyboardState) -> NextScene {
+        self.stats.time_spent += dt;
+        let undo_keys = self.scene.undo_keys();
+        self.record_keys(undo_keys, |code| keyboard.is_pressed(code));
+
+        self.scene.step(dt, keyboard)
+    }
+    fn click(&mut self, point: point::Point<i32>) -> bool {
+        self.stats.clicks += 1;
+        self.scene.click(point)
+    }
+    fn interaction_stats(&self) -> Option<InteractionStats> {
+        Some(self.stats.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::{undo_key_codes, Component, Rect};
+    use crate::{Assets, Context2D};
+
+    // counts the clicks and steps that reach it.
+    #[derive(Default)]
+    struct Inner {
+        clicks: u32,
+        time_stepped: f64,
+        is_editing: bool,
+    }
+    impl Component for Inner {
+        type DrawArgs = ();
+        fn bounding_rect(&self) -> Rect {
+            Rect::new(point::Point(0, 0), point::Point(10, 10))
+        }
+        fn draw(&self, _context: &Context2D, _assets: &Assets, _args: ()) {}
+        fn click(&mut self, _point: point::Point<i32>) -> bool {
+            self.clicks += 1;
+            true
+        }
+        fn step(&mut self, dt: f64, _keyboard: &KeyboardState) -> NextScene {
+            self.time_stepped += dt;
+            NextScene::Continue
+        }
+        fn undo_keys(&self) -> &'static [&'static str] {
+            undo_key_codes(self.is_editing)
+        }
+    }
+
+    #[test]
+    fn clicks_are_counted_and_forwarded() {
+        let mut instrumented = Instrumented::new(Inner::default());
+        assert!(instrumented.click(point::Point(1, 1)));
+        assert!(instrumented.click(point::Point(2, 2)));
+        assert_eq!(instrumented.scene.clicks, 2);
+        assert_eq!(instrumented.interaction_stats().unwrap().clicks, 2);
+    }
+
+    #[test]
+    fn steps_count_time_keys_and_restarts() {
+        let mut instrumented = Instrumented::new(Inner::default());
+        instrumented.step(16.0, &KeyboardState::pressing(&["ArrowUp", "KeyR"]));
+        instrumented.step(16.0, &KeyboardState::pressing(&["KeyQ"]));
+        instrumented.step(8.0, &KeyboardState::pressing(&["Escape"]));
+
+        let stats = instrumented.interaction_stats().unwrap();
+        assert_eq!(stats.key_presses, 3);
+        assert_eq!(stats.restarts, 2);
+        assert_eq!(stats.time_spent, 40.0);
+        assert_eq!(instrumented.scene.time_stepped, 40.0);
+    }
+
+    #[test]
+    fn z_only_counts_while_it_undoes() {
+        let mut instrumented = Instrumented::new(Inner::default());
+        instrumented.step(16.0, &KeyboardState::pressing(&["KeyZ"]));
+        assert_eq!(instrumented.interaction_stats().unwrap().key_presses, 1);
+
+        instrumented.scene.is_editing = true;
+        instrumented.step(16.0, &KeyboardState::pressing(&["KeyZ"]));
+        instrumented.step(16.0, &KeyboardState::pressing(&["KeyU"]));
+        assert_eq!(instrumented.interaction_stats().unwrap().key_presses, 2);
+    }
+}
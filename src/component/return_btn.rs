@@ -1,6 +1,6 @@
 use crate::{Context2D, Assets, KeyboardState, point};
 
-use super::{NextScene, Rect};
+use super::{Button, Component, IconSheet, NextScene, Rect};
 
 pub struct ReturnButton<T> {
     scene: T,
@@ -16,20 +16,24 @@ where
             is_returning: false,
         }
     }
-    fn get_button_bounds(&self) -> Rect {
+    fn button(&self) -> Button {
         let bounds = self.scene.bounding_rect();
-        bounds.shrink_bottom_left(Rect::TWO_BY_TWO)
+        let rect = bounds.shrink_bottom_left(Rect::TWO_BY_TWO);
+        Button::new(rect, Self::BACK_BUTTON, IconSheet::Misc).pressed(self.is_returning)
     }
     const BACK_BUTTON: Rect = Rect::indexed(point::Point(0, 5), Rect::TWO_BY_TWO);
 }
 
-impl<T> super::Component for ReturnButton<T>
+impl<T> super::Wrapper for ReturnButton<T>
 where 
     T: super::Component,
 {
-    type DrawArgs = T::DrawArgs;
-    fn bounding_rect(&self) -> super::Rect {
-        self.scene.bounding_rect()
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.scene
+    }
+    fn inner_mut(&mut self) -> &mut T {
+        &mut self.scene
     }
     fn step(&mut self, dt: f64, keyboard: &KeyboardState) -> NextScene {
         if self.is_returning {
@@ -37,14 +41,13 @@ where
         }
         self.scene.step(dt, keyboard)
     }
-    fn draw(&self, context: &Context2D, assets: &Assets, args: Self::DrawArgs) {
+    fn draw(&self, context: &Context2D, assets: &Assets, args: T::DrawArgs) {
         self.scene.draw(context, assets, args);
 
-        let destination = self.get_button_bounds();
-        assets.misc.draw_with_rect(context, &Self::BACK_BUTTON, &destination);
+        self.button().draw(context, assets, ());
     }
     fn click(&mut self, point: point::Point<i32>) -> bool {
-        if self.get_button_bounds().inside(point) {
+        if self.button().click(point) {
             self.is_returning = true;
             return true;
         }
@@ -63,4 +66,4 @@ where
         self.is_returning = false;
         self.scene.jumped_into(object)
     }
-}
\ No newline at end of file
+}
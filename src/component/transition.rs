@@ -1,10 +1,12 @@
-use crate::{Context2D, Assets, KeyboardState, util, point};
+use crate::{Context2D, Assets, KeyboardState, util};
 
 use super::NextScene;
 
 pub struct Transition<T> {
     scene: T,
     state: TransitionState,
+    // skips the wipes in and out.
+    reduces_motion: bool,
 }
 pub enum TransitionState {
     In(f64),
@@ -16,9 +18,13 @@ impl<T> Transition<T> {
         Transition {
             scene,
             state: TransitionState::In(0.0),
+            reduces_motion: false,
         }
     }
     const TOTAL_TIME: f64 = 400.0;
+    // the most a wipe moves on in one frame, so a long frame, such as after
+    // the tab was in the background, can't skip it.
+    const MAX_FRAME_TIME: f64 = Self::TOTAL_TIME / 8.0;
     const SCREEN_DIMS: super::Rect = crate::level::cow_level::CowLevel::BOUNDING_RECT;
     fn draw_box_in(context: &Context2D, mut animation_time: f64) {
         animation_time = Self::TOTAL_TIME - animation_time;
@@ -47,42 +53,63 @@ impl<T> Transition<T> {
         self.state = TransitionState::In(0.0);
     }
 }
-
-impl<T> super::Component for Transition<T>
-where 
+impl<T> Transition<T>
+where
     T: super::Component,
 {
-    type DrawArgs = T::DrawArgs;
-    fn bounding_rect(&self) -> super::Rect {
-        self.scene.bounding_rect()
-    }
-    fn step(&mut self, dt: f64, keyboard: &KeyboardState) -> super::NextScene {
-        match &mut self.state {
+    // moves on from the wipes, using `step_scene` to step the scene while
+    // it is running.
+    fn step_with<F>(&mut self, dt: f64, step_scene: F) -> NextScene
+    where
+        F: FnOnce(&mut T, f64) -> NextScene,
+    {
+        let reduces_motion = self.reduces_motion;
+        let dt = match &self.state {
             TransitionState::In(animation_time) => {
-                *animation_time += dt;
-                if *animation_time > Self::TOTAL_TIME {
-                    self.state = TransitionState::Running;
+                if !reduces_motion && *animation_time <= Self::TOTAL_TIME {
+                    return NextScene::Continue;
                 };
-                NextScene::Continue
-            },
-            TransitionState::Running => {
-                let result = self.scene.step(dt, keyboard);
-                if NextScene::Continue != result {
-                    self.state = TransitionState::Out(result, 0.0);
-                }
-                NextScene::Continue
+                // the scene runs on the frame the wipe finishes, so it always
+                // gets a step before it can be left.
+                self.state = TransitionState::Running;
+                dt.min(Self::MAX_FRAME_TIME)
             },
+            TransitionState::Running => dt,
             TransitionState::Out(result, animation_time) => {
-                *animation_time += dt;
-                if *animation_time > Self::TOTAL_TIME {
+                if reduces_motion || *animation_time > Self::TOTAL_TIME {
                     return result.clone();
                 };
-                NextScene::Continue
+                return NextScene::Continue;
             }
+        };
+
+        let result = step_scene(&mut self.scene, dt);
+        if NextScene::Continue != result {
+            self.state = TransitionState::Out(result, 0.0);
         }
+        NextScene::Continue
+    }
+}
+
+impl<T> super::Wrapper for Transition<T>
+where 
+    T: super::Component,
+{
+    type Inner = T;
+    fn inner(&self) -> &T {
+        &self.scene
+    }
+    fn inner_mut(&mut self) -> &mut T {
+        &mut self.scene
+    }
+    fn step(&mut self, dt: f64, keyboard: &KeyboardState) -> super::NextScene {
+        self.step_with(dt, |scene, dt| scene.step(dt, keyboard))
     }
-    fn draw(&self, context: &Context2D, assets: &Assets, args: Self::DrawArgs) {
+    fn draw(&self, context: &Context2D, assets: &Assets, args: T::DrawArgs) {
         self.scene.draw(context, assets, args);
+        if self.reduces_motion {
+            return;
+        }
         match self.state {
             TransitionState::In(animation_time) => {
                 Self::draw_box_in(context, animation_time);
@@ -93,9 +120,6 @@ where
             },
         }
     }
-    fn click(&mut self, point: point::Point<i32>) -> bool {
-        self.scene.click(point)
-    }
     fn returned_into(&mut self, object: super::Object) {
         self.reset();
         self.scene.returned_into(object)
@@ -108,4 +132,74 @@ where
         self.reset();
         self.scene.jumped_into(object)
     }
-}
\ No newline at end of file
+    fn animate(&mut self, dt: f64) {
+        match &mut self.state {
+            TransitionState::In(animation_time) | TransitionState::Out(_, animation_time) => {
+                *animation_time += dt.min(Self::MAX_FRAME_TIME);
+            }
+            TransitionState::Running => {}
+        }
+        self.scene.animate(dt)
+    }
+    fn set_reduced_motion(&mut self, reduces_motion: bool) {
+        self.reduces_motion = reduces_motion;
+        self.scene.set_reduced_motion(reduces_motion)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::{Component, Object, Rect};
+
+    struct Blank;
+    impl Component for Blank {
+        type DrawArgs = ();
+        fn bounding_rect(&self) -> Rect {
+            Transition::<Blank>::SCREEN_DIMS
+        }
+        fn draw(&self, _context: &Context2D, _assets: &Assets, _args: ()) {}
+    }
+
+    fn leave(_scene: &mut Blank, _dt: f64) -> NextScene {
+        NextScene::Return(Object::Bool(true))
+    }
+
+    #[test]
+    fn reduced_motion_leaves_on_the_first_step_out() {
+        let mut transition = Transition::new(Blank);
+        transition.set_reduced_motion(true);
+        assert_eq!(transition.step_with(16.0, leave), NextScene::Continue);
+        assert_eq!(transition.step_with(16.0, leave), NextScene::Return(Object::Bool(true)));
+    }
+
+    #[test]
+    fn wipes_wait_without_reduced_motion() {
+        let mut transition = Transition::new(Blank);
+        assert_eq!(transition.step_with(16.0, leave), NextScene::Continue);
+        assert!(matches!(transition.state, TransitionState::In(_)));
+    }
+
+    #[test]
+    fn long_frames_still_step_the_scene() {
+        let mut transition = Transition::new(Blank);
+        let mut stepped_for = None;
+        let long_frame = Transition::<Blank>::TOTAL_TIME * 3.0;
+        for _ in 0..10 {
+            transition.animate(long_frame);
+        }
+        transition.step_with(long_frame, |_, dt| {
+            stepped_for = Some(dt);
+            NextScene::Continue
+        });
+        assert!(matches!(transition.state, TransitionState::Running));
+        assert_eq!(stepped_for, Some(Transition::<Blank>::MAX_FRAME_TIME));
+    }
+
+    #[test]
+    fn long_frames_do_not_skip_the_wipe() {
+        let mut transition = Transition::new(Blank);
+        transition.animate(Transition::<Blank>::TOTAL_TIME * 3.0);
+        assert_eq!(transition.step_with(16.0, leave), NextScene::Continue);
+        assert!(matches!(transition.state, TransitionState::In(_)));
+    }
+}
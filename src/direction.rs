@@ -7,8 +7,27 @@ pub enum Direction {
     Down = 2,
     Left = 3,
 }
+// Directions are sprite offsets and bit positions in `Surroundings`, so these
+// values must never change. Each line fails to compile if its discriminant
+// does.
+const _: [(); 0] = [(); Direction::Up as usize];
+const _: [(); 1] = [(); Direction::Right as usize];
+const _: [(); 2] = [(); Direction::Down as usize];
+const _: [(); 3] = [(); Direction::Left as usize];
+
 impl Direction {
     pub const TOTAL_DIRECTIONS: u8 = 4;
+    /// The direction with the given discriminant, or `None` if there isn't
+    /// one.
+    pub fn from_u8(n: u8) -> Option<Self> {
+        match n {
+            0 => Some(Direction::Up),
+            1 => Some(Direction::Right),
+            2 => Some(Direction::Down),
+            3 => Some(Direction::Left),
+            _ => None,
+        }
+    }
     pub fn increment(self) -> Self {
         match self {
             Direction::Up => Direction::Right,
@@ -33,14 +52,23 @@ impl Direction {
             Direction::Left => Direction::Right,
         }
     }
-    pub fn for_every<F>(mut func: F)
+    /// Reflects the direction in a vertical line.
+    pub fn mirror_horizontal(self) -> Self {
+        match self {
+            Direction::Left | Direction::Right => self.opposite(),
+            Direction::Up | Direction::Down => self,
+        }
+    }
+    /// Every direction, clockwise from up.
+    pub fn all() -> [Direction; 4] {
+        [Direction::Up, Direction::Right, Direction::Down, Direction::Left]
+    }
+    /// Calls `func` once with each direction, clockwise from up.
+    pub fn for_every<F>(func: F)
     where
         F: FnMut(Direction),
     {
-        func(Direction::Up);
-        func(Direction::Left);
-        func(Direction::Right);
-        func(Direction::Down);
+        Self::all().iter().copied().for_each(func);
     }
 }
 impl Default for Direction {
@@ -53,3 +81,18 @@ impl From<Direction> for u8 {
         direction as u8
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directions_round_trip_through_u8() {
+        for n in 0..Direction::TOTAL_DIRECTIONS {
+            let direction = Direction::from_u8(n).unwrap();
+            assert_eq!(u8::from(direction), n);
+        }
+        assert_eq!(Direction::from_u8(Direction::TOTAL_DIRECTIONS), None);
+        assert_eq!(Direction::from_u8(u8::MAX), None);
+    }
+}
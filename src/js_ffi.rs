@@ -15,22 +15,14 @@ extern "C" {
     #[wasm_bindgen(method)]
     pub fn play_sound(this: &BasicAudioPlayer, id: &str);
 
-    pub type KeyboardState;
-    #[wasm_bindgen(constructor)]
-    pub fn new() -> KeyboardState;
-    #[wasm_bindgen(method)]
-    pub fn is_pressed(this: &KeyboardState, code: &str) -> bool;
-    #[wasm_bindgen(method)]
-    pub fn is_held(this: &KeyboardState, code: &str) -> bool;
-    #[wasm_bindgen(method)]
-    pub fn tick(this: &KeyboardState);
-
     #[wasm_bindgen]
     pub fn draw_layer(
         context: &Context2D,
         image: &Image,
         sprite_width: i32,
         sprite_height: i32,
+        dest_width: i32,
+        dest_height: i32,
         data: *const u8,
         width: i32,
         height: i32,
@@ -38,3 +30,43 @@ extern "C" {
     #[wasm_bindgen]
     pub fn draw_rope(context: &Context2D, start_x: f64, start_y: f64, end_x: f64, end_y: f64);
 }
+
+#[cfg(not(test))]
+#[wasm_bindgen]
+extern "C" {
+    pub type KeyboardState;
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> KeyboardState;
+    #[wasm_bindgen(method)]
+    pub fn is_pressed(this: &KeyboardState, code: &str) -> bool;
+    #[wasm_bindgen(method)]
+    pub fn is_held(this: &KeyboardState, code: &str) -> bool;
+    #[wasm_bindgen(method)]
+    pub fn tick(this: &KeyboardState);
+}
+
+/// Stands in for the browser's keyboard in native tests. The given keys
+/// are pressed this frame, and so also held.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct KeyboardState {
+    pressed: Vec<&'static str>,
+}
+#[cfg(test)]
+impl KeyboardState {
+    pub fn new() -> Self {
+        KeyboardState::default()
+    }
+    pub fn pressing(codes: &[&'static str]) -> Self {
+        KeyboardState {
+            pressed: codes.to_vec(),
+        }
+    }
+    pub fn is_pressed(&self, code: &str) -> bool {
+        self.pressed.contains(&code)
+    }
+    pub fn is_held(&self, code: &str) -> bool {
+        self.is_pressed(code)
+    }
+    pub fn tick(&self) {}
+}
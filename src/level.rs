@@ -1,3 +1,6 @@
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
 use serde::{Deserialize, Serialize};
 
 use crate::console_log;
@@ -9,22 +12,26 @@ use crate::{Assets, Context2D, Point};
 mod board;
 pub mod cell;
 mod cow;
+mod level_code;
 pub mod cow_level;
 pub mod god_level;
 pub mod overworld_level;
 
 use board::Board;
-use cell::{CellType, GroundCell, OverlayCell, PaletteResult};
-use cow::{Command, CowSprite, Cows};
+use cell::{CellType, GateCondition, GroundCell, OverlayCell, PaletteResult, Signals};
+use cow::{Command, CowSprite, Cows, OwnershipError};
 use cow_level::CowLevel;
 
 // green.
 const BG_FILL: &str = "#669238";
+// faint white.
+const GRID_COLOUR: &str = "rgba(255, 255, 255, 0.3)";
+const BOUNCE_COLOUR: &str = "white";
 
 #[derive(Clone, Debug)]
 pub struct NotEnoughInputSpace;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum KeyboardCommand {
     Direction(Direction),
     Space,
@@ -36,24 +43,35 @@ impl KeyboardCommand {
             _ => false,
         }
     }
+    /// A single letter for the command, for writing down move lists.
+    fn to_char(self) -> char {
+        match self {
+            Self::Direction(Direction::Up) => 'U',
+            Self::Direction(Direction::Right) => 'R',
+            Self::Direction(Direction::Down) => 'D',
+            Self::Direction(Direction::Left) => 'L',
+            Self::Space => 'W',
+        }
+    }
+    fn from_char(character: char) -> Option<Self> {
+        match character {
+            'U' => Some(Self::Direction(Direction::Up)),
+            'R' => Some(Self::Direction(Direction::Right)),
+            'D' => Some(Self::Direction(Direction::Down)),
+            'L' => Some(Self::Direction(Direction::Left)),
+            'W' => Some(Self::Space),
+            _ => None,
+        }
+    }
 }
 
 trait Level {
     fn is_finished_animating(&self) -> bool;
     fn get_keyboard_command(&self, keyboard_state: &KeyboardState) -> Option<KeyboardCommand> {
-        if self.keyboard_event(keyboard_state, &["ArrowUp", "KeyW"]) {
-            Some(KeyboardCommand::Direction(Direction::Up))
-        } else if self.keyboard_event(keyboard_state, &["ArrowRight", "KeyD"]) {
-            Some(KeyboardCommand::Direction(Direction::Right))
-        } else if self.keyboard_event(keyboard_state, &["ArrowDown", "KeyS"]) {
-            Some(KeyboardCommand::Direction(Direction::Down))
-        } else if self.keyboard_event(keyboard_state, &["ArrowLeft", "KeyA"]) {
-            Some(KeyboardCommand::Direction(Direction::Left))
-        } else if self.keyboard_event(keyboard_state, &["Space", "Enter"]) {
-            Some(KeyboardCommand::Space)
-        } else {
-            None
-        }
+        COMMAND_KEYS
+            .iter()
+            .find(|(_, codes)| self.keyboard_event(keyboard_state, codes))
+            .map(|(command, _)| *command)
     }
     fn keyboard_event(&self, keyboard_state: &KeyboardState, codes: &[&str]) -> bool {
         for code in codes.iter() {
@@ -68,8 +86,108 @@ trait Level {
     }
 }
 
+const COMMAND_KEYS: [(KeyboardCommand, &[&str]); 5] = [
+    (KeyboardCommand::Direction(Direction::Up), &["ArrowUp", "KeyW"]),
+    (KeyboardCommand::Direction(Direction::Right), &["ArrowRight", "KeyD"]),
+    (KeyboardCommand::Direction(Direction::Down), &["ArrowDown", "KeyS"]),
+    (KeyboardCommand::Direction(Direction::Left), &["ArrowLeft", "KeyA"]),
+    (KeyboardCommand::Space, &["Space", "Enter"]),
+];
+
+/// A command from `KeyRepeat`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum KeyInput {
+    /// The key was just pressed.
+    Pressed(KeyboardCommand),
+    /// The key has been held long enough to repeat.
+    Held(KeyboardCommand),
+}
+
+/// Turns held command keys into repeated commands. The first repeat waits
+/// `delay` milliseconds, and each one after comes sooner, down to
+/// `min_delay`.
+#[derive(Debug, Clone)]
+struct KeyRepeat {
+    delay: f64,
+    min_delay: f64,
+    // the held command, its keys, and the time left until it repeats.
+    held: Option<(KeyboardCommand, &'static [&'static str], f64)>,
+    current_delay: f64,
+}
+impl KeyRepeat {
+    const DEFAULT_DELAY: f64 = 250.0;
+    const DEFAULT_MIN_DELAY: f64 = 60.0;
+    const ACCELERATION: f64 = 0.75;
+
+    fn new(delay: f64, min_delay: f64) -> Self {
+        KeyRepeat {
+            delay,
+            min_delay,
+            held: None,
+            current_delay: delay,
+        }
+    }
+    fn step(&mut self, dt: f64, keyboard_state: &KeyboardState) -> Option<KeyInput> {
+        let pressed = COMMAND_KEYS
+            .iter()
+            .find(|(_, codes)| codes.iter().any(|code| keyboard_state.is_pressed(code)));
+        if let Some((command, codes)) = pressed {
+            self.held = Some((*command, *codes, self.delay));
+            self.current_delay = self.delay;
+            return Some(KeyInput::Pressed(*command));
+        }
+
+        let (command, codes, countdown) = self.held.as_mut()?;
+        if !codes.iter().any(|code| keyboard_state.is_held(code)) {
+            self.held = None;
+            return None;
+        }
+
+        *countdown -= dt;
+        if *countdown > 0.0 {
+            return None;
+        }
+        self.current_delay = (self.current_delay * Self::ACCELERATION).max(self.min_delay);
+        *countdown += self.current_delay;
+        Some(KeyInput::Held(*command))
+    }
+}
+impl Default for KeyRepeat {
+    fn default() -> Self {
+        KeyRepeat::new(Self::DEFAULT_DELAY, Self::DEFAULT_MIN_DELAY)
+    }
+}
+
 pub trait Pasture<C> {
     fn get_pasture_cell(&self, point: Point<i32>) -> &C;
+    /// Pushes any pushable cells at `point` one step in `direction`, and
+    /// returns whether they moved. Cells are never pushed where `limits`
+    /// forbids.
+    fn push_cells(
+        &mut self,
+        _point: Point<i32>,
+        _direction: Direction,
+        _limits: &PushLimits,
+    ) -> bool {
+        false
+    }
+}
+
+/// Where pushed cells may go, besides needing empty ground: inside the board
+/// and out from under every cow.
+pub struct PushLimits<'a> {
+    pub dimensions: Point<i32>,
+    pub cows: &'a [Point<i32>],
+}
+impl PushLimits<'_> {
+    /// Allows no pushing at all, for pastures without pushable cells.
+    pub const NONE: PushLimits<'static> = PushLimits {
+        dimensions: Point(0, 0),
+        cows: &[],
+    };
+    fn allows(&self, point: Point<i32>) -> bool {
+        point.is_inside(self.dimensions) && !self.cows.contains(&point)
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -78,6 +196,12 @@ pub enum SuccessState {
     Running = 1,
     Succeeded = 2,
 }
+// Reported to the host page as numbers, so these values must never change.
+// Each line fails to compile if its discriminant does.
+const _: [(); 0] = [(); SuccessState::Failed as usize];
+const _: [(); 1] = [(); SuccessState::Running as usize];
+const _: [(); 2] = [(); SuccessState::Succeeded as usize];
+
 impl SuccessState {
     fn is_running(self) -> bool {
         match self {
@@ -105,6 +229,20 @@ struct LevelState {
     board: Board,
     cows: Cows,
     animation_frame: u8,
+    /// The size of the board in cells.
+    #[serde(default = "LevelState::default_dimensions")]
+    dimensions: Point<i32>,
+    /// The cell types the player may place. Every type is allowed if unset.
+    #[serde(default)]
+    allowed_cells: Option<Vec<CellType>>,
+    /// The author's solution, used to give hints.
+    #[serde(default)]
+    solution: Option<Vec<KeyboardCommand>>,
+    #[serde(skip)]
+    signals: Signals,
+    /// The player's command that led to this state, if any.
+    #[serde(skip)]
+    last_command: Option<KeyboardCommand>,
 }
 impl LevelState {
     fn new() -> Self {
@@ -118,6 +256,11 @@ impl LevelState {
                 ],
             ),
             animation_frame: LevelState::INITIAL_ANIMATION_FRAME,
+            dimensions: LevelState::default_dimensions(),
+            allowed_cells: None,
+            solution: None,
+            signals: Signals::default(),
+            last_command: None,
         }
     }
 
@@ -128,10 +271,128 @@ impl LevelState {
     fn success_state(&self) -> SuccessState {
         self.cows.success_state(&self.board)
     }
-    fn set_cell_at_point(&mut self, point: Point<i32>, cell_type: PaletteResult<CellType>) {
-        self.board.set_cell_at_point(point, cell_type);
+    /// A hash of everything that decides how the state plays out. The
+    /// animation frame is left out, as it changes every step.
+    fn fingerprint(&self) -> u64 {
+        let mut state = self.clone();
+        state.animation_frame = LevelState::INITIAL_ANIMATION_FRAME;
+
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        ron::ser::to_string(&state).unwrap().hash(&mut hasher);
+        hasher.finish()
+    }
+    /// Whether this state was already reached while stepping automatically,
+    /// so it will loop forever. `seen` holds the states stepped through so
+    /// far, and this state is added to it.
+    fn is_deadlocked(&self, seen: &mut HashSet<u64>) -> bool {
+        self.success_state().is_running() && !seen.insert(self.fingerprint())
+    }
+    #[cfg(debug_assertions)]
+    fn check_invariants(&self) {
+        self.board.check_invariants();
+        self.cows.check_invariants();
+    }
+    fn validate_cows(&self) -> Result<(), OwnershipError> {
+        self.cows.validate()
+    }
+    fn is_inside(&self, dimensions: Point<i32>) -> bool {
+        self.board.is_inside(dimensions) && self.cows.is_inside(dimensions)
+    }
+    fn player_position(&self) -> Point<i32> {
+        self.cows.player_position()
+    }
+    fn default_dimensions() -> Point<i32> {
+        Point(CowLevel::LEVEL_WIDTH, CowLevel::LEVEL_HEIGHT)
+    }
+    /// Checks that a board of `dimensions` cells is not empty and fits on
+    /// the screen.
+    fn check_dimensions(dimensions: Point<i32>) -> Result<(), String> {
+        let max = LevelState::default_dimensions();
+        if dimensions.x() <= 0 || dimensions.y() <= 0 {
+            return Err(format!(
+                "Level has an empty {}x{} board",
+                dimensions.x(),
+                dimensions.y()
+            ));
+        }
+        if dimensions.x() > max.x() || dimensions.y() > max.y() {
+            return Err(format!(
+                "Level has a {}x{} board, but at most {}x{} fits on the screen",
+                dimensions.x(),
+                dimensions.y(),
+                max.x(),
+                max.y()
+            ));
+        }
+        Ok(())
+    }
+    fn bounding_rect(&self) -> crate::component::Rect {
+        crate::component::Rect {
+            top_left: Point(0, 0),
+            dimensions: self.dimensions
+                * Point(crate::SpriteSheet::STANDARD_WIDTH, crate::SpriteSheet::STANDARD_HEIGHT),
+        }
+    }
+    fn is_allowed(&self, cell_type: CellType) -> bool {
+        self.allowed_cells
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(&cell_type))
+    }
+    fn palette(&self) -> Vec<cell::CellCursorEntry<CellType>> {
+        match &self.allowed_cells {
+            Some(allowed) => CellType::restricted_palette(allowed),
+            None => CellType::full_palette(),
+        }
+    }
+    fn set_cell_at_point(
+        &mut self,
+        point: Point<i32>,
+        cell_type: PaletteResult<CellType>,
+        blanks_only: bool,
+    ) {
+        self.board.set_cell_at_point(point, cell_type, blanks_only);
+    }
+    /// The ground cell at a grid coordinate.
+    pub fn ground_cell_at(&self, index: Point<i32>) -> GroundCell {
+        *self.board.get_ground_cell(&index)
+    }
+    /// The overlay cell at a grid coordinate.
+    pub fn overlay_cell_at(&self, index: Point<i32>) -> OverlayCell {
+        *self.board.get_overlay_cell(&index)
+    }
+    fn mirror_horizontal(&mut self) {
+        self.board.mirror_horizontal(self.dimensions.x());
+        self.cows.mirror_horizontal(self.dimensions.x());
+    }
+    fn rotate_180(&mut self) {
+        self.board.rotate_180(self.dimensions);
+        self.cows.rotate_180(self.dimensions);
+    }
+    fn add_cow_at(&mut self, index: Point<i32>) -> bool {
+        self.cows.add_cow_at(index)
+    }
+    fn remove_cow_at(&mut self, index: Point<i32>) -> bool {
+        self.cows.remove_cow_at(index)
+    }
+    fn ownership_chain_at(&self, index: Point<i32>) -> Vec<usize> {
+        self.cows.ownership_chain_at(index)
+    }
+    fn fill_at_point(&mut self, point: Point<i32>, cell_type: PaletteResult<CellType>) {
+        self.board.fill_at_point(point, cell_type, self.dimensions);
     }
 
+    fn input_space(&self) -> usize {
+        self.board.input_space()
+    }
+    fn output_space(&self) -> usize {
+        self.board.output_space()
+    }
+    fn has_extra_outputs(&self) -> bool {
+        self.board.has_extra_outputs()
+    }
+    fn has_colour_cycle(&self) -> bool {
+        self.board.has_colour_cycle()
+    }
     fn set_inputs(&mut self, inputs: &[cell::Colour]) -> Result<(), NotEnoughInputSpace> {
         self.board.set_inputs(inputs)
     }
@@ -145,7 +406,35 @@ impl LevelState {
 
     fn command(&mut self, command: Command) {
         self.animation_frame = (self.animation_frame + 1) % LevelState::TOTAL_ANIMATION_FRAMES;
-        self.cows.command_player(&mut self.board, command);
+        self.update_gates();
+        self.cows.command_player(&mut self.board, command, self.dimensions);
+        self.update_gates();
+    }
+
+    fn set_signals(&mut self, signals: Signals) {
+        if self.signals != signals {
+            self.signals = signals;
+            self.update_gates();
+        }
+    }
+    fn update_gates(&mut self) {
+        for (point, condition) in self.board.get_gates() {
+            let is_open = self.is_gate_open(condition);
+            self.board.set_gate_open(point, is_open);
+        }
+    }
+    fn is_gate_open(&self, condition: GateCondition) -> bool {
+        match condition {
+            GateCondition::Even(colour) => {
+                self.cows.count_owned_on(&self.board, colour).is_multiple_of(2)
+            }
+            GateCondition::Odd(colour) => {
+                !self.cows.count_owned_on(&self.board, colour).is_multiple_of(2)
+            }
+            GateCondition::AtLeast(threshold) => self.board.input_sum() >= u32::from(threshold),
+            GateCondition::Signal(channel) => self.signals.is_on(channel),
+            GateCondition::PlayerCarries(colour) => self.cows.player_carries(&self.board, colour),
+        }
     }
 
     fn draw(
@@ -155,28 +444,157 @@ impl LevelState {
         old_state: &LevelState,
         anim_progress: f64,
     ) {
-        // TODO variable dimension/ofset of tiles.
-        self.board.draw_ground(
-            context,
-            &assets.blocks,
-            Point(0, 0),
-            Point(CowLevel::LEVEL_WIDTH, CowLevel::LEVEL_HEIGHT),
-        );
+        let anim_progress = if assets.reduces_motion() { 1.0 } else { anim_progress };
+        // TODO variable ofset of tiles.
+        self.board
+            .draw_ground(context, &assets.blocks, Point(0, 0), self.dimensions);
+        if assets.is_colourblind() {
+            self.board
+                .draw_colour_glyphs(context, Point(0, 0), self.dimensions);
+        }
+        self.board
+            .draw_gate_labels(context, Point(0, 0), self.dimensions);
         self.cows.draw(
             context,
             &assets.sprites,
             &old_state.cows,
             anim_progress,
             self.animation_frame,
+            assets.shows_ghosts(),
         );
-        self.board.draw_overlay(
-            context,
-            &assets.blocks,
-            Point(0, 0),
-            Point(CowLevel::LEVEL_WIDTH, CowLevel::LEVEL_HEIGHT),
-        );
+        self.board
+            .draw_overlay(context, &assets.blocks, Point(0, 0), self.dimensions);
+    }
+
+    /// Numbers the input and output cells in the order they are used.
+    fn draw_io_order(&self, context: &Context2D) {
+        self.board.draw_io_order(context, Point(0, 0), self.dimensions);
+    }
+
+    /// Flashes a ring around every cow that just bounced off a wall, fading
+    /// out as the move animates.
+    fn draw_bounces(&self, context: &Context2D, anim_progress: f64) {
+        let cell_size =
+            Point(crate::SpriteSheet::STANDARD_WIDTH, crate::SpriteSheet::STANDARD_HEIGHT)
+                .map(f64::from);
+
+        crate::util::with_saved_context(context, || {
+            context.set_global_alpha(1.0 - anim_progress);
+            context.set_stroke_style_str(BOUNCE_COLOUR);
+            context.set_line_width(2.0);
+            for position in self.cows.bounced_positions() {
+                let Point(x, y) = position.map(|n| f64::from(n) + 0.5) * cell_size;
+                context.begin_path();
+                context
+                    .arc(x, y, cell_size.x() / 2.0, 0.0, std::f64::consts::PI * 2.0)
+                    .unwrap();
+                context.stroke();
+            }
+        });
+    }
+
+    /// Draws faint lines between the cells, so cells are easier to place.
+    fn draw_grid(&self, context: &Context2D) {
+        let Point(width, height) = self.bounding_rect().dimensions;
+        let cell_width = crate::SpriteSheet::STANDARD_WIDTH;
+        let cell_height = crate::SpriteSheet::STANDARD_HEIGHT;
+
+        crate::util::with_saved_context(context, || {
+            context.set_stroke_style_str(GRID_COLOUR);
+            context.set_line_width(1.0);
+            context.begin_path();
+            for column in 1..self.dimensions.x() {
+                let x = f64::from(column * cell_width);
+                context.move_to(x, 0.0);
+                context.line_to(x, f64::from(height));
+            }
+            for row in 1..self.dimensions.y() {
+                let y = f64::from(row * cell_height);
+                context.move_to(0.0, y);
+                context.line_to(f64::from(width), y);
+            }
+            context.stroke();
+        });
     }
 
     const TOTAL_ANIMATION_FRAMES: u8 = 4;
     const INITIAL_ANIMATION_FRAME: u8 = 0;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::cell::Colour;
+    use crate::SpriteSheet;
+
+    // a pixel inside the given cell, away from its top left corner.
+    fn inside(Point(x, y): Point<i32>) -> Point<i32> {
+        Point(x * SpriteSheet::STANDARD_WIDTH + 5, y * SpriteSheet::STANDARD_HEIGHT + 7)
+    }
+
+    #[test]
+    fn cells_read_back_where_they_were_placed() {
+        let mut state = LevelState::new();
+        let arrow = PaletteResult(CellType::Arrow, Colour::Red, Direction::Left);
+        let success = PaletteResult(CellType::Overlay, Colour::Green, Direction::Up);
+        state.set_cell_at_point(inside(Point(3, 2)), arrow, false);
+        state.set_cell_at_point(inside(Point(5, 6)), success, false);
+
+        assert_eq!(state.ground_cell_at(Point(3, 2)), GroundCell::Arrow(Direction::Left));
+        assert_eq!(state.ground_cell_at(Point(5, 6)), GroundCell::Empty);
+        assert!(matches!(state.overlay_cell_at(Point(5, 6)), OverlayCell::Success(_)));
+        assert!(matches!(state.overlay_cell_at(Point(3, 2)), OverlayCell::Empty));
+    }
+
+    #[test]
+    fn blanks_only_placement_keeps_existing_cells() {
+        let mut state = LevelState::new();
+        let arrow = PaletteResult(CellType::Arrow, Colour::Red, Direction::Left);
+        let block = PaletteResult(CellType::ColouredBlock, Colour::Blue, Direction::Up);
+        state.set_cell_at_point(inside(Point(1, 1)), arrow, false);
+        state.set_cell_at_point(inside(Point(1, 1)), block, true);
+        state.set_cell_at_point(inside(Point(2, 1)), block, true);
+
+        assert_eq!(state.ground_cell_at(Point(1, 1)), GroundCell::Arrow(Direction::Left));
+        assert_eq!(state.ground_cell_at(Point(2, 1)), GroundCell::ColouredBlock(Colour::Blue));
+    }
+
+    // the player, starting at (24, 4) facing right, walled in on both sides.
+    fn walled_in() -> LevelState {
+        let mut state = LevelState::new();
+        for x in &[22, 27] {
+            state.board.set_ground_cell(Point(*x, 4), GroundCell::Wall(cell::Surroundings::new()));
+        }
+        state
+    }
+
+    #[test]
+    fn bouncing_between_two_walls_is_deadlocked() {
+        let mut state = walled_in();
+        let mut seen = HashSet::new();
+        assert!(!state.is_deadlocked(&mut seen));
+
+        let mut steps = 0;
+        loop {
+            state.auto();
+            steps += 1;
+            if state.is_deadlocked(&mut seen) {
+                break;
+            }
+            assert!(steps < 20, "Still not deadlocked after {} steps", steps);
+        }
+        // over to the far wall, back to the near one, and on to where it began.
+        assert_eq!(steps, 6);
+    }
+
+    #[test]
+    fn finished_states_are_never_deadlocked() {
+        let mut state = walled_in();
+        for point in &[Point(24, 4), Point(8, 12)] {
+            state.board.set_overlay_cell(*point, OverlayCell::Success(cell::Surroundings::new()));
+        }
+        let mut seen = HashSet::new();
+        assert!(!state.is_deadlocked(&mut seen));
+        assert!(!state.is_deadlocked(&mut seen));
+    }
+}
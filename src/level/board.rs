@@ -1,24 +1,73 @@
+use std::cell::RefCell;
+use std::collections::{HashSet, VecDeque};
 use std::convert::{TryFrom, TryInto};
 
 use im_rc::OrdMap;
 use serde::{Deserialize, Serialize};
 
-use super::cell::{Cell, CellType, Colour, GroundCell, OverlayCell, PaletteResult};
-use super::NotEnoughInputSpace;
+use super::cell::{Cell, CellType, Colour, GateCondition, GroundCell, OverlayCell, PaletteResult};
+use super::{NotEnoughInputSpace, PushLimits};
+use crate::component::Rect;
 use crate::direction::Direction;
 use crate::js_ffi::draw_layer;
-use crate::{Context2D, Image, Point, SpriteSheet};
+use crate::{util, Context2D, Image, Point, SpriteSheet};
 
 pub fn get_grid_index(point: Point<i32>) -> Point<i32> {
-    let x_index = point.x() / (SpriteSheet::STANDARD_WIDTH as i32);
-    let y_index = point.y() / (SpriteSheet::STANDARD_HEIGHT as i32);
-    Point(x_index, y_index)
+    let cell_dimensions = Point(
+        SpriteSheet::STANDARD_WIDTH,
+        SpriteSheet::STANDARD_HEIGHT,
+    );
+    // with no size, every point falls in the first cell.
+    point.checked_div(cell_dimensions).unwrap_or(Point(0, 0))
+}
+
+// the smallest rectangle of grid cells holding every point.
+fn bounding_rect_of(points: &[Point<i32>]) -> Option<Rect> {
+    let first = *points.first()?;
+    let (min, max) = points.iter().fold((first, first), |(min, max), point| {
+        (
+            Point(min.x().min(point.x()), min.y().min(point.y())),
+            Point(max.x().max(point.x()), max.y().max(point.y())),
+        )
+    });
+    Some(Rect::new(min, max - min + Point(1, 1)))
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    from = "LevelLayerData<T>",
+    bound(deserialize = "T: Deserialize<'de> + Clone + PartialEq + Cell")
+)]
 pub struct LevelLayer<T: Clone> {
     layer: OrdMap<Point<i32>, T>,
     default: T,
+    // The most recently drawn layer. Cleared whenever a cell changes.
+    #[serde(skip)]
+    cache: RefCell<Option<Layer>>,
+}
+// The saved form of a `LevelLayer`. Surroundings are worked out again on
+// load, as older saves may not have tracked every neighbour.
+#[derive(Deserialize)]
+struct LevelLayerData<T: Clone> {
+    layer: OrdMap<Point<i32>, T>,
+    default: T,
+}
+impl<T> From<LevelLayerData<T>> for LevelLayer<T>
+where
+    T: Clone + PartialEq + Cell,
+{
+    fn from(data: LevelLayerData<T>) -> Self {
+        let points: Vec<Point<i32>> = data.layer.keys().cloned().collect();
+        let mut layer = LevelLayer {
+            layer: data.layer,
+            default: data.default,
+            cache: RefCell::new(None),
+        };
+        if let Some(rect) = bounding_rect_of(&points) {
+            layer.recompute_surrounds_in(rect);
+        }
+        layer
+    }
 }
 impl<T> Default for LevelLayer<T>
 where
@@ -28,6 +77,7 @@ where
         LevelLayer {
             layer: OrdMap::new(),
             default: T::default(),
+            cache: RefCell::new(None),
         }
     }
 }
@@ -40,6 +90,7 @@ where
         LevelLayer {
             layer: OrdMap::new(),
             default,
+            cache: RefCell::new(None),
         }
     }
     pub fn get_cell(&self, point: &Point<i32>) -> &T {
@@ -56,6 +107,16 @@ where
                 other
             })
         });
+        Direction::for_every(|corner| {
+            let mut diagonal = point;
+            diagonal.increment_2d(corner);
+            diagonal.increment_2d(corner.increment());
+
+            self.map_cell_unchecked(diagonal, |mut other| {
+                cell.calculate_diagonal(&mut other, corner);
+                other
+            })
+        });
 
         self.set_cell_unchecked(point, cell);
     }
@@ -67,7 +128,124 @@ where
         self.set_cell(point, func(self.get_cell(&point).clone()));
     }
 
+    /// Moves every cell through `func`, recalculating surroundings from
+    /// scratch.
+    pub fn remap<F>(&mut self, mut func: F)
+    where
+        F: FnMut(Point<i32>, T) -> (Point<i32>, T),
+    {
+        let old_layer = std::mem::replace(&mut self.layer, OrdMap::new());
+        *self.cache.get_mut() = None;
+        let mut new_points = Vec::new();
+        for (point, cell) in old_layer.into_iter() {
+            let (new_point, new_cell) = func(point, cell);
+            self.set_cell_unchecked(new_point, new_cell);
+            new_points.push(new_point);
+        }
+
+        if let Some(rect) = bounding_rect_of(&new_points) {
+            self.recompute_surrounds_in(rect);
+        }
+    }
+
+    /// Recalculates the surroundings of every cell in `rect`, given in grid
+    /// cells, and of the cells just outside it. Use after changing cells
+    /// with `set_cell_unchecked`.
+    pub fn recompute_surrounds_in(&mut self, rect: Rect) {
+        let top_left = rect.top_left - Point(1, 1);
+        let bottom_right = rect.top_left + rect.dimensions + Point(1, 1);
+        for y in top_left.y()..bottom_right.y() {
+            for x in top_left.x()..bottom_right.x() {
+                let cell = self.fresh_surrounds(Point(x, y));
+                self.set_cell_unchecked(Point(x, y), cell);
+            }
+        }
+    }
+
+    // the cell at `point`, with surroundings worked out from its neighbours.
+    fn fresh_surrounds(&self, point: Point<i32>) -> T {
+        let mut cell = self.get_cell(&point).clone();
+        Direction::for_every(|direction| {
+            let mut adjacent = point;
+            adjacent.increment_2d(direction);
+
+            let mut other = self.get_cell(&adjacent).clone();
+            cell.calculate_surround(&mut other, direction);
+        });
+        Direction::for_every(|corner| {
+            let mut diagonal = point;
+            diagonal.increment_2d(corner);
+            diagonal.increment_2d(corner.increment());
+
+            let mut other = self.get_cell(&diagonal).clone();
+            cell.calculate_diagonal(&mut other, corner);
+        });
+        cell
+    }
+
+    /// Replaces every cell connected to `start` that is the same type as it,
+    /// stopping at the edge of `within`.
+    pub fn flood_fill(&mut self, start: Point<i32>, within: Point<i32>, new: T) {
+        if !start.is_inside(within) {
+            return;
+        }
+        let target = std::mem::discriminant(self.get_cell(&start));
+        let mut visited = HashSet::new();
+        let mut queue = VecDeque::new();
+        visited.insert(start);
+        queue.push_back(start);
+
+        let mut filled = Vec::new();
+        while let Some(point) = queue.pop_front() {
+            self.set_cell_unchecked(point, new.clone());
+            filled.push(point);
+
+            Direction::for_every(|direction| {
+                let mut adjacent = point;
+                adjacent.increment_2d(direction);
+
+                if adjacent.is_inside(within)
+                    && !visited.contains(&adjacent)
+                    && std::mem::discriminant(self.get_cell(&adjacent)) == target
+                {
+                    visited.insert(adjacent);
+                    queue.push_back(adjacent);
+                }
+            });
+        }
+
+        if let Some(rect) = bounding_rect_of(&filled) {
+            self.recompute_surrounds_in(rect);
+        }
+    }
+
+    /// Checks that every cell's surroundings match its neighbours, as if the
+    /// whole layer had been placed from scratch.
+    #[cfg(debug_assertions)]
+    pub fn check_invariants(&self)
+    where
+        T: std::fmt::Debug,
+    {
+        for (point, cell) in self.layer.iter() {
+            let expected = self.fresh_surrounds(*point);
+            assert!(
+                expected == *cell,
+                "Stale surroundings at {:?}: found {:?}, expected {:?}",
+                point,
+                cell,
+                expected
+            );
+        }
+    }
+
+    /// Sets every cell back to the default.
+    pub fn clear(&mut self) {
+        *self.cache.get_mut() = None;
+        self.layer.clear();
+    }
+
     fn set_cell_unchecked(&mut self, point: Point<i32>, cell: T) {
+        *self.cache.get_mut() = None;
         if cell == self.default {
             let _ = self.layer.remove(&point);
         } else {
@@ -82,6 +260,8 @@ where
         self.set_cell_unchecked(point, func(self.get_cell(&point).clone()));
     }
 
+    /// Draws the layer, reusing the previous buffer if no cells have changed
+    /// since the last draw.
     pub fn draw(
         &self,
         context: &Context2D,
@@ -89,28 +269,74 @@ where
         top_left: Point<i32>,
         dimensions: Point<i32>,
     ) {
-        let mut layer = Layer::new(top_left, dimensions, Self::CELL_SIZE, Self::CELL_SIZE);
+        let mut cache = self.cache.borrow_mut();
+        let source_dimensions = blocks.sprite_dimensions();
+        let is_cached = cache.as_ref().is_some_and(|layer| {
+            layer.is_drawn_at(top_left, dimensions) && layer.is_drawn_from(source_dimensions)
+        });
+
+        if !is_cached {
+            *cache = Some(self.build_layer(top_left, dimensions, source_dimensions, Self::CELL_SIZE));
+        }
+
+        cache.as_ref().unwrap().draw(context, blocks.get_image());
+    }
+    /// Draws the layer with each cell shrunk or grown to
+    /// `dest_cell_dimensions` pixels. Scaled drawing is not cached.
+    pub fn draw_scaled(
+        &self,
+        context: &Context2D,
+        blocks: &SpriteSheet,
+        top_left: Point<i32>,
+        dimensions: Point<i32>,
+        dest_cell_dimensions: Point<i32>,
+    ) {
+        self.build_layer(top_left, dimensions, blocks.sprite_dimensions(), dest_cell_dimensions)
+            .draw(context, blocks.get_image());
+    }
+
+    fn build_layer(
+        &self,
+        top_left: Point<i32>,
+        dimensions: Point<i32>,
+        cell_dimensions: Point<i32>,
+        dest_cell_dimensions: Point<i32>,
+    ) -> Layer {
+        let mut layer = Layer::new(top_left, dimensions, cell_dimensions, dest_cell_dimensions);
 
         assert!(dimensions.x() >= 0);
         assert!(dimensions.y() >= 0);
 
+        let default_index = self.default.get_sprite_sheet_index();
         for (point, cell) in self.layer.iter() {
             if !point.is_inside(dimensions) {
                 continue;
             }
 
-            while layer.cursor() < *point {
-                self.default.draw_into_layer(&mut layer);
-            }
-
+            layer.fill_default_until(*point, default_index);
             cell.draw_into_layer(&mut layer);
         }
 
-        while !layer.is_full() {
-            self.default.draw_into_layer(&mut layer);
-        }
+        layer.fill_default_until(Point(0, dimensions.y()), default_index);
+        assert!(layer.is_full());
 
-        layer.draw(context, blocks.get_image());
+        layer
+    }
+}
+impl<T: Clone> LevelLayer<T> {
+    /// Returns true if every non-default cell lies inside the given grid
+    /// dimensions.
+    pub fn is_inside(&self, dimensions: Point<i32>) -> bool {
+        self.layer.keys().all(|point| point.is_inside(dimensions))
+    }
+    /// The smallest grid dimensions holding every non-default cell, counting
+    /// from the origin.
+    pub fn extent(&self) -> Point<i32> {
+        self.layer
+            .keys()
+            .fold(Point(0, 0), |extent, point| {
+                Point(extent.x().max(point.x() + 1), extent.y().max(point.y() + 1))
+            })
     }
 }
 impl<T> super::Pasture<T> for LevelLayer<T>
@@ -162,30 +388,201 @@ impl LevelLayer<GroundCell> {
             })
             .collect()
     }
+    /// The colour held by each multiplier, repeated `copies` times.
+    pub fn get_multiplied_blocks(&self, copies: usize) -> Vec<Colour> {
+        self.layer
+            .values()
+            .filter_map(|cell| match cell {
+                GroundCell::Multiplier(Some(colour)) => Some(*colour),
+                _ => None,
+            })
+            .flat_map(|colour| std::iter::repeat_n(colour, copies))
+            .collect()
+    }
+    /// Whether any cell adds outputs of its own, on top of the blocks left
+    /// in the output area.
+    pub fn has_extra_outputs(&self) -> bool {
+        self.layer.values().any(|cell| {
+            matches!(
+                cell,
+                GroundCell::Multiplier(_) | GroundCell::Sequencer(_) | GroundCell::Recorder
+            )
+        })
+    }
+    /// Where a block placed at `point` lands, and its colour once there,
+    /// after being handed along any converters. `None` if the converters hand
+    /// it around a loop, as then it never lands.
+    pub fn follow_converters(
+        &self,
+        mut point: Point<i32>,
+        mut colour: Colour,
+    ) -> Option<(Point<i32>, Colour)> {
+        let mut visited = HashSet::new();
+        while let GroundCell::Converter(to, direction) = *self.get_cell(&point) {
+            if !visited.insert(point) {
+                return None;
+            }
+            colour = to;
+            point.increment_2d(direction);
+        }
+        Some((point, colour))
+    }
+    /// Whether any converters point around a loop, which would change a block
+    /// placed on them forever.
+    pub fn has_colour_cycle(&self) -> bool {
+        self.layer.iter().any(|(point, cell)| {
+            matches!(cell, GroundCell::Converter(_, _))
+                && self.follow_converters(*point, Colour::default()).is_none()
+        })
+    }
+}
+
+/// The order that a test's input is laid out over the input area.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub enum InputOrdering {
+    /// Left to right, then top to bottom.
+    #[default]
+    RowMajor,
+    /// Top to bottom, then left to right.
+    ColumnMajor,
+    /// The given cells first, then any other input cells in row major order.
+    /// Cells that are not part of the input area are skipped.
+    Custom(Vec<Point<i32>>),
+}
+impl InputOrdering {
+    // sorts input coordinates, given in row major order.
+    fn order(&self, mut coordinates: Vec<Point<i32>>) -> Vec<Point<i32>> {
+        match self {
+            InputOrdering::RowMajor => coordinates,
+            InputOrdering::ColumnMajor => {
+                coordinates.sort_by_key(|point| (point.x(), point.y()));
+                coordinates
+            }
+            InputOrdering::Custom(order) => {
+                let mut ordered = Vec::with_capacity(coordinates.len());
+                for point in order {
+                    if coordinates.contains(point) && !ordered.contains(point) {
+                        ordered.push(*point);
+                    }
+                }
+                coordinates.retain(|point| !ordered.contains(point));
+                ordered.extend(coordinates);
+                ordered
+            }
+        }
+    }
+    /// The ordering that follows this one when cycling through them in the
+    /// editor. Custom orderings go back to row major.
+    pub fn next(&self) -> Self {
+        match self {
+            InputOrdering::RowMajor => InputOrdering::ColumnMajor,
+            InputOrdering::ColumnMajor | InputOrdering::Custom(_) => InputOrdering::RowMajor,
+        }
+    }
+    // keeps custom orderings pointing at the same cells when the board moves.
+    fn map_points<F>(&mut self, func: F)
+    where
+        F: Fn(Point<i32>) -> Point<i32>,
+    {
+        if let InputOrdering::Custom(order) = self {
+            order.iter_mut().for_each(|point| *point = func(*point));
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Board {
     ground: LevelLayer<GroundCell>,
     overlay: LevelLayer<OverlayCell>,
+    // directions walked onto recorders, oldest first.
+    #[serde(default)]
+    recorded_path: Vec<Direction>,
+    // how many times each multiplier outputs its colour.
+    #[serde(default = "Board::default_copies")]
+    multiplier_copies: u8,
+    // every block placed on a sequencer, in the order they were placed. It
+    // only lives as long as a run, so it is never saved.
+    #[serde(skip)]
+    sequence: Vec<Colour>,
+    #[serde(default)]
+    input_ordering: InputOrdering,
 }
 impl Board {
+    #[cfg(debug_assertions)]
+    pub fn check_invariants(&self) {
+        self.ground.check_invariants();
+        self.overlay.check_invariants();
+    }
     pub fn new(default_cell: GroundCell, default_overlay: OverlayCell) -> Self {
         Board {
             ground: LevelLayer::new(default_cell),
             overlay: LevelLayer::new(default_overlay),
+            recorded_path: Vec::new(),
+            multiplier_copies: Board::default_copies(),
+            sequence: Vec::new(),
+            input_ordering: InputOrdering::default(),
         }
     }
+    fn default_copies() -> u8 {
+        2
+    }
+    /// How many input cells there are to hold a test's input.
+    pub fn input_space(&self) -> usize {
+        self.overlay.get_input_coordinates().len()
+    }
+    /// How many output cells there are to check a test's output.
+    pub fn output_space(&self) -> usize {
+        self.overlay.get_output_coordinates().len()
+    }
+    /// Whether outputs can come from cells other than the output area.
+    pub fn has_extra_outputs(&self) -> bool {
+        self.ground.has_extra_outputs()
+    }
+    /// See `LevelLayer::follow_converters`.
+    pub fn follow_converters(
+        &self,
+        point: Point<i32>,
+        colour: Colour,
+    ) -> Option<(Point<i32>, Colour)> {
+        self.ground.follow_converters(point, colour)
+    }
+    /// Whether any converters hand blocks around a loop, so that a run could
+    /// keep changing a block's colour without end.
+    pub fn has_colour_cycle(&self) -> bool {
+        self.ground.has_colour_cycle()
+    }
+    /// The total of the colour values of the blocks in the input area.
+    pub fn input_sum(&self) -> u32 {
+        let input_coordinates = self.overlay.get_input_coordinates();
+        self.ground
+            .get_coloured_blocks(&input_coordinates)
+            .into_iter()
+            .map(|colour| u32::from(u8::from(colour)))
+            .sum()
+    }
+    /// The blocks in the output area, followed by the multiplied blocks, the
+    /// sequenced blocks in the order they were placed, then the recorded path
+    /// written out as colours.
     pub fn get_outputs(&self) -> Vec<Colour> {
         let output_coordinates = self.overlay.get_output_coordinates();
-        self.ground.get_coloured_blocks(&output_coordinates)
+        let mut outputs = self.ground.get_coloured_blocks(&output_coordinates);
+        outputs.extend(self.ground.get_multiplied_blocks(self.multiplier_copies.into()));
+        outputs.extend(self.sequence.iter().copied());
+        outputs.extend(self.recorded_path.iter().map(|direction| Colour::from(*direction)));
+        outputs
+    }
+    pub fn record(&mut self, direction: Direction) {
+        self.recorded_path.push(direction);
+    }
+    pub fn record_write(&mut self, colour: Colour) {
+        self.sequence.push(colour);
     }
 
     /// Sets the input overlay area as coloured blocks. Returns false and leaves
     /// the board unchanged if the input area is loess than the input size. It
     /// will return true if the input fits inside of the input area.
     pub fn set_inputs(&mut self, input: &[Colour]) -> Result<(), NotEnoughInputSpace> {
-        let input_coordinates = self.overlay.get_input_coordinates();
+        let input_coordinates = self.ordered_input_coordinates();
         if input_coordinates.len() < input.len() {
             return Err(NotEnoughInputSpace);
         };
@@ -202,6 +599,62 @@ impl Board {
         Ok(())
     }
 
+    // the input cells in the order that `set_inputs` fills them.
+    fn ordered_input_coordinates(&self) -> Vec<Point<i32>> {
+        self.input_ordering.order(self.overlay.get_input_coordinates())
+    }
+
+    pub fn input_ordering(&self) -> &InputOrdering {
+        &self.input_ordering
+    }
+
+    /// Sets the order that `set_inputs` fills the input area in.
+    pub fn set_input_ordering(&mut self, ordering: InputOrdering) {
+        self.input_ordering = ordering;
+    }
+
+    pub fn is_inside(&self, dimensions: Point<i32>) -> bool {
+        self.ground.is_inside(dimensions) && self.overlay.is_inside(dimensions)
+    }
+
+    /// Removes every cell from both layers, keeping their default cells.
+    pub fn clear(&mut self) {
+        self.ground.clear();
+        self.overlay.clear();
+        self.recorded_path.clear();
+        self.sequence.clear();
+    }
+
+    pub fn get_gates(&self) -> Vec<(Point<i32>, GateCondition)> {
+        self.ground
+            .layer
+            .iter()
+            .filter_map(|(point, cell)| {
+                if let GroundCell::Gate(condition, _) = cell {
+                    Some((*point, *condition))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+    pub fn get_swappers(&self) -> Vec<Point<i32>> {
+        self.ground
+            .layer
+            .iter()
+            .filter_map(|(point, cell)| match cell {
+                GroundCell::Swapper => Some(*point),
+                _ => None,
+            })
+            .collect()
+    }
+    pub fn set_gate_open(&mut self, point: Point<i32>, is_open: bool) {
+        self.map_ground_cell(point, |cell| match cell {
+            GroundCell::Gate(condition, _) => GroundCell::Gate(condition, is_open),
+            cell => cell,
+        });
+    }
+
     pub fn get_ground_cell(&self, point: &Point<i32>) -> &GroundCell {
         self.ground.get_cell(point)
     }
@@ -211,6 +664,13 @@ impl Board {
     pub fn set_ground_cell(&mut self, point: Point<i32>, cell: GroundCell) {
         self.ground.set_cell(point, cell);
     }
+    pub fn set_overlay_cell(&mut self, point: Point<i32>, cell: OverlayCell) {
+        self.overlay.set_cell(point, cell);
+    }
+    /// The cells shown outside of the board, and wherever nothing is placed.
+    pub fn default_cells(&self) -> (GroundCell, OverlayCell) {
+        (self.ground.default, self.overlay.default)
+    }
     pub fn map_ground_cell<F>(&mut self, point: Point<i32>, func: F)
     where
         F: FnOnce(GroundCell) -> GroundCell,
@@ -218,8 +678,18 @@ impl Board {
         self.ground.map_cell(point, func)
     }
 
-    pub fn set_cell_at_point(&mut self, point: Point<i32>, cell_type: PaletteResult<CellType>) {
+    /// Places the palette's cell. If `blanks_only` is set, cells are only
+    /// placed over empty ground.
+    pub fn set_cell_at_point(
+        &mut self,
+        point: Point<i32>,
+        cell_type: PaletteResult<CellType>,
+        blanks_only: bool,
+    ) {
         let index = get_grid_index(point);
+        if blanks_only && *self.ground.get_cell(&index) != GroundCell::Empty {
+            return;
+        }
 
         if let Ok(cell) = GroundCell::try_from(cell_type) {
             self.ground.set_cell(index, cell)
@@ -229,6 +699,106 @@ impl Board {
         }
     }
 
+    /// Reflects the board left to right.
+    pub fn mirror_horizontal(&mut self, width: i32) {
+        let mirror = |point: Point<i32>| point.mirror_horizontal_within(width);
+        self.ground.remap(|point, cell| (mirror(point), cell.mirror_horizontal()));
+        self.overlay.remap(|point, cell| (mirror(point), cell));
+        self.input_ordering.map_points(mirror);
+    }
+    /// Turns the board upside down.
+    pub fn rotate_180(&mut self, dimensions: Point<i32>) {
+        let rotate = |point: Point<i32>| point.rotate_180_within(dimensions);
+        self.ground.remap(|point, cell| (rotate(point), cell.rotate_180()));
+        self.overlay.remap(|point, cell| (rotate(point), cell));
+        self.input_ordering.map_points(rotate);
+    }
+
+    /// Flood fills the ground from the given point with a ground cell.
+    pub fn fill_at_point(
+        &mut self,
+        point: Point<i32>,
+        cell_type: PaletteResult<CellType>,
+        dimensions: Point<i32>,
+    ) {
+        if let Ok(cell) = GroundCell::try_from(cell_type) {
+            self.ground.flood_fill(get_grid_index(point), dimensions, cell)
+        }
+    }
+
+    /// Writes the colour's letter over every coloured ground cell.
+    pub fn draw_colour_glyphs(&self, context: &Context2D, top_left: Point<i32>, dimensions: Point<i32>) {
+        util::with_saved_context(context, || {
+            context.set_font("8px KongText");
+            context.set_text_align("center");
+            context.set_fill_style_str("white");
+            for (point, cell) in self.ground.layer.iter() {
+                let colour = match cell.colour() {
+                    Some(colour) => colour,
+                    None => continue,
+                };
+                let relative = *point - top_left;
+                if !relative.is_inside(dimensions) {
+                    continue;
+                }
+                let Point(x, y) = relative * LevelLayer::<GroundCell>::CELL_SIZE;
+                context
+                    .fill_text(
+                        colour.glyph(),
+                        f64::from(x + SpriteSheet::STANDARD_WIDTH / 2),
+                        f64::from(y + SpriteSheet::STANDARD_HEIGHT * 3 / 4),
+                    )
+                    .unwrap();
+            }
+        });
+    }
+
+    /// Writes each gate's colour, threshold or channel in its corner.
+    pub fn draw_gate_labels(&self, context: &Context2D, top_left: Point<i32>, dimensions: Point<i32>) {
+        util::with_saved_context(context, || {
+            context.set_font("8px KongText");
+            context.set_text_align("right");
+            context.set_text_baseline("bottom");
+            for (point, condition) in self.get_gates() {
+                let relative = point - top_left;
+                if !relative.is_inside(dimensions) {
+                    continue;
+                }
+                let Point(x, y) = (relative + Point(1, 1)) * LevelLayer::<GroundCell>::CELL_SIZE;
+                let (x, y) = (f64::from(x) - 1.0, f64::from(y) - 1.0);
+                util::draw_text_outlined(context, &condition.label(), x, y, "white", "black");
+            }
+        });
+    }
+
+    // the number drawn on each input and output cell, counting inputs in the
+    // order they are placed and outputs in the order they are read.
+    fn io_order(&self) -> Vec<(Point<i32>, usize)> {
+        let inputs = self.ordered_input_coordinates();
+        let outputs = self.overlay.get_output_coordinates();
+        let numbered = |coordinates: Vec<Point<i32>>| coordinates.into_iter().zip(1..);
+        numbered(inputs).chain(numbered(outputs)).collect()
+    }
+
+    /// Numbers the input and output cells in the order that inputs are
+    /// placed and outputs are read.
+    pub fn draw_io_order(&self, context: &Context2D, top_left: Point<i32>, dimensions: Point<i32>) {
+        util::with_saved_context(context, || {
+            context.set_font("8px KongText");
+            context.set_text_align("left");
+            context.set_text_baseline("top");
+            for (point, number) in self.io_order() {
+                let relative = point - top_left;
+                if !relative.is_inside(dimensions) {
+                    continue;
+                }
+                let Point(x, y) = relative * LevelLayer::<OverlayCell>::CELL_SIZE;
+                let (x, y) = (f64::from(x) + 2.0, f64::from(y) + 2.0);
+                util::draw_text_outlined(context, &number.to_string(), x, y, "white", "black");
+            }
+        });
+    }
+
     pub fn draw_ground(
         &self,
         context: &Context2D,
@@ -253,8 +823,33 @@ impl super::Pasture<GroundCell> for Board {
     fn get_pasture_cell(&self, point: Point<i32>) -> &GroundCell {
         self.get_ground_cell(&point)
     }
+    // a line of push blocks moves together, as long as there is empty ground
+    // after the last one that `limits` allows.
+    fn push_cells(&mut self, point: Point<i32>, direction: Direction, limits: &PushLimits) -> bool {
+        let mut end = point;
+        let mut colours = Vec::new();
+        while let GroundCell::PushBlock(colour) = *self.get_ground_cell(&end) {
+            colours.push(colour);
+            end.increment_2d(direction);
+        }
+        if colours.is_empty()
+            || *self.get_ground_cell(&end) != GroundCell::Empty
+            || !limits.allows(end)
+        {
+            return false;
+        }
+
+        let mut current = point;
+        self.set_ground_cell(current, GroundCell::Empty);
+        for colour in colours {
+            current.increment_2d(direction);
+            self.set_ground_cell(current, GroundCell::PushBlock(colour));
+        }
+        true
+    }
 }
 
+#[derive(Debug, Clone)]
 pub struct Layer {
     top_left: Point<i32>,
     grid_dimensions: Point<i32>,
@@ -295,6 +890,28 @@ impl Layer {
         self.buffer.push(x);
         self.buffer.push(y);
     }
+    /// Adds copies of the same cell until the cursor reaches `target`. Does
+    /// nothing if the cursor is already at or past `target`. A target beyond
+    /// the end of the grid fills the rest of the layer.
+    pub fn fill_default_until(&mut self, target: Point<i32>, default_index: Option<Point<u8>>) {
+        if self.is_full() || self.cursor() >= target {
+            return;
+        }
+
+        let total_cells = (self.grid_dimensions.x() * self.grid_dimensions.y()) as usize;
+        let target_cells = (target.y() * self.grid_dimensions.x() + target.x()) as usize;
+        let run_length = target_cells.min(total_cells) - self.buffer.len() / 2;
+
+        let Point(x, y) = default_index.unwrap_or(Point(Layer::EMPTY, Layer::EMPTY));
+        self.buffer.extend([x, y].iter().cycle().take(run_length * 2));
+    }
+    pub fn is_drawn_at(&self, top_left: Point<i32>, grid_dimensions: Point<i32>) -> bool {
+        self.top_left == top_left && self.grid_dimensions == grid_dimensions
+    }
+    /// Whether the layer was built for sprites of `cell_dimensions` pixels.
+    pub fn is_drawn_from(&self, cell_dimensions: Point<i32>) -> bool {
+        self.cell_dimensions == cell_dimensions
+    }
     pub fn cursor(&self) -> Point<i32> {
         assert!(self.buffer.len() % 2 == 0);
         assert!(
@@ -324,9 +941,288 @@ impl Layer {
             image,
             self.cell_dimensions.x(),
             self.cell_dimensions.y(),
+            self.dest_cell_dimensions.x(),
+            self.dest_cell_dimensions.y(),
             self.buffer.as_ptr(),
             self.grid_dimensions.x(),
             self.grid_dimensions.y(),
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::cell::Surroundings;
+    use crate::level::Pasture;
+    use crate::sprite_layout::SpriteLayout;
+
+    fn wall_surrounds(layer: &LevelLayer<GroundCell>, point: Point<i32>) -> Surroundings {
+        match layer.get_cell(&point) {
+            GroundCell::Wall(surrounds) => *surrounds,
+            cell => panic!("Expected a wall at {:?}, found {:?}", point, cell),
+        }
+    }
+
+    #[test]
+    fn l_shaped_wall_has_inner_corner() {
+        let mut layer = LevelLayer::new(GroundCell::Empty);
+        for point in &[Point(0, 0), Point(1, 0), Point(0, 1)] {
+            layer.set_cell(*point, GroundCell::Wall(Surroundings::new()));
+        }
+
+        let corner = wall_surrounds(&layer, Point(0, 0));
+        assert_eq!(
+            Into::<u8>::into(corner),
+            1 << Direction::Right as u8 | 1 << Direction::Down as u8
+        );
+        assert_eq!(corner.inner_corners(), 1 << Direction::Right as u8);
+        assert_eq!(wall_surrounds(&layer, Point(1, 0)).inner_corners(), 0);
+        assert_eq!(wall_surrounds(&layer, Point(0, 1)).inner_corners(), 0);
+
+        let layout = SpriteLayout::current();
+        let cardinals = corner.cardinals();
+        assert_eq!(layout.wall_sprite(corner), SpriteLayout::along_row(layout.wall, cardinals));
+
+        layer.set_cell(Point(1, 1), GroundCell::Wall(Surroundings::new()));
+        let filled = wall_surrounds(&layer, Point(0, 0));
+        assert_eq!(filled.inner_corners(), 0);
+        assert_eq!(layout.wall_sprite(filled).y(), layout.wall_corner.y());
+        let sprite = layer.get_cell(&Point(0, 0)).get_sprite_sheet_index();
+        assert_eq!(sprite, Some(layout.wall_sprite(filled)));
+        layer.check_invariants();
+    }
+
+    #[test]
+    fn random_edits_keep_surroundings_fresh() {
+        const SIZE: i32 = 6;
+        let cells = [
+            GroundCell::Empty,
+            GroundCell::Fence(Surroundings::new()),
+            GroundCell::Wall(Surroundings::new()),
+        ];
+        for seed in 1..=200 {
+            let mut rng = util::Rng::new(seed);
+            let mut layer = LevelLayer::new(GroundCell::Empty);
+            for _ in 0..60 {
+                let point = Point(rng.below(SIZE as usize) as i32, rng.below(SIZE as usize) as i32);
+                layer.set_cell(point, cells[rng.below(cells.len())]);
+
+                for y in -1..=SIZE {
+                    for x in -1..=SIZE {
+                        let point = Point(x, y);
+                        assert_eq!(
+                            *layer.get_cell(&point),
+                            layer.fresh_surrounds(point),
+                            "Stale surroundings at {:?} with seed {}",
+                            point,
+                            seed
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn board_with_push_block(point: Point<i32>) -> Board {
+        let mut board = Board::new(GroundCell::Empty, OverlayCell::Empty);
+        board.set_ground_cell(point, GroundCell::PushBlock(Colour::Red));
+        board
+    }
+
+    #[test]
+    fn push_blocks_move_onto_empty_ground() {
+        let mut board = board_with_push_block(Point(1, 0));
+        let limits = PushLimits {
+            dimensions: Point(4, 1),
+            cows: &[Point(0, 0)],
+        };
+        assert!(board.push_cells(Point(1, 0), Direction::Right, &limits));
+        assert_eq!(*board.get_ground_cell(&Point(1, 0)), GroundCell::Empty);
+        assert_eq!(*board.get_ground_cell(&Point(2, 0)), GroundCell::PushBlock(Colour::Red));
+    }
+
+    #[test]
+    fn push_blocks_stop_at_cows() {
+        let mut board = board_with_push_block(Point(1, 0));
+        let limits = PushLimits {
+            dimensions: Point(4, 1),
+            cows: &[Point(0, 0), Point(2, 0)],
+        };
+        assert!(!board.push_cells(Point(1, 0), Direction::Right, &limits));
+        assert_eq!(*board.get_ground_cell(&Point(1, 0)), GroundCell::PushBlock(Colour::Red));
+    }
+
+    #[test]
+    fn push_blocks_stop_at_the_edge() {
+        let mut board = board_with_push_block(Point(3, 0));
+        let limits = PushLimits {
+            dimensions: Point(4, 1),
+            cows: &[Point(2, 0)],
+        };
+        assert!(!board.push_cells(Point(3, 0), Direction::Right, &limits));
+        assert_eq!(*board.get_ground_cell(&Point(3, 0)), GroundCell::PushBlock(Colour::Red));
+        assert!(!board.push_cells(Point(3, 0), Direction::Up, &limits));
+    }
+
+    #[test]
+    fn loading_recomputes_surroundings() {
+        #[derive(Deserialize)]
+        struct Level {
+            board: Board,
+        }
+        let levels = [
+            include_str!("../level_data/blank_level.ron"),
+            include_str!("../level_data/blank_level_4_cows.ron"),
+            include_str!("../level_data/level_0_0.ron"),
+            include_str!("../level_data/level_0_1.ron"),
+            include_str!("../level_data/level_0_2.ron"),
+            include_str!("../level_data/level_0_3.ron"),
+            include_str!("../level_data/level_0_4.ron"),
+            include_str!("../level_data/level_0_5.ron"),
+            include_str!("../level_data/level_0_6.ron"),
+        ];
+        for level in levels.iter() {
+            let level: Level = ron::de::from_str(level).unwrap();
+            level.board.check_invariants();
+        }
+    }
+
+    #[test]
+    fn sequenced_blocks_are_output_in_order_but_never_saved() {
+        let mut board = Board::new(GroundCell::Empty, OverlayCell::Empty);
+        board.record_write(Colour::Green);
+        board.record_write(Colour::Red);
+        board.record_write(Colour::Green);
+        assert_eq!(board.get_outputs(), vec![Colour::Green, Colour::Red, Colour::Green]);
+
+        let saved = ron::ser::to_string(&board).unwrap();
+        assert!(!saved.contains("sequence"));
+        let loaded: Board = ron::de::from_str(&saved).unwrap();
+        assert!(loaded.get_outputs().is_empty());
+    }
+
+    #[test]
+    fn extent_holds_every_cell() {
+        let mut layer = LevelLayer::new(GroundCell::Empty);
+        assert_eq!(layer.extent(), Point(0, 0));
+        layer.set_cell(Point(3, 0), GroundCell::Swapper);
+        layer.set_cell(Point(0, 7), GroundCell::Swapper);
+        assert_eq!(layer.extent(), Point(4, 8));
+    }
+
+    #[test]
+    fn only_swappers_are_found() {
+        let mut board = Board::new(GroundCell::Empty, OverlayCell::Empty);
+        board.set_ground_cell(Point(2, 1), GroundCell::Swapper);
+        board.set_ground_cell(Point(0, 0), GroundCell::Swapper);
+        board.set_ground_cell(Point(1, 1), GroundCell::Wall(Surroundings::new()));
+
+        let mut swappers = board.get_swappers();
+        swappers.sort_by_key(|point| (point.y(), point.x()));
+        assert_eq!(swappers, vec![Point(0, 0), Point(2, 1)]);
+    }
+
+    // a two by two input area in the corner, filled with red then blue.
+    fn inputs_under(ordering: InputOrdering) -> Vec<GroundCell> {
+        let mut board = Board::new(GroundCell::Empty, OverlayCell::Empty);
+        let area = [Point(0, 0), Point(1, 0), Point(0, 1), Point(1, 1)];
+        for point in area.iter() {
+            board.set_overlay_cell(*point, OverlayCell::Input(Surroundings::new()));
+        }
+        board.set_input_ordering(ordering);
+        board.set_inputs(&[Colour::Red, Colour::Blue]).unwrap();
+        area.iter().map(|point| *board.get_ground_cell(point)).collect()
+    }
+
+    #[test]
+    fn inputs_follow_the_ordering() {
+        let red = GroundCell::ColouredBlock(Colour::Red);
+        let blue = GroundCell::ColouredBlock(Colour::Blue);
+        let empty = GroundCell::Empty;
+
+        assert_eq!(inputs_under(InputOrdering::RowMajor), vec![red, blue, empty, empty]);
+        assert_eq!(inputs_under(InputOrdering::ColumnMajor), vec![red, empty, blue, empty]);
+        assert_eq!(
+            inputs_under(InputOrdering::Custom(vec![Point(1, 1), Point(5, 5)])),
+            vec![blue, empty, empty, red]
+        );
+    }
+
+    #[test]
+    fn orderings_cycle_back_to_row_major() {
+        assert_eq!(InputOrdering::RowMajor.next(), InputOrdering::ColumnMajor);
+        assert_eq!(InputOrdering::ColumnMajor.next(), InputOrdering::RowMajor);
+        assert_eq!(InputOrdering::Custom(vec![Point(0, 0)]).next(), InputOrdering::RowMajor);
+    }
+
+    #[test]
+    fn io_cells_are_numbered_in_the_order_they_are_used() {
+        let mut board = Board::new(GroundCell::Empty, OverlayCell::Empty);
+        let inputs = [Point(0, 0), Point(1, 0), Point(0, 1)];
+        let outputs = [Point(5, 2), Point(3, 4)];
+        for point in inputs.iter() {
+            board.set_overlay_cell(*point, OverlayCell::Input(Surroundings::new()));
+        }
+        for point in outputs.iter() {
+            board.set_overlay_cell(*point, OverlayCell::Output(Surroundings::new()));
+        }
+        board.set_input_ordering(InputOrdering::ColumnMajor);
+
+        assert_eq!(
+            board.io_order(),
+            vec![
+                (Point(0, 0), 1),
+                (Point(0, 1), 2),
+                (Point(1, 0), 3),
+                (Point(5, 2), 1),
+                (Point(3, 4), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn cleared_boards_read_as_their_defaults() {
+        let mut board = Board::new(GroundCell::Empty, OverlayCell::Empty);
+        board.set_ground_cell(Point(2, 2), GroundCell::Wall(Surroundings::new()));
+        board.set_overlay_cell(Point(0, 0), OverlayCell::Input(Surroundings::new()));
+        board.set_overlay_cell(Point(3, 0), OverlayCell::Output(Surroundings::new()));
+        board.record_write(Colour::Red);
+
+        board.clear();
+        assert_eq!(*board.get_ground_cell(&Point(2, 2)), GroundCell::Empty);
+        assert!(matches!(board.get_overlay_cell(&Point(0, 0)), OverlayCell::Empty));
+        assert!(board.ordered_input_coordinates().is_empty());
+        assert!(board.get_outputs().is_empty());
+    }
+
+    #[test]
+    fn converter_chains_recolour_and_land() {
+        let mut board = Board::new(GroundCell::Empty, OverlayCell::Empty);
+        board.set_ground_cell(Point(1, 1), GroundCell::Converter(Colour::Blue, Direction::Right));
+        board.set_ground_cell(Point(2, 1), GroundCell::Converter(Colour::Green, Direction::Down));
+
+        assert!(!board.has_colour_cycle());
+        assert_eq!(
+            board.follow_converters(Point(1, 1), Colour::Red),
+            Some((Point(2, 2), Colour::Green))
+        );
+        assert_eq!(
+            board.follow_converters(Point(0, 0), Colour::Red),
+            Some((Point(0, 0), Colour::Red))
+        );
+    }
+
+    #[test]
+    fn converters_pointing_in_a_loop_are_a_cycle() {
+        let mut board = Board::new(GroundCell::Empty, OverlayCell::Empty);
+        board.set_ground_cell(Point(1, 1), GroundCell::Converter(Colour::Blue, Direction::Right));
+        board.set_ground_cell(Point(2, 1), GroundCell::Converter(Colour::Green, Direction::Down));
+        board.set_ground_cell(Point(2, 2), GroundCell::Converter(Colour::Red, Direction::Left));
+        assert!(!board.has_colour_cycle());
+
+        board.set_ground_cell(Point(1, 2), GroundCell::Converter(Colour::Orange, Direction::Up));
+        assert!(board.has_colour_cycle());
+        assert_eq!(board.follow_converters(Point(1, 1), Colour::Red), None);
+    }
+}
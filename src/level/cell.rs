@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 
 use crate::direction::Direction;
+use crate::sprite_layout::SpriteLayout;
 use crate::Point;
 
 use super::board::Layer;
@@ -11,17 +12,19 @@ pub mod cell_cursor;
 mod cell_graphic;
 mod cell_type;
 pub mod colour;
+mod gate;
 mod surroundings;
 
 pub use cell_cursor::{CellCursorEntry, CellPalette, PaletteResult};
 pub use cell_graphic::CellGraphic;
 pub use cell_type::CellType;
 pub use colour::Colour;
+pub use gate::{GateCondition, Signals};
 pub use surroundings::Surroundings;
 
 pub trait Cell: Sized {
     /// If both cells are equal, set them to show the correct graphics.
-    #[allow(clippy::mem_discriminant_non_enum)]
+    #[allow(enum_intrinsics_non_enums)]
     fn calculate_surround(&mut self, other: &mut Self, direction: Direction) {
         let is_adjacent = std::mem::discriminant(self) == std::mem::discriminant(other);
         self.set_surround(direction, is_adjacent);
@@ -30,6 +33,18 @@ pub trait Cell: Sized {
     /// tell a cell that it's neghbor is of the same / different type.
     fn set_surround(&mut self, direction: Direction, is_adjacent: bool);
 
+    /// Like `calculate_surround`, for the diagonal neighbour clockwise of
+    /// `corner`.
+    #[allow(enum_intrinsics_non_enums)]
+    fn calculate_diagonal(&mut self, other: &mut Self, corner: Direction) {
+        let is_adjacent = std::mem::discriminant(self) == std::mem::discriminant(other);
+        self.set_diagonal(corner, is_adjacent);
+        other.set_diagonal(corner.opposite(), is_adjacent);
+    }
+    /// tell a cell that a diagonal neighbour is of the same / different type.
+    /// Only cells that draw inner corners need to track this.
+    fn set_diagonal(&mut self, _corner: Direction, _is_adjacent: bool) {}
+
     /// Returns the x,y coordinate of the given sprite on a spritesheet
     fn get_sprite_sheet_index(&self) -> Option<Point<u8>>;
 
@@ -132,19 +147,25 @@ impl Cell for OverworldCell {
         }
     }
     fn get_sprite_sheet_index(&self) -> Option<Point<u8>> {
+        let layout = SpriteLayout::current();
         match *self {
-            OverworldCell::Fence(surrounds) => Some(Point((surrounds).into(), 14)),
-            OverworldCell::Wall(surrounds) => Some(Point((surrounds).into(), 15)),
-            OverworldCell::BlockedPath(surrounds) => Some(Point((surrounds).into(), 8)),
-            OverworldCell::ClearPath(surrounds) => Some(Point((surrounds).into(), 9)),
+            OverworldCell::Fence(surrounds) => {
+                Some(SpriteLayout::along_row(layout.fence, surrounds.into()))
+            }
+            OverworldCell::Wall(surrounds) => {
+                Some(SpriteLayout::along_row(layout.wall, surrounds.into()))
+            }
+            OverworldCell::BlockedPath(surrounds) => {
+                Some(SpriteLayout::along_row(layout.blocked_path, surrounds.into()))
+            }
+            OverworldCell::ClearPath(surrounds) => {
+                Some(SpriteLayout::along_row(layout.clear_path, surrounds.into()))
+            }
             OverworldCell::Level(level_num, colour) => {
-                assert!(level_num <= 0x0F);
-                let y_offset = 16 + (level_num % 4);
-                let x_offset = level_num - (level_num % 4) + u8::from(colour);
-                Some(Point(x_offset, y_offset))
+                Some(layout.level_sprite(level_num, colour.into()))
             }
-            OverworldCell::Empty => Some(Point(0, 4)),
-            OverworldCell::Finish => Some(Point(4, 1)),
+            OverworldCell::Empty => Some(layout.empty),
+            OverworldCell::Finish => Some(layout.finish),
         }
     }
 }
@@ -187,12 +208,21 @@ pub enum OverlayCell {
 }
 impl Cell for OverlayCell {
     fn get_sprite_sheet_index(&self) -> Option<Point<u8>> {
+        let layout = SpriteLayout::current();
         match self {
             OverlayCell::Empty => None,
-            OverlayCell::Success(surrounds) => Some(Point((*surrounds).into(), 13)),
-            OverlayCell::Failure(surrounds) => Some(Point((*surrounds).into(), 12)),
-            OverlayCell::Input(surrounds) => Some(Point((*surrounds).into(), 11)),
-            OverlayCell::Output(surrounds) => Some(Point((*surrounds).into(), 10)),
+            OverlayCell::Success(surrounds) => {
+                Some(SpriteLayout::along_row(layout.success, (*surrounds).into()))
+            }
+            OverlayCell::Failure(surrounds) => {
+                Some(SpriteLayout::along_row(layout.failure, (*surrounds).into()))
+            }
+            OverlayCell::Input(surrounds) => {
+                Some(SpriteLayout::along_row(layout.input, (*surrounds).into()))
+            }
+            OverlayCell::Output(surrounds) => {
+                Some(SpriteLayout::along_row(layout.output, (*surrounds).into()))
+            }
         }
     }
     fn set_surround(&mut self, direction: Direction, is_adjacent: bool) {
@@ -243,22 +273,78 @@ pub enum GroundCell {
     RotateLeft,
     Fence(Surroundings),
     Wall(Surroundings),
+    // the flag is true when the gate is open.
+    Gate(GateCondition, bool),
+    Swapper,
+    // records the direction of every cow that walks onto it.
+    Recorder,
+    // holds the colour of the last block placed on it, to be output several
+    // times over.
+    Multiplier(Option<Colour>),
+    // outputs every block placed on it, in the order they were placed.
+    Sequencer(Option<Colour>),
+    // a block that cows push along rather than walk over.
+    PushBlock(Colour),
+    // like a coloured arrow, but checks the cell it points to rather than
+    // the cow's children, so it works for cows that own nothing.
+    SelfColouredArrow(Colour, Direction),
+    // turns cows to face its direction, without moving them that tick.
+    Sign(Direction),
+    // turns a block placed on it to its colour, and hands it on to the cell
+    // it points to.
+    Converter(Colour, Direction),
 }
 impl Cell for GroundCell {
     fn get_sprite_sheet_index(&self) -> Option<Point<u8>> {
+        let layout = SpriteLayout::current();
         match self {
-            GroundCell::Empty => Some(Point(0, 4)),
-            GroundCell::ColouredBlock(colour) => Some(Point((*colour).into(), 0)),
-            GroundCell::Arrow(direction) => Some(Point((*direction).into(), 7)),
+            GroundCell::Empty => Some(layout.empty),
+            GroundCell::ColouredBlock(colour) => {
+                Some(SpriteLayout::along_row(layout.coloured_block, (*colour).into()))
+            }
+            GroundCell::Arrow(direction) => {
+                Some(SpriteLayout::along_row(layout.arrow, (*direction).into()))
+            }
             GroundCell::ColouredArrow(colour, direction) => {
                 let x = Into::<u8>::into(*colour) * 4 + Into::<u8>::into(*direction);
-                Some(Point(x, 3))
+                Some(SpriteLayout::along_row(layout.coloured_arrow, x))
+            }
+            GroundCell::ArrowBlock(direction) => {
+                Some(SpriteLayout::along_row(layout.arrow_block, (*direction).into()))
+            }
+            GroundCell::RotateLeft => Some(layout.rotate_left),
+            GroundCell::RotateRight => Some(layout.rotate_right),
+            GroundCell::Fence(surrounds) => {
+                Some(SpriteLayout::along_row(layout.fence, (*surrounds).into()))
+            }
+            GroundCell::Wall(surrounds) => Some(layout.wall_sprite(*surrounds)),
+            GroundCell::Gate(condition, true) => {
+                Some(SpriteLayout::along_row(layout.gate_open, condition.kind()))
+            }
+            GroundCell::Gate(condition, false) => {
+                Some(SpriteLayout::along_row(layout.gate_closed, condition.kind()))
+            }
+            GroundCell::Swapper => Some(layout.swapper),
+            GroundCell::Recorder => Some(layout.recorder),
+            GroundCell::Multiplier(None) => Some(layout.multiplier),
+            GroundCell::Multiplier(Some(colour)) | GroundCell::Sequencer(Some(colour)) => {
+                Some(SpriteLayout::along_row(layout.coloured_block, (*colour).into()))
+            }
+            GroundCell::Sequencer(None) => Some(layout.sequencer),
+            GroundCell::PushBlock(colour) => {
+                Some(SpriteLayout::along_row(layout.push_block, (*colour).into()))
+            }
+            GroundCell::SelfColouredArrow(colour, direction) => {
+                let x = Into::<u8>::into(*colour) * 4 + Into::<u8>::into(*direction);
+                Some(SpriteLayout::along_row(layout.self_coloured_arrow, x))
+            }
+            GroundCell::Sign(direction) => {
+                Some(SpriteLayout::along_row(layout.sign, (*direction).into()))
+            }
+            GroundCell::Converter(colour, direction) => {
+                let x = Into::<u8>::into(*colour) * 4 + Into::<u8>::into(*direction);
+                Some(SpriteLayout::along_row(layout.converter, x))
             }
-            GroundCell::ArrowBlock(direction) => Some(Point((*direction).into(), 1)),
-            GroundCell::RotateLeft => Some(Point(1, 2)),
-            GroundCell::RotateRight => Some(Point(0, 2)),
-            GroundCell::Fence(surrounds) => Some(Point((*surrounds).into(), 14)),
-            GroundCell::Wall(surrounds) => Some(Point((*surrounds).into(), 15)),
         }
     }
     fn set_surround(&mut self, direction: Direction, is_adjacent: bool) {
@@ -269,11 +355,17 @@ impl Cell for GroundCell {
             _ => {}
         }
     }
+    fn set_diagonal(&mut self, corner: Direction, is_adjacent: bool) {
+        if let GroundCell::Wall(ref mut surrounds) = *self {
+            surrounds.set_diagonal(corner, is_adjacent)
+        }
+    }
 }
 impl PastureCell for GroundCell {
     fn is_solid_to_cows(&self) -> bool {
         match self {
-            GroundCell::Fence(_) | GroundCell::Wall(_) => true,
+            GroundCell::Fence(_) | GroundCell::Wall(_) | GroundCell::PushBlock(_) => true,
+            GroundCell::Gate(_, is_open) => !is_open,
             _ => false,
         }
     }
@@ -293,39 +385,168 @@ impl TryFrom<PaletteResult<CellType>> for GroundCell {
             CellType::RotateRight => Ok(GroundCell::RotateRight),
             CellType::Fence => Ok(GroundCell::Fence(Surroundings::new())),
             CellType::Wall => Ok(GroundCell::Wall(Surroundings::new())),
+            CellType::EvenGate => Ok(GroundCell::Gate(GateCondition::Even(colour), false)),
+            CellType::OddGate => Ok(GroundCell::Gate(GateCondition::Odd(colour), false)),
+            CellType::SumGate => {
+                let threshold = u8::from(colour) * Direction::TOTAL_DIRECTIONS + u8::from(direction);
+                Ok(GroundCell::Gate(GateCondition::AtLeast(threshold), false))
+            }
+            CellType::SignalGate => {
+                let channel = u8::from(colour) * Direction::TOTAL_DIRECTIONS + u8::from(direction);
+                Ok(GroundCell::Gate(GateCondition::Signal(channel), false))
+            }
+            CellType::KeyGate => Ok(GroundCell::Gate(GateCondition::PlayerCarries(colour), false)),
+            CellType::Swapper => Ok(GroundCell::Swapper),
+            CellType::Recorder => Ok(GroundCell::Recorder),
+            CellType::Multiplier => Ok(GroundCell::Multiplier(None)),
+            CellType::Sequencer => Ok(GroundCell::Sequencer(None)),
+            CellType::PushBlock => Ok(GroundCell::PushBlock(colour)),
+            CellType::SelfColouredArrow => Ok(GroundCell::SelfColouredArrow(colour, direction)),
+            CellType::Sign => Ok(GroundCell::Sign(direction)),
+            CellType::Converter => Ok(GroundCell::Converter(colour, direction)),
             CellType::Overlay => Err(()),
         }
     }
 }
 impl GroundCell {
+    /// The colour shown on this cell, if any.
+    pub fn colour(self) -> Option<Colour> {
+        match self {
+            GroundCell::ColouredBlock(colour)
+            | GroundCell::ColouredArrow(colour, _)
+            | GroundCell::SelfColouredArrow(colour, _)
+            | GroundCell::Converter(colour, _)
+            | GroundCell::Multiplier(Some(colour))
+            | GroundCell::Sequencer(Some(colour))
+            | GroundCell::PushBlock(colour) => Some(colour),
+            _ => None,
+        }
+    }
+    pub fn mirror_horizontal(self) -> Self {
+        match self.map_direction(Direction::mirror_horizontal) {
+            GroundCell::RotateLeft => GroundCell::RotateRight,
+            GroundCell::RotateRight => GroundCell::RotateLeft,
+            cell => cell,
+        }
+    }
+    pub fn rotate_180(self) -> Self {
+        self.map_direction(Direction::opposite)
+    }
+    fn map_direction<F>(self, func: F) -> Self
+    where
+        F: FnOnce(Direction) -> Direction,
+    {
+        match self {
+            GroundCell::Arrow(direction) => GroundCell::Arrow(func(direction)),
+            GroundCell::ColouredArrow(colour, direction) => {
+                GroundCell::ColouredArrow(colour, func(direction))
+            }
+            GroundCell::SelfColouredArrow(colour, direction) => {
+                GroundCell::SelfColouredArrow(colour, func(direction))
+            }
+            GroundCell::ArrowBlock(direction) => GroundCell::ArrowBlock(func(direction)),
+            GroundCell::Sign(direction) => GroundCell::Sign(func(direction)),
+            GroundCell::Converter(colour, direction) => {
+                GroundCell::Converter(colour, func(direction))
+            }
+            cell => cell,
+        }
+    }
+    /// Turns the cell a quarter turn clockwise. Only cells that point
+    /// somewhere change: rotate cells have no orientation, so they stay as
+    /// they are.
     pub fn rotate_right(self) -> Self {
         match self {
             cell @ GroundCell::Empty => cell,
+            cell @ GroundCell::RotateRight => cell,
+            cell @ GroundCell::RotateLeft => cell,
             cell @ GroundCell::ColouredBlock(_) => cell,
             cell @ GroundCell::Fence(_) => cell,
             cell @ GroundCell::Wall(_) => cell,
+            cell @ GroundCell::Gate(_, _) => cell,
+            cell @ GroundCell::Swapper => cell,
+            cell @ GroundCell::Recorder => cell,
+            cell @ GroundCell::Multiplier(_) => cell,
+            cell @ GroundCell::Sequencer(_) => cell,
+            cell @ GroundCell::PushBlock(_) => cell,
             GroundCell::Arrow(direction) => GroundCell::Arrow(direction.increment()),
             GroundCell::ColouredArrow(colour, direction) => {
                 GroundCell::ColouredArrow(colour, direction.increment())
             }
+            GroundCell::SelfColouredArrow(colour, direction) => {
+                GroundCell::SelfColouredArrow(colour, direction.increment())
+            }
             GroundCell::ArrowBlock(direction) => GroundCell::ArrowBlock(direction.increment()),
-            GroundCell::RotateLeft => GroundCell::RotateRight,
-            GroundCell::RotateRight => GroundCell::RotateLeft,
+            GroundCell::Sign(direction) => GroundCell::Sign(direction.increment()),
+            GroundCell::Converter(colour, direction) => {
+                GroundCell::Converter(colour, direction.increment())
+            }
         }
     }
+    /// Turns the cell a quarter turn anticlockwise, see `rotate_right`.
     pub fn rotate_left(self) -> Self {
         match self {
             cell @ GroundCell::Empty => cell,
+            cell @ GroundCell::RotateRight => cell,
+            cell @ GroundCell::RotateLeft => cell,
             cell @ GroundCell::ColouredBlock(_) => cell,
             cell @ GroundCell::Fence(_) => cell,
             cell @ GroundCell::Wall(_) => cell,
+            cell @ GroundCell::Gate(_, _) => cell,
+            cell @ GroundCell::Swapper => cell,
+            cell @ GroundCell::Recorder => cell,
+            cell @ GroundCell::Multiplier(_) => cell,
+            cell @ GroundCell::Sequencer(_) => cell,
+            cell @ GroundCell::PushBlock(_) => cell,
             GroundCell::Arrow(direction) => GroundCell::Arrow(direction.decrement()),
             GroundCell::ColouredArrow(colour, direction) => {
                 GroundCell::ColouredArrow(colour, direction.decrement())
             }
+            GroundCell::SelfColouredArrow(colour, direction) => {
+                GroundCell::SelfColouredArrow(colour, direction.decrement())
+            }
             GroundCell::ArrowBlock(direction) => GroundCell::ArrowBlock(direction.decrement()),
-            GroundCell::RotateLeft => GroundCell::RotateRight,
-            GroundCell::RotateRight => GroundCell::RotateLeft,
+            GroundCell::Sign(direction) => GroundCell::Sign(direction.decrement()),
+            GroundCell::Converter(colour, direction) => {
+                GroundCell::Converter(colour, direction.decrement())
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sprites(cells: impl Iterator<Item = GroundCell>) -> Vec<Option<Point<u8>>> {
+        cells.map(|cell| cell.get_sprite_sheet_index()).collect()
+    }
+
+    fn directions() -> impl Iterator<Item = Direction> {
+        (0..Direction::TOTAL_DIRECTIONS).filter_map(Direction::from_u8)
+    }
+
+    fn colours() -> impl Iterator<Item = Colour> {
+        (0..Colour::TOTAL_COLOURS).filter_map(Colour::from_u8)
+    }
+
+    #[test]
+    fn signs_do_not_look_like_arrow_blocks() {
+        let signs = sprites(directions().map(GroundCell::Sign));
+        let arrow_blocks = sprites(directions().map(GroundCell::ArrowBlock));
+        assert!(signs.iter().all(|sprite| !arrow_blocks.contains(sprite)));
+    }
+
+    #[test]
+    fn self_coloured_arrows_do_not_look_like_coloured_arrows() {
+        let pairs = || {
+            colours().flat_map(|colour| directions().map(move |direction| (colour, direction)))
+        };
+        let own = sprites(pairs().map(|(colour, direction)| {
+            GroundCell::SelfColouredArrow(colour, direction)
+        }));
+        let coloured =
+            sprites(pairs().map(|(colour, direction)| GroundCell::ColouredArrow(colour, direction)));
+        assert!(own.iter().all(|sprite| !coloured.contains(sprite)));
+    }
+}
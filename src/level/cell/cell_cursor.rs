@@ -3,7 +3,8 @@ use std::convert::TryInto;
 use crate::component::{combine_dimensions, Component, Rect, Translation};
 use crate::direction::Direction;
 use crate::point::Point;
-use crate::{Assets, Context2D, SpriteSheet};
+use crate::sprite_layout::SpriteLayout;
+use crate::{util, Assets, Context2D, KeyboardState, SpriteSheet};
 
 use super::{CellGraphic, CellType, Colour, OverworldCellType};
 
@@ -23,6 +24,10 @@ impl<T> CellPalette<T> {
     const TOP_MARGIN: i32 = SpriteSheet::STANDARD_HEIGHT / 2;
     const CONTROL_OFFSET: Point<i32> = Point(Self::LEFT_MARGIN, Self::TOP_MARGIN);
     const PALETTE_OFFSET: Point<i32> = Point(Self::LEFT_MARGIN, Self::TOP_MARGIN * 4);
+    const KEY_CODES: [&'static str; 13] = [
+        "Digit0", "Digit1", "Digit2", "Digit3", "Digit4", "Digit5", "Digit6", "Digit7", "Digit8",
+        "Digit9", "KeyC", "KeyX", "KeyZ",
+    ];
 
     pub fn new(palette: Vec<CellCursorEntry<T>>) -> Self {
         assert!(!palette.is_empty());
@@ -33,7 +38,56 @@ impl<T> CellPalette<T> {
         }
     }
 }
+impl<T> CellPalette<T> {
+    /// Picks a cell, colour or direction from a key: digits select a palette
+    /// slot, C cycles the colour, and X and Z turn right and left. Returns
+    /// whether the key was used.
+    pub fn handle_key(&mut self, code: &str) -> bool {
+        match code {
+            "KeyC" => self.control.colour = self.control.colour.increment(),
+            "KeyX" => self.control.direction = self.control.direction.increment(),
+            "KeyZ" => self.control.direction = self.control.direction.decrement(),
+            _ => {
+                let slot = code
+                    .strip_prefix("Digit")
+                    .and_then(|digit| digit.parse::<usize>().ok());
+                match slot {
+                    Some(index) if index < self.palette.entries.len() => {
+                        self.palette.current = index
+                    }
+                    _ => return false,
+                }
+            }
+        }
+        true
+    }
+    /// Passes every palette key pressed this frame to `handle_key`.
+    pub fn handle_keyboard(&mut self, keyboard_state: &KeyboardState) -> bool {
+        let mut handled = false;
+        for code in Self::KEY_CODES.iter() {
+            if keyboard_state.is_pressed(code) {
+                handled |= self.handle_key(code);
+            }
+        }
+        handled
+    }
+    /// Draws the selected cell faintly over the given grid position.
+    pub fn draw_preview(&self, context: &Context2D, assets: &Assets, grid_index: Point<i32>) {
+        let entry = &self.palette.entries[self.palette.current];
+        let graphic = entry.graphic(self.control.colour, self.control.direction);
+        let Point(x, y) = grid_index * CellGraphic::CELL_SIZE;
+
+        util::with_saved_context(context, || {
+            context.set_global_alpha(0.5);
+            assets.blocks.draw(context, graphic, Point(f64::from(x), f64::from(y)));
+        })
+    }
+}
 impl<T: Clone> CellPalette<T> {
+    /// Whether cells should only be placed over empty ground.
+    pub fn is_blanks_only(&self) -> bool {
+        self.control.blanks_only
+    }
     pub fn value(&self) -> PaletteResult<T> {
         PaletteResult(
             self.palette.get_current().clone(),
@@ -59,6 +113,10 @@ impl<T> Component for CellPalette<T> {
     fn click(&mut self, point: Point<i32>) -> bool {
         self.control.click(point) || self.palette.click(point)
     }
+    fn on_resize(&mut self, dimensions: Point<i32>) {
+        let max_height = dimensions.y() - Self::PALETTE_OFFSET.y() - Self::TOP_MARGIN;
+        self.palette.reflow(max_height);
+    }
 }
 
 // no invariants
@@ -66,23 +124,30 @@ impl<T> Component for CellPalette<T> {
 struct PaletteControl {
     direction: Direction,
     colour: Colour,
+    // when set, placing a cell never overwrites another.
+    blanks_only: bool,
 }
 impl PaletteControl {
     const HEIGHT: i32 = SpriteSheet::STANDARD_HEIGHT;
-    const WIDTH: i32 = SpriteSheet::STANDARD_WIDTH * 4;
+    const WIDTH: i32 = SpriteSheet::STANDARD_WIDTH * 11 / 2;
 
     const ROTATE_LEFT_GRAPHIC: CellGraphic = CellGraphic::new(Point(0, 0), Point(6, 0));
     const ROTATE_COLOUR_GRAPHIC: CellGraphic =
         CellGraphic::new(Point(SpriteSheet::STANDARD_WIDTH * 3 / 2, 0), Point(4, 0));
     const ROTATE_RIGHT_GRAPHIC: CellGraphic =
         CellGraphic::new(Point(SpriteSheet::STANDARD_WIDTH * 3, 0), Point(5, 0));
+    const BLANKS_ONLY_OFFSET: Point<i32> = Point(SpriteSheet::STANDARD_WIDTH * 9 / 2, 0);
 
     fn new() -> Self {
         PaletteControl {
             direction: Direction::default(),
             colour: Colour::default(),
+            blanks_only: false,
         }
     }
+    fn blanks_only_graphic() -> CellGraphic {
+        CellGraphic::new(Self::BLANKS_ONLY_OFFSET, SpriteLayout::current().empty)
+    }
 }
 impl Component for PaletteControl {
     type DrawArgs = ();
@@ -102,6 +167,9 @@ impl Component for PaletteControl {
         } else if Self::ROTATE_RIGHT_GRAPHIC.in_boundary(point) {
             self.direction = self.direction.increment();
             true
+        } else if Self::blanks_only_graphic().in_boundary(point) {
+            self.blanks_only = !self.blanks_only;
+            true
         } else {
             false
         }
@@ -110,6 +178,12 @@ impl Component for PaletteControl {
         Self::ROTATE_LEFT_GRAPHIC.draw(context, assets, ());
         Self::ROTATE_COLOUR_GRAPHIC.draw(context, assets, ());
         Self::ROTATE_RIGHT_GRAPHIC.draw(context, assets, ());
+        Self::blanks_only_graphic().draw(context, assets, ());
+        if self.blanks_only {
+            let cursor =
+                CellGraphic::new(Self::BLANKS_ONLY_OFFSET, Palette::<()>::CELL_CURSOR_GRAPHIC);
+            cursor.draw(context, assets, ());
+        }
     }
 }
 
@@ -118,6 +192,8 @@ impl Component for PaletteControl {
 struct Palette<T> {
     entries: Vec<CellCursorEntry<T>>,
     current: usize,
+    // at least `COLUMNS`, more if the rows would not fit.
+    columns: i32,
 }
 impl<T> Palette<T> {
     const COLUMNS: i32 = 4;
@@ -127,17 +203,31 @@ impl<T> Palette<T> {
         Palette {
             entries,
             current: 0,
+            columns: Self::COLUMNS,
         }
     }
-    fn get_point_from_index(index: usize) -> Point<i32> {
+    fn rows(&self, columns: i32) -> i32 {
+        let len_i32: i32 = self.entries.len().try_into().unwrap();
+        (len_i32 + columns - 1) / columns
+    }
+    /// Uses the fewest columns that fit every entry into `max_height`
+    /// pixels, or one row if nothing fits.
+    fn reflow(&mut self, max_height: i32) {
+        let len_i32: i32 = self.entries.len().try_into().unwrap();
+        let max_rows = (max_height / CellGraphic::CELL_SIZE.y()).max(1);
+        self.columns = (Self::COLUMNS..len_i32)
+            .find(|columns| self.rows(*columns) <= max_rows)
+            .unwrap_or_else(|| len_i32.max(Self::COLUMNS));
+    }
+    fn get_point_from_index(&self, index: usize) -> Point<i32> {
         let index_i32: i32 = index.try_into().unwrap();
-        let column = index_i32 % Self::COLUMNS;
-        let row = (index_i32 - column) / Self::COLUMNS;
+        let column = index_i32 % self.columns;
+        let row = (index_i32 - column) / self.columns;
         Point(column, row)
     }
     fn get_index_from_point(&self, point: Point<i32>) -> Option<usize> {
-        let Point(x_index, y_index) = point / CellGraphic::CELL_SIZE;
-        let absolute_index = (x_index + y_index * Self::COLUMNS).try_into().unwrap();
+        let Point(x_index, y_index) = point.checked_div(CellGraphic::CELL_SIZE)?;
+        let absolute_index = (x_index + y_index * self.columns).try_into().unwrap();
         if absolute_index >= self.entries.len() {
             None
         } else {
@@ -151,9 +241,7 @@ impl<T> Palette<T> {
 impl<T> Component for Palette<T> {
     type DrawArgs = (Colour, Direction);
     fn bounding_rect(&self) -> Rect {
-        let len_i32: i32 = self.entries.len().try_into().unwrap();
-        let rows = (len_i32 + Self::COLUMNS - 1) / Self::COLUMNS;
-        let dimensions = Point(Self::COLUMNS, rows) * CellGraphic::CELL_SIZE;
+        let dimensions = Point(self.columns, self.rows(self.columns)) * CellGraphic::CELL_SIZE;
         Rect {
             top_left: Point(0, 0),
             dimensions,
@@ -171,13 +259,13 @@ impl<T> Component for Palette<T> {
     }
     fn draw(&self, context: &Context2D, assets: &Assets, (colour, direction): (Colour, Direction)) {
         for (index, entry) in self.entries.iter().enumerate() {
-            let offset = Self::get_point_from_index(index) * CellGraphic::CELL_SIZE;
+            let offset = self.get_point_from_index(index) * CellGraphic::CELL_SIZE;
             let graphic = CellGraphic::new(offset, entry.graphic(colour, direction));
 
             graphic.draw(context, assets, ());
         }
 
-        let offset = Self::get_point_from_index(self.current) * CellGraphic::CELL_SIZE;
+        let offset = self.get_point_from_index(self.current) * CellGraphic::CELL_SIZE;
         let cursor_graphic = CellGraphic::new(offset, Self::CELL_CURSOR_GRAPHIC);
         cursor_graphic.draw(context, assets, ());
     }
@@ -193,37 +281,65 @@ pub struct CellCursorEntry<T> {
 }
 impl From<CellType> for CellCursorEntry<CellType> {
     fn from(cell_type: CellType) -> Self {
+        let layout = SpriteLayout::current();
         match cell_type {
-            CellType::Empty => Self::new(cell_type, Point(8, 0), false, false),
-            CellType::ColouredBlock => Self::new(cell_type, Point(0, 0), true, false),
-            CellType::Arrow => Self::new(cell_type, Point(0, 7), false, true),
-            CellType::ColouredArrow => Self::new(cell_type, Point(0, 3), true, true),
-            CellType::ArrowBlock => Self::new(cell_type, Point(0, 1), false, true),
-            CellType::RotateRight => Self::new(cell_type, Point(0, 2), false, false),
-            CellType::RotateLeft => Self::new(cell_type, Point(1, 2), false, false),
-            CellType::Fence => Self::new(cell_type, Point(0, 14), false, false),
-            CellType::Wall => Self::new(cell_type, Point(0, 15), false, false),
-            CellType::Overlay => Self::new(cell_type, Point(9, 0), true, false),
+            CellType::Empty => Self::new(cell_type, layout.eraser_icon, false, false),
+            CellType::ColouredBlock => Self::new(cell_type, layout.coloured_block, true, false),
+            CellType::Arrow => Self::new(cell_type, layout.arrow, false, true),
+            CellType::ColouredArrow => Self::new(cell_type, layout.coloured_arrow, true, true),
+            CellType::ArrowBlock => Self::new(cell_type, layout.arrow_block, false, true),
+            CellType::RotateRight => Self::new(cell_type, layout.rotate_right, false, false),
+            CellType::RotateLeft => Self::new(cell_type, layout.rotate_left, false, false),
+            CellType::Fence => Self::new(cell_type, layout.fence, false, false),
+            CellType::Wall => Self::new(cell_type, layout.wall, false, false),
+            CellType::EvenGate => {
+                Self::new(cell_type, SpriteLayout::along_row(layout.gate_closed, 0), false, false)
+            }
+            CellType::OddGate => {
+                Self::new(cell_type, SpriteLayout::along_row(layout.gate_closed, 1), false, false)
+            }
+            CellType::SumGate => {
+                Self::new(cell_type, SpriteLayout::along_row(layout.gate_closed, 2), false, false)
+            }
+            CellType::SignalGate => {
+                Self::new(cell_type, SpriteLayout::along_row(layout.gate_closed, 3), false, false)
+            }
+            CellType::KeyGate => {
+                Self::new(cell_type, SpriteLayout::along_row(layout.gate_closed, 4), false, false)
+            }
+            CellType::Swapper => Self::new(cell_type, layout.swapper, false, false),
+            CellType::Recorder => Self::new(cell_type, layout.recorder, false, false),
+            CellType::Multiplier => Self::new(cell_type, layout.multiplier, false, false),
+            CellType::Sequencer => Self::new(cell_type, layout.sequencer, false, false),
+            CellType::PushBlock => Self::new(cell_type, layout.push_block, true, false),
+            CellType::SelfColouredArrow => {
+                Self::new(cell_type, layout.self_coloured_arrow, true, true)
+            }
+            CellType::Sign => Self::new(cell_type, layout.sign, false, true),
+            CellType::Converter => Self::new(cell_type, layout.converter, true, true),
+            CellType::Overlay => Self::new(cell_type, layout.overlay_icon, true, false),
         }
     }
 }
 impl From<OverworldCellType> for CellCursorEntry<OverworldCellType> {
     fn from(cell_type: OverworldCellType) -> Self {
+        let layout = SpriteLayout::current();
+        let level = |level_num| Self::new(cell_type, layout.level_sprite(level_num, 0), true, false);
         match cell_type {
-            OverworldCellType::Empty => Self::new(cell_type, Point(8, 0), false, false),
-            OverworldCellType::Fence => Self::new(cell_type, Point(0, 14), false, false),
-            OverworldCellType::Wall => Self::new(cell_type, Point(0, 15), false, false),
-            OverworldCellType::BlockedPath => Self::new(cell_type, Point(0, 8), false, false),
-            OverworldCellType::ClearPath => Self::new(cell_type, Point(0, 9), false, false),
-            OverworldCellType::Finish => Self::new(cell_type, Point(4, 1), false, false),
-            OverworldCellType::Level0 => Self::new(cell_type, Point(0, 16), true, false),
-            OverworldCellType::Level1 => Self::new(cell_type, Point(0, 17), true, false),
-            OverworldCellType::Level2 => Self::new(cell_type, Point(0, 18), true, false),
-            OverworldCellType::Level3 => Self::new(cell_type, Point(0, 19), true, false),
-            OverworldCellType::Level4 => Self::new(cell_type, Point(4, 16), true, false),
-            OverworldCellType::Level5 => Self::new(cell_type, Point(4, 17), true, false),
-            OverworldCellType::Level6 => Self::new(cell_type, Point(4, 18), true, false),
-            OverworldCellType::Level7 => Self::new(cell_type, Point(4, 19), true, false),
+            OverworldCellType::Empty => Self::new(cell_type, layout.eraser_icon, false, false),
+            OverworldCellType::Fence => Self::new(cell_type, layout.fence, false, false),
+            OverworldCellType::Wall => Self::new(cell_type, layout.wall, false, false),
+            OverworldCellType::BlockedPath => Self::new(cell_type, layout.blocked_path, false, false),
+            OverworldCellType::ClearPath => Self::new(cell_type, layout.clear_path, false, false),
+            OverworldCellType::Finish => Self::new(cell_type, layout.finish, false, false),
+            OverworldCellType::Level0 => level(0),
+            OverworldCellType::Level1 => level(1),
+            OverworldCellType::Level2 => level(2),
+            OverworldCellType::Level3 => level(3),
+            OverworldCellType::Level4 => level(4),
+            OverworldCellType::Level5 => level(5),
+            OverworldCellType::Level6 => level(6),
+            OverworldCellType::Level7 => level(7),
         }
     }
 }
@@ -236,6 +352,9 @@ impl<T> CellCursorEntry<T> {
             has_direction,
         }
     }
+    pub fn value(&self) -> &T {
+        &self.value
+    }
     fn graphic(&self, colour: Colour, direction: Direction) -> Point<u8> {
         let mut sprite_index_offset = 0;
         if self.has_direction {
@@ -251,3 +370,53 @@ impl<T> CellCursorEntry<T> {
         Point(self.graphic.x() + sprite_index_offset, self.graphic.y())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette() -> CellPalette<CellType> {
+        CellPalette::new(CellType::full_palette())
+    }
+
+    #[test]
+    fn c_cycles_the_colour() {
+        let mut palette = palette();
+        let colour = palette.value().1;
+        assert!(palette.handle_key("KeyC"));
+        assert_eq!(palette.value().1, colour.increment());
+    }
+
+    #[test]
+    fn x_and_z_turn_the_direction() {
+        let mut palette = palette();
+        let direction = palette.value().2;
+        assert!(palette.handle_key("KeyX"));
+        assert_eq!(palette.value().2, direction.increment());
+        assert!(palette.handle_key("KeyZ"));
+        assert_eq!(palette.value().2, direction);
+    }
+
+    #[test]
+    fn digits_select_palette_slots() {
+        let mut palette = palette();
+        assert!(palette.handle_key("Digit1"));
+        assert_eq!(palette.value().0, CellType::full_palette()[1].value().clone());
+        assert!(!palette.handle_key("KeyQ"));
+    }
+
+    #[test]
+    fn short_views_widen_the_palette() {
+        let mut palette = palette();
+        let tall = palette.bounding_rect();
+
+        palette.on_resize(Point(512, 80));
+        let short = palette.bounding_rect();
+        assert!(short.dimensions.y() <= 80, "{:?} is too tall", short);
+        assert!(short.dimensions.x() > tall.dimensions.x());
+        assert_eq!(short.top_left, tall.top_left);
+
+        palette.on_resize(Point(512, 256));
+        assert_eq!(palette.bounding_rect().dimensions, tall.dimensions);
+    }
+}
@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use super::CellCursorEntry;
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
 pub enum CellType {
     Empty,
     ColouredBlock,
@@ -11,6 +13,19 @@ pub enum CellType {
     RotateLeft,
     Fence,
     Wall,
+    EvenGate,
+    OddGate,
+    SumGate,
+    SignalGate,
+    KeyGate,
+    Swapper,
+    Recorder,
+    Multiplier,
+    Sequencer,
+    PushBlock,
+    SelfColouredArrow,
+    Sign,
+    Converter,
     Overlay,
 }
 impl CellType {
@@ -25,7 +40,27 @@ impl CellType {
             CellType::RotateLeft.into(),
             CellType::Fence.into(),
             CellType::Wall.into(),
+            CellType::EvenGate.into(),
+            CellType::OddGate.into(),
+            CellType::SumGate.into(),
+            CellType::SignalGate.into(),
+            CellType::KeyGate.into(),
+            CellType::Swapper.into(),
+            CellType::Recorder.into(),
+            CellType::Multiplier.into(),
+            CellType::Sequencer.into(),
+            CellType::PushBlock.into(),
+            CellType::SelfColouredArrow.into(),
+            CellType::Sign.into(),
+            CellType::Converter.into(),
             CellType::Overlay.into(),
         ]
     }
+    /// The full palette, keeping only the allowed cell types.
+    pub fn restricted_palette(allowed: &[CellType]) -> Vec<CellCursorEntry<CellType>> {
+        Self::full_palette()
+            .into_iter()
+            .filter(|entry| allowed.contains(entry.value()))
+            .collect()
+    }
 }
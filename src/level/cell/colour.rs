@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::direction::Direction;
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Colour {
     Red = 0,
@@ -7,8 +9,34 @@ pub enum Colour {
     Green = 2,
     Orange = 3,
 }
+// Colours are sprite offsets and appear in saved levels, so these values must
+// never change. Each line fails to compile if its discriminant does.
+const _: [(); 0] = [(); Colour::Red as usize];
+const _: [(); 1] = [(); Colour::Blue as usize];
+const _: [(); 2] = [(); Colour::Green as usize];
+const _: [(); 3] = [(); Colour::Orange as usize];
+
 impl Colour {
     pub const TOTAL_COLOURS: u8 = 4;
+    /// The colour with the given discriminant, or `None` if there isn't one.
+    pub fn from_u8(n: u8) -> Option<Self> {
+        match n {
+            0 => Some(Colour::Red),
+            1 => Some(Colour::Blue),
+            2 => Some(Colour::Green),
+            3 => Some(Colour::Orange),
+            _ => None,
+        }
+    }
+    /// A letter that tells the colour apart without relying on hue.
+    pub fn glyph(self) -> &'static str {
+        match self {
+            Colour::Red => "R",
+            Colour::Blue => "B",
+            Colour::Green => "G",
+            Colour::Orange => "O",
+        }
+    }
     pub fn increment(self) -> Self {
         match self {
             Colour::Red => Colour::Blue,
@@ -23,8 +51,34 @@ impl Default for Colour {
         Colour::Red
     }
 }
+// Used to write directions out as coloured blocks.
+impl From<Direction> for Colour {
+    fn from(direction: Direction) -> Self {
+        match direction {
+            Direction::Up => Colour::Red,
+            Direction::Right => Colour::Blue,
+            Direction::Down => Colour::Green,
+            Direction::Left => Colour::Orange,
+        }
+    }
+}
 impl From<Colour> for u8 {
     fn from(colour: Colour) -> Self {
         colour as u8
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colours_round_trip_through_u8() {
+        for n in 0..Colour::TOTAL_COLOURS {
+            let colour = Colour::from_u8(n).unwrap();
+            assert_eq!(u8::from(colour), n);
+        }
+        assert_eq!(Colour::from_u8(Colour::TOTAL_COLOURS), None);
+        assert_eq!(Colour::from_u8(u8::MAX), None);
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+use super::Colour;
+
+/// The condition that opens a gate. Gates are re-evaluated every tick, and
+/// are solid to cows while closed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum GateCondition {
+    /// Open when an even number of owned cows stand on blocks of this colour.
+    Even(Colour),
+    /// Open when an odd number of owned cows stand on blocks of this colour.
+    Odd(Colour),
+    /// Open when the colour values of the blocks in the input area add up to
+    /// at least this amount.
+    AtLeast(u8),
+    /// Open while the given signal channel is on.
+    Signal(u8),
+    /// Open while a cow owned by the player stands on a block of this colour.
+    PlayerCarries(Colour),
+}
+
+impl GateCondition {
+    /// The gate's offset along its sprite row.
+    pub fn kind(self) -> u8 {
+        match self {
+            GateCondition::Even(_) => 0,
+            GateCondition::Odd(_) => 1,
+            GateCondition::AtLeast(_) => 2,
+            GateCondition::Signal(_) => 3,
+            GateCondition::PlayerCarries(_) => 4,
+        }
+    }
+    /// The parameter written over the gate, as the sprite only shows the kind.
+    pub fn label(self) -> String {
+        match self {
+            GateCondition::Even(colour)
+            | GateCondition::Odd(colour)
+            | GateCondition::PlayerCarries(colour) => colour.glyph().to_owned(),
+            GateCondition::AtLeast(threshold) => threshold.to_string(),
+            GateCondition::Signal(channel) => channel.to_string(),
+        }
+    }
+}
+
+/// Signal channels set from outside the game, one bit per channel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Signals(u32);
+impl Signals {
+    pub const TOTAL_CHANNELS: u8 = 32;
+
+    /// Channels past `TOTAL_CHANNELS` are always off.
+    pub fn is_on(self, channel: u8) -> bool {
+        channel < Self::TOTAL_CHANNELS && self.0 & (1 << channel) != 0
+    }
+    pub fn set(&mut self, channel: u8, is_on: bool) {
+        if channel >= Self::TOTAL_CHANNELS {
+            return;
+        }
+        if is_on {
+            self.0 |= 1 << channel;
+        } else {
+            self.0 &= !(1 << channel);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Cell, GroundCell};
+    use super::*;
+
+    const EVERY_KIND: [GateCondition; 5] = [
+        GateCondition::Even(Colour::Red),
+        GateCondition::Odd(Colour::Red),
+        GateCondition::AtLeast(3),
+        GateCondition::Signal(2),
+        GateCondition::PlayerCarries(Colour::Red),
+    ];
+
+    #[test]
+    fn every_gate_kind_and_state_has_its_own_sprite() {
+        let mut sprites = Vec::new();
+        for &condition in EVERY_KIND.iter() {
+            for &is_open in [false, true].iter() {
+                let sprite = GroundCell::Gate(condition, is_open).get_sprite_sheet_index();
+                assert!(!sprites.contains(&sprite), "{:?} shares a sprite", condition);
+                sprites.push(sprite);
+            }
+        }
+        let ground = GroundCell::Empty.get_sprite_sheet_index();
+        assert!(!sprites.contains(&ground));
+    }
+
+    #[test]
+    fn labels_show_the_parameter() {
+        assert_eq!(GateCondition::Even(Colour::Blue).label(), "B");
+        assert_eq!(GateCondition::PlayerCarries(Colour::Orange).label(), "O");
+        assert_eq!(GateCondition::AtLeast(12).label(), "12");
+        assert_eq!(GateCondition::Signal(7).label(), "7");
+    }
+}
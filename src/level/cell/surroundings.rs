@@ -1,6 +1,10 @@
 use crate::direction::Direction;
 use serde::{Deserialize, Serialize};
 
+/// Which neighbours of a cell are of the same type. The low 4 bits hold the
+/// cardinal neighbours, one per `Direction`. The high 4 bits mark diagonal
+/// neighbours that are *not* of the same type, so that surroundings saved
+/// before diagonals were tracked read as having no gaps.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Surroundings(u8);
 impl Surroundings {
@@ -15,10 +19,39 @@ impl Surroundings {
             self.0 &= !(0x1 << bit);
         }
     }
+    /// Sets whether the diagonal neighbour clockwise of `corner` is of the
+    /// same type, i.e. `Direction::Up` is the upper right neighbour.
+    pub fn set_diagonal(&mut self, corner: Direction, value: bool) {
+        let bit = corner as u8 + Direction::TOTAL_DIRECTIONS;
+        if value {
+            self.0 &= !(0x1 << bit);
+        } else {
+            self.0 |= 0x1 << bit;
+        }
+    }
+    /// The cardinal surroundings, used to pick a sprite along its row.
+    pub fn cardinals(self) -> u8 {
+        self.0 & 0x0F
+    }
+    /// The corners where both cardinal neighbours are the same type, one
+    /// bit per corner as in `set_diagonal`.
+    pub fn junctions(self) -> u8 {
+        Self::junctions_of(self.cardinals())
+    }
+    /// Like `junctions`, for the given cardinal surroundings.
+    pub fn junctions_of(cardinals: u8) -> u8 {
+        let clockwise = (cardinals >> 1 | cardinals << 3) & 0x0F;
+        cardinals & clockwise
+    }
+    /// The junctions where the diagonal neighbour between the two cardinal
+    /// neighbours is not the same type.
+    pub fn inner_corners(self) -> u8 {
+        let gaps = self.0 >> Direction::TOTAL_DIRECTIONS;
+        gaps & self.junctions()
+    }
 }
 impl Into<u8> for Surroundings {
     fn into(self) -> u8 {
-        assert!(self.0 <= 0x0F);
-        self.0
+        self.cardinals()
     }
 }
@@ -2,11 +2,12 @@ use serde::{Deserialize, Serialize};
 
 use crate::direction::Direction;
 use crate::point::interpolate_2d;
-use crate::{console_log, Context2D, Point, SpriteSheet};
+use crate::sprite_layout::SpriteLayout;
+use crate::{console_log, util, Context2D, Point, SpriteSheet};
 
 use super::board::Board;
 use super::cell::{Colour, GroundCell, PastureCell};
-use super::{KeyboardCommand, LevelState, SuccessState};
+use super::{KeyboardCommand, LevelState, PushLimits, SuccessState};
 
 #[derive(Clone, Debug, Copy, Serialize, Deserialize)]
 pub enum CowSprite {
@@ -14,6 +15,27 @@ pub enum CowSprite {
     Grey = 1,
     Brown = 2,
 }
+// Sprite rows on the sprite sheet. Each line fails to compile if its
+// discriminant changes.
+const _: [(); 0] = [(); CowSprite::White as usize];
+const _: [(); 1] = [(); CowSprite::Grey as usize];
+const _: [(); 2] = [(); CowSprite::Brown as usize];
+
+impl CowSprite {
+    /// The sprite with the given discriminant, or `None` if there isn't one.
+    pub fn from_u8(n: u8) -> Option<Self> {
+        match n {
+            0 => Some(CowSprite::White),
+            1 => Some(CowSprite::Grey),
+            2 => Some(CowSprite::Brown),
+            _ => None,
+        }
+    }
+    /// The row of the sprite sheet this cow is drawn from.
+    pub fn sprite_row(self) -> u8 {
+        SpriteLayout::current().cow.y() + self as u8
+    }
+}
 
 #[derive(Clone, Debug, Copy, Eq, PartialEq)]
 pub enum Command {
@@ -34,19 +56,58 @@ impl From<KeyboardCommand> for Command {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, Copy)]
+#[derive(Serialize, Deserialize, Clone, Debug, Copy, Eq, PartialEq)]
 pub struct CowIndex(usize);
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Cows {
     player: CowIndex,
+    // players after the first, moved by the same commands as the first.
+    #[serde(default)]
+    other_players: Vec<CowIndex>,
     parents: Vec<CowIndex>,
     cows: Vec<Cow>,
 }
+/// A cow's position, facing, sprite and the indices of the cows it owns.
+pub type CowData = (Point<i32>, Direction, CowSprite, Vec<usize>);
+/// A problem with which cows own which.
+#[derive(Clone, Debug)]
+pub enum OwnershipError {
+    OutOfRange(usize),
+    Cycle(usize),
+    IsPlayer(usize),
+    DuplicatePlayer(usize),
+    OwnedPlayer(usize),
+}
+impl std::fmt::Display for OwnershipError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            OwnershipError::OutOfRange(index) => write!(formatter, "There is no COW {}", index),
+            OwnershipError::Cycle(index) => write!(formatter, "COW {} ends up owning itself", index),
+            OwnershipError::IsPlayer(index) => write!(formatter, "COW {} is a player", index),
+            OwnershipError::DuplicatePlayer(index) => {
+                write!(formatter, "COW {} is listed as a player twice", index)
+            }
+            OwnershipError::OwnedPlayer(index) => {
+                write!(formatter, "COW {} is a player, so can't be owned", index)
+            }
+        }
+    }
+}
+
 impl Cows {
-    pub fn new(
-        player: usize,
-        cow_data: Vec<(Point<i32>, Direction, CowSprite, Vec<usize>)>,
-    ) -> Self {
+    const GHOST_ALPHA: f64 = 0.3;
+
+    pub fn new(player: usize, cow_data: Vec<CowData>) -> Self {
+        Cows::try_new(player, cow_data).unwrap()
+    }
+
+    pub fn try_new(player: usize, cow_data: Vec<CowData>) -> Result<Self, OwnershipError> {
+        let total = cow_data.len();
+        let indices = cow_data.iter().flat_map(|(_, _, _, children)| children.iter());
+        if let Some(index) = std::iter::once(&player).chain(indices).find(|index| **index >= total) {
+            return Err(OwnershipError::OutOfRange(*index));
+        }
+
         let mut parent_vec = vec![true; cow_data.len()];
         parent_vec[player] = false;
 
@@ -69,22 +130,236 @@ impl Cows {
             .map(|(index, _)| CowIndex(index))
             .collect();
 
-        Cows {
+        let cows = Cows {
             player: CowIndex(player),
+            other_players: Vec::new(),
             parents,
             cows,
+        };
+        cows.validate()?;
+        Ok(cows)
+    }
+
+    /// Like `try_new`, with more players after the first. Every player is
+    /// given the same commands.
+    pub fn try_with_players(
+        player: usize,
+        other_players: Vec<usize>,
+        cow_data: Vec<CowData>,
+    ) -> Result<Self, OwnershipError> {
+        let mut cows = Cows::try_new(player, cow_data)?;
+        if let Some(index) = other_players.iter().find(|index| **index >= cows.cows.len()) {
+            return Err(OwnershipError::OutOfRange(*index));
+        }
+        cows.other_players = other_players.into_iter().map(CowIndex).collect();
+        let other_players = &cows.other_players;
+        cows.parents.retain(|index| !other_players.contains(index));
+        cows.validate()?;
+        Ok(cows)
+    }
+
+    /// The players and cows in the form taken by `try_with_players`.
+    pub fn to_data(&self) -> (usize, Vec<usize>, Vec<CowData>) {
+        let other_players = self.other_players.iter().map(|index| index.0).collect();
+        let cow_data = self
+            .cows
+            .iter()
+            .map(|cow| {
+                let children = cow.children.iter().map(|index| index.0).collect();
+                (cow.position, cow.direction, cow.sprite, children)
+            })
+            .collect();
+        (self.player.0, other_players, cow_data)
+    }
+
+    /// Checks that every index refers to a cow, that each player is listed
+    /// once and owned by nobody, and that no cow ends up owning itself.
+    pub fn validate(&self) -> Result<(), OwnershipError> {
+        let total = self.cows.len();
+        let children = || self.cows.iter().flat_map(|cow| cow.children.iter());
+        let mut indices = self.players().chain(self.parents.iter()).chain(children());
+        if let Some(index) = indices.find(|index| index.0 >= total) {
+            return Err(OwnershipError::OutOfRange(index.0));
+        }
+
+        let mut is_player = vec![false; total];
+        for player in self.players() {
+            if is_player[player.0] {
+                return Err(OwnershipError::DuplicatePlayer(player.0));
+            }
+            is_player[player.0] = true;
+        }
+        if let Some(child) = children().find(|child| is_player[child.0]) {
+            return Err(OwnershipError::OwnedPlayer(child.0));
+        }
+
+        let mut is_visiting = vec![false; total];
+        let mut is_done = vec![false; total];
+        for index in 0..total {
+            self.find_cycle(CowIndex(index), &mut is_visiting, &mut is_done)?;
+        }
+        Ok(())
+    }
+
+    fn find_cycle(
+        &self,
+        index: CowIndex,
+        is_visiting: &mut Vec<bool>,
+        is_done: &mut Vec<bool>,
+    ) -> Result<(), OwnershipError> {
+        if is_done[index.0] {
+            return Ok(());
+        }
+        if is_visiting[index.0] {
+            return Err(OwnershipError::Cycle(index.0));
+        }
+
+        is_visiting[index.0] = true;
+        for child_index in self.get_cow(index).children.iter() {
+            self.find_cycle(*child_index, is_visiting, is_done)?;
         }
+        is_visiting[index.0] = false;
+        is_done[index.0] = true;
+        Ok(())
+    }
+
+    pub fn player_position(&self) -> Point<i32> {
+        self.cows[self.player.0].get_position()
+    }
+    /// Where every cow stands, in order.
+    pub fn positions(&self) -> Vec<Point<i32>> {
+        self.cows.iter().map(|cow| cow.position).collect()
+    }
+
+    /// Every player controlled cow, the first player first.
+    fn players(&self) -> impl Iterator<Item = &CowIndex> {
+        std::iter::once(&self.player).chain(self.other_players.iter())
+    }
+
+    /// Gives every player the same command.
+    pub fn command_player(&mut self, board: &mut Board, command: Command, dimensions: Point<i32>) {
+        let commands = vec![command; self.players().count()];
+        self.command_players(board, &commands, dimensions);
     }
 
-    pub fn command_player(&mut self, board: &mut Board, command: Command) {
-        self.command(self.player, board, command);
+    /// Gives each player its command, in order. Players without a command
+    /// halt. Nothing is pushed outside a board of `dimensions` cells.
+    pub fn command_players(
+        &mut self,
+        board: &mut Board,
+        commands: &[Command],
+        dimensions: Point<i32>,
+    ) {
+        self.cows.iter_mut().for_each(|cow| {
+            cow.bump = None;
+            cow.bounced = false;
+        });
+
+        let players: Vec<CowIndex> = self.players().copied().collect();
+        for (index, player) in players.iter().enumerate() {
+            let command = commands.get(index).copied().unwrap_or(Command::Halt);
+            self.command(*player, board, command, dimensions);
+        }
 
         // CIRCULAR REFERENCE WARNING !!!! The parents vector is cached here.
         // It is assumed that the parents are unmodified through the process of
         // updating the children. Breaking this assumption may lead to bugs.
         let parents = self.parents.clone();
         for cow_index in parents {
-            self.command(cow_index, board, Command::Auto);
+            if players.contains(&cow_index) {
+                continue;
+            }
+            self.command(cow_index, board, Command::Auto, dimensions);
+        }
+
+        self.swap_across(board);
+    }
+
+    /// Two cows standing on opposite sides of a swapper trade places.
+    fn swap_across(&mut self, board: &Board) {
+        for point in board.get_swappers() {
+            // the cells left of and above the swapper, each swapped with the
+            // cell on the opposite side.
+            let left = point - Point(1, 0);
+            for first in [left, left.rotate_cw_90(point)].iter().copied() {
+                let second = first.rotate_180(point);
+
+                if let (Some(first_index), Some(second_index)) =
+                    (self.cow_at(first), self.cow_at(second))
+                {
+                    self.get_cow_mut(first_index).position = second;
+                    self.get_cow_mut(second_index).position = first;
+                }
+            }
+        }
+    }
+
+    fn cow_at(&self, point: Point<i32>) -> Option<CowIndex> {
+        self.cows
+            .iter()
+            .position(|cow| cow.position == point)
+            .map(CowIndex)
+    }
+
+    pub fn is_inside(&self, dimensions: Point<i32>) -> bool {
+        self.cows.iter().all(|cow| cow.position.is_inside(dimensions))
+    }
+
+    /// Reflects every cow left to right, see `Board::mirror_horizontal`.
+    pub fn mirror_horizontal(&mut self, width: i32) {
+        for cow in self.cows.iter_mut() {
+            cow.position = cow.position.mirror_horizontal_within(width);
+            cow.direction = cow.direction.mirror_horizontal();
+        }
+    }
+    /// Turns every cow upside down, see `Board::rotate_180`.
+    pub fn rotate_180(&mut self, dimensions: Point<i32>) {
+        for cow in self.cows.iter_mut() {
+            cow.position = cow.position.rotate_180_within(dimensions);
+            cow.direction = cow.direction.opposite();
+        }
+    }
+
+    /// The number of owned cows standing on a block of the given colour.
+    pub fn count_owned_on(&self, board: &Board, colour: Colour) -> usize {
+        self.cows
+            .iter()
+            .flat_map(|cow| cow.children.iter())
+            .filter(|child_index| {
+                self.get_cow(**child_index).get_cell(board) == GroundCell::ColouredBlock(colour)
+            })
+            .count()
+    }
+
+    /// Whether a cow owned by a player stands on a block of the given
+    /// colour. Cows owned by other cows do not count.
+    pub fn player_carries(&self, board: &Board, colour: Colour) -> bool {
+        self.players()
+            .flat_map(|player| self.get_cow(*player).children.iter())
+            .any(|child_index| {
+                self.get_cow(*child_index).get_cell(board) == GroundCell::ColouredBlock(colour)
+            })
+    }
+
+    #[cfg(debug_assertions)]
+    pub fn check_invariants(&self) {
+        let total = self.cows.len();
+        for player in self.players() {
+            assert!(player.0 < total, "Player is cow {} of {}", player.0, total);
+        }
+        for parent in self.parents.iter() {
+            assert!(parent.0 < total, "Parent is cow {} of {}", parent.0, total);
+        }
+        for (index, cow) in self.cows.iter().enumerate() {
+            for child in cow.children.iter() {
+                assert!(child.0 < total, "Cow {} owns cow {} of {}", index, child.0, total);
+                assert!(
+                    self.parents.iter().all(|parent| parent.0 != child.0),
+                    "Cow {} is owned by cow {} but is cached as a parent",
+                    child.0,
+                    index
+                );
+            }
         }
     }
 
@@ -96,6 +371,100 @@ impl Cows {
         acc
     }
 
+    /// The cow that owns the given cow, if any.
+    pub fn parent_of(&self, index: CowIndex) -> Option<CowIndex> {
+        self.cows
+            .iter()
+            .position(|cow| cow.children.contains(&index))
+            .map(CowIndex)
+    }
+
+    /// The given cow followed by its owner, its owner's owner, and so on up
+    /// to a cow that is not owned. Stops early if the ownership loops back on
+    /// itself.
+    pub fn ownership_chain(&self, index: CowIndex) -> Vec<CowIndex> {
+        let mut chain = vec![index];
+        let mut current = index;
+
+        while let Some(parent) = self.parent_of(current) {
+            if chain.contains(&parent) {
+                break;
+            }
+            chain.push(parent);
+            current = parent;
+        }
+        chain
+    }
+
+    /// `ownership_chain` for the cow standing at `point`, if any.
+    pub fn ownership_chain_at(&self, point: Point<i32>) -> Vec<usize> {
+        self.cow_at(point).map_or_else(Vec::new, |index| {
+            self.ownership_chain(index).into_iter().map(|index| index.0).collect()
+        })
+    }
+
+    /// Adds a cow, owned by `parent` or else moving on its own, and returns
+    /// its index. Nothing spawns cows during a level yet, only the editor
+    /// adds them.
+    pub fn add_cow(&mut self, cow: Cow, parent: Option<CowIndex>) -> CowIndex {
+        let index = CowIndex(self.cows.len());
+        match parent {
+            Some(parent) => self.get_cow_mut(parent).children.push(index),
+            None => self.parents.push(index),
+        }
+        self.cows.push(cow);
+        index
+    }
+
+    /// Removes a cow. Any cows it owned are let go and move on their own.
+    /// Every cow after it moves down an index. Players can't be removed.
+    pub fn remove_cow(&mut self, index: CowIndex) -> Result<Cow, OwnershipError> {
+        if index.0 >= self.cows.len() {
+            return Err(OwnershipError::OutOfRange(index.0));
+        }
+        if self.players().any(|player| *player == index) {
+            return Err(OwnershipError::IsPlayer(index.0));
+        }
+
+        let cow = self.cows.remove(index.0);
+        self.parents.retain(|parent| *parent != index);
+        for other in self.cows.iter_mut() {
+            other.children.retain(|child| *child != index);
+        }
+        self.parents.extend(cow.children.iter().copied());
+
+        let shift = |cow_index: &mut CowIndex| {
+            if cow_index.0 > index.0 {
+                cow_index.0 -= 1;
+            }
+        };
+        shift(&mut self.player);
+        self.other_players.iter_mut().for_each(shift);
+        self.parents.iter_mut().for_each(shift);
+        for other in self.cows.iter_mut() {
+            other.children.iter_mut().for_each(shift);
+        }
+        Ok(cow)
+    }
+
+    /// Adds a cow moving on its own at `point`, unless one stands there
+    /// already. Returns whether a cow was added.
+    pub fn add_cow_at(&mut self, point: Point<i32>) -> bool {
+        if self.cow_at(point).is_some() {
+            return false;
+        }
+        self.add_cow(Cow::new(point, Direction::Right, vec![], CowSprite::Grey), None);
+        true
+    }
+    /// Removes the cow at `point`, unless there is none or it is a player.
+    /// Returns whether a cow was removed.
+    pub fn remove_cow_at(&mut self, point: Point<i32>) -> bool {
+        match self.cow_at(point) {
+            Some(index) => self.remove_cow(index).is_ok(),
+            None => false,
+        }
+    }
+
     fn get_cow(&self, cow_index: CowIndex) -> &Cow {
         &self.cows[cow_index.0]
     }
@@ -104,44 +473,89 @@ impl Cows {
         &mut self.cows[cow_index.0]
     }
 
-    fn command(&mut self, cow_index: CowIndex, board: &mut Board, command: Command) {
+    fn command(
+        &mut self,
+        cow_index: CowIndex,
+        board: &mut Board,
+        command: Command,
+        dimensions: Point<i32>,
+    ) {
+        let cows = self.positions();
+        let limits = PushLimits { dimensions, cows: &cows };
         let cow = self.get_cow_mut(cow_index);
+        let old_position = cow.position;
 
         match command {
             Command::Auto => {
                 let cell = cow.get_cell(board);
-                match cell {
+                let bounced = match cell {
                     GroundCell::Empty
                     | GroundCell::ColouredBlock(_)
                     | GroundCell::ArrowBlock(_)
                     | GroundCell::RotateLeft
                     | GroundCell::RotateRight
                     | GroundCell::Fence(_)
-                    | GroundCell::Wall(_) => cow.walk_bounce(board),
-                    GroundCell::Arrow(direction) => cow.walk_stop(board, direction),
+                    | GroundCell::Wall(_)
+                    | GroundCell::Gate(_, _)
+                    | GroundCell::Swapper
+                    | GroundCell::Recorder
+                    | GroundCell::Multiplier(_)
+                    | GroundCell::Sequencer(_)
+                    | GroundCell::PushBlock(_)
+                    | GroundCell::Converter(_, _) => cow.walk_bounce(board, &limits),
+                    GroundCell::Arrow(direction) => {
+                        cow.walk_stop(board, direction, &limits);
+                        false
+                    }
+                    GroundCell::Sign(direction) => {
+                        if cow.direction == direction {
+                            cow.walk_bounce(board, &limits)
+                        } else {
+                            cow.face(direction);
+                            false
+                        }
+                    }
+                    GroundCell::SelfColouredArrow(colour, direction) => {
+                        let mut ahead = cow.position;
+                        ahead.increment_2d(direction);
+                        if *board.get_ground_cell(&ahead) == GroundCell::ColouredBlock(colour) {
+                            cow.walk_stop(board, direction, &limits);
+                            false
+                        } else {
+                            cow.walk_bounce(board, &limits)
+                        }
+                    }
                     GroundCell::ColouredArrow(colour, direction) => {
                         // Caching warning. Children is cached here.
                         let children = cow.children.clone();
                         
-                        self.conditional_walk(cow_index, board, colour, direction);
+                        let bounced =
+                            self.conditional_walk(cow_index, board, colour, direction, &limits);
                         children.into_iter().for_each(|child_index| {
-                            self.command(child_index, board, Command::DeleteCell);
+                            self.command(child_index, board, Command::DeleteCell, dimensions);
                         });
+                        bounced
                     }
                 };
+                self.get_cow_mut(cow_index).bounced = bounced;
             }
             Command::Halt => {}
-            Command::Walk(direction) => cow.walk_stop(board, direction),
+            Command::Walk(direction) => cow.walk_stop(board, direction, &limits),
             Command::PlaceBlock(colour) => cow.place_block(board, colour),
             Command::DeleteCell => cow.delete_cell(board),
             Command::RotateLeft => cow.rotate_block_left(board),
             Command::RotateRight => cow.rotate_block_right(board),
         }
 
-        self.update_children(cow_index, board);
+        let cow = self.get_cow(cow_index);
+        if cow.position != old_position && cow.get_cell(board) == GroundCell::Recorder {
+            board.record(cow.direction);
+        }
+
+        self.update_children(cow_index, board, dimensions);
     }
 
-    fn update_children(&mut self, cow_index: CowIndex, board: &mut Board) {
+    fn update_children(&mut self, cow_index: CowIndex, board: &mut Board, dimensions: Point<i32>) {
         let cow = self.get_cow(cow_index);
         let cell = cow.get_cell(board);
 
@@ -156,6 +570,8 @@ impl Cows {
             GroundCell::Arrow(_) => Command::Halt,
             GroundCell::ArrowBlock(direction) => Command::Walk(direction),
             GroundCell::ColouredArrow(_, _) => Command::Halt,
+            GroundCell::SelfColouredArrow(_, _) => Command::Halt,
+            GroundCell::Sign(_) => Command::Halt,
             GroundCell::RotateRight => Command::RotateRight,
             GroundCell::RotateLeft => Command::RotateLeft,
             GroundCell::Fence(_) => {
@@ -166,49 +582,65 @@ impl Cows {
                 console_log!("WARNING: Cow registered inside wall");
                 Command::Halt
             }
+            GroundCell::PushBlock(_) => {
+                console_log!("WARNING: Cow registered inside push block");
+                Command::Halt
+            }
+            GroundCell::Gate(_, _) => Command::Halt,
+            GroundCell::Swapper => Command::Halt,
+            GroundCell::Recorder => Command::Halt,
+            GroundCell::Multiplier(_) => Command::Halt,
+            GroundCell::Sequencer(_) => Command::Halt,
+            GroundCell::Converter(_, _) => Command::Halt,
         };
 
         children.into_iter().for_each(|child_index| {
-            self.command(child_index, board, command);
+            self.command(child_index, board, command, dimensions);
         });
     }
 
+    // returns true if the cow bounced off a wall.
     fn conditional_walk(
         &mut self,
         cow_index: CowIndex,
-        board: &Board,
+        board: &mut Board,
         colour: Colour,
         direction: Direction,
-    ) {
+        limits: &PushLimits,
+    ) -> bool {
         let is_correct_colour = self.get_cow(cow_index).children.iter().any(|child_index| {
             self.get_cow(*child_index).get_cell(board) == GroundCell::ColouredBlock(colour)
         });
 
         if is_correct_colour {
-            self.get_cow_mut(cow_index).walk_stop(board, direction);
+            self.get_cow_mut(cow_index).walk_stop(board, direction, limits);
+            false
         } else {
-            self.get_cow_mut(cow_index).walk_bounce(board);
+            self.get_cow_mut(cow_index).walk_bounce(board, limits)
         }
     }
 
+    /// Where each cow that bounced off a wall on the last move now stands.
+    pub fn bounced_positions(&self) -> Vec<Point<i32>> {
+        self.cows
+            .iter()
+            .filter(|cow| cow.bounced)
+            .map(|cow| cow.position)
+            .collect()
+    }
+
     fn get_screen_position(
         &self,
         old_cows: &Cows,
         index: CowIndex,
         anim_progress: f64,
     ) -> Point<f64> {
-        let new_position = self.get_cow(index).position;
+        let cow = self.get_cow(index);
         let old_position = old_cows
             .cows
             .get(index.0)
-            .map_or(new_position, |old_cow| old_cow.position);
-        let grid_position = interpolate_2d(old_position, new_position, anim_progress);
-
-        grid_position
-            * Point(
-                f64::from(SpriteSheet::STANDARD_WIDTH),
-                f64::from(SpriteSheet::STANDARD_HEIGHT),
-            )
+            .map_or(cow.position, |old_cow| old_cow.position);
+        cow.get_screen_position(old_position, anim_progress)
     }
 
     pub fn draw(
@@ -218,6 +650,7 @@ impl Cows {
         old_cows: &Cows,
         anim_progress: f64,
         anim_frame: u8,
+        shows_ghosts: bool,
     ) {
         context.save();
 
@@ -250,6 +683,12 @@ impl Cows {
                 .cows
                 .get(index)
                 .map_or(cow.position, |old_cow| old_cow.position);
+            if shows_ghosts && anim_progress < 1.0 && old_position != cow.position {
+                util::with_saved_context(context, || {
+                    context.set_global_alpha(Self::GHOST_ALPHA);
+                    cow.draw(context, sprite_sheet, old_position, 0.0, anim_frame);
+                });
+            }
             cow.draw(
                 context,
                 sprite_sheet,
@@ -267,6 +706,12 @@ pub struct Cow {
     direction: Direction,
     children: Vec<CowIndex>,
     sprite: CowSprite,
+    /// The direction the cow was blocked in on its last move, if any.
+    #[serde(skip)]
+    bump: Option<Direction>,
+    /// Whether the cow turned around at a wall on its last move.
+    #[serde(skip)]
+    bounced: bool,
 }
 impl Default for Cow {
     fn default() -> Self {
@@ -275,10 +720,15 @@ impl Default for Cow {
             direction: Direction::Right,
             children: vec![],
             sprite: CowSprite::White,
+            bump: None,
+            bounced: false,
         }
     }
 }
 impl Cow {
+    /// How far a blocked cow leans into the obstacle, in cells.
+    const BUMP_DISTANCE: f64 = 0.2;
+
     pub fn new(
         position: Point<i32>,
         direction: Direction,
@@ -290,18 +740,25 @@ impl Cow {
             direction,
             children,
             sprite,
+            bump: None,
+            bounced: false,
         }
     }
     pub fn get_position(&self) -> Point<i32> {
         self.position
     }
+    /// Turns the cow to face `direction` without moving it.
+    pub fn face(&mut self, direction: Direction) {
+        self.direction = direction;
+        self.bump = None;
+    }
 
     fn get_cell(&self, board: &Board) -> GroundCell {
         *board.get_ground_cell(&self.position)
     }
 
     // walk until you hit a wall.
-    pub fn walk_stop<P, C>(&mut self, board: &P, direction: Direction)
+    pub fn walk_stop<P, C>(&mut self, board: &mut P, direction: Direction, limits: &PushLimits)
     where
         P: super::Pasture<C>,
         C: PastureCell,
@@ -311,14 +768,17 @@ impl Cow {
         let mut forwards = self.position;
         forwards.increment_2d(direction);
 
+        board.push_cells(forwards, direction, limits);
         if !board.get_pasture_cell(forwards).is_solid_to_cows() {
             self.position.increment_2d(direction);
             return;
         }
+        self.bump = Some(direction);
     }
 
-    // when you hit a wall, turn around and bounce the other way.
-    fn walk_bounce<P, C>(&mut self, board: &P)
+    // when you hit a wall, turn around and bounce the other way. Returns true
+    // if the cow hit a wall.
+    fn walk_bounce<P, C>(&mut self, board: &mut P, limits: &PushLimits) -> bool
     where
         P: super::Pasture<C>,
         C: PastureCell,
@@ -326,9 +786,10 @@ impl Cow {
         let mut forwards = self.position;
         forwards.increment_2d(self.direction);
 
+        board.push_cells(forwards, self.direction, limits);
         if !board.get_pasture_cell(forwards).is_solid_to_cows() {
             self.position.increment_2d(self.direction);
-            return;
+            return false;
         }
 
         let opposite_dir = self.direction.opposite();
@@ -337,17 +798,44 @@ impl Cow {
         let mut backwards = self.position;
         backwards.increment_2d(opposite_dir);
 
+        board.push_cells(backwards, opposite_dir, limits);
         if !board.get_pasture_cell(backwards).is_solid_to_cows() {
             self.position.increment_2d(opposite_dir);
+            return true;
         }
+        self.bump = Some(opposite_dir);
+        true
     }
 
     fn place_block(&mut self, board: &mut Board, colour: Colour) {
-        board.set_ground_cell(self.position, GroundCell::ColouredBlock(colour));
+        let (position, colour) = match board.follow_converters(self.position, colour) {
+            Some(landing) => landing,
+            // a block handed around a loop of converters never lands.
+            None => return,
+        };
+        let cell = match *board.get_ground_cell(&position) {
+            GroundCell::Multiplier(_) => GroundCell::Multiplier(Some(colour)),
+            GroundCell::Sequencer(held) => {
+                // owned cows place their block every tick, so only count changes.
+                if held != Some(colour) {
+                    board.record_write(colour);
+                }
+                GroundCell::Sequencer(Some(colour))
+            }
+            _ => GroundCell::ColouredBlock(colour),
+        };
+        board.set_ground_cell(position, cell);
     }
     fn delete_cell(&mut self, board: &mut Board) {
-        if let GroundCell::ColouredBlock(_) = self.get_cell(board) {
-            board.set_ground_cell(self.position, GroundCell::Empty);
+        match self.get_cell(board) {
+            GroundCell::ColouredBlock(_) => board.set_ground_cell(self.position, GroundCell::Empty),
+            GroundCell::Multiplier(Some(_)) => {
+                board.set_ground_cell(self.position, GroundCell::Multiplier(None))
+            }
+            GroundCell::Sequencer(Some(_)) => {
+                board.set_ground_cell(self.position, GroundCell::Sequencer(None))
+            }
+            _ => {}
         }
     }
 
@@ -360,13 +848,18 @@ impl Cow {
     }
 
     fn get_screen_position(&self, other: Point<i32>, anim_progress: f64) -> Point<f64> {
-        let grid_position = interpolate_2d(other, self.position, anim_progress);
+        let mut grid_position = interpolate_2d(other, self.position, anim_progress);
 
-        grid_position
-            * Point(
-                f64::from(SpriteSheet::STANDARD_WIDTH),
-                f64::from(SpriteSheet::STANDARD_HEIGHT),
-            )
+        // lean towards the obstacle and back again.
+        if let Some(direction) = self.bump {
+            let mut target = self.position;
+            target.increment_2d(direction);
+            let lean = Self::BUMP_DISTANCE * (anim_progress * std::f64::consts::PI).sin();
+            grid_position = interpolate_2d(self.position, target, lean);
+        }
+
+        let cell_size = Point(SpriteSheet::STANDARD_WIDTH, SpriteSheet::STANDARD_HEIGHT);
+        grid_position * cell_size.map(f64::from)
     }
 
     pub fn draw(
@@ -378,11 +871,247 @@ impl Cow {
         animation_frame: u8,
     ) {
         let position = self.get_screen_position(old_position, anim_progress);
+        let cow_sprite = SpriteLayout::current().cow;
         let sprite_index = Point(
-            self.direction as u8 * LevelState::TOTAL_ANIMATION_FRAMES + animation_frame,
-            self.sprite as u8,
+            cow_sprite.x() + self.direction as u8 * LevelState::TOTAL_ANIMATION_FRAMES + animation_frame,
+            self.sprite.sprite_row(),
         );
 
         sprite_sheet.draw(context, sprite_index, position);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::cell::{OverlayCell, Surroundings};
+
+    const DIMENSIONS: Point<i32> = Point(8, 8);
+
+    fn empty_board() -> Board {
+        Board::new(GroundCell::Empty, OverlayCell::Empty)
+    }
+
+    /// A player tucked in the corner, and one free cow.
+    fn one_cow(position: Point<i32>, direction: Direction) -> Cows {
+        Cows::new(
+            0,
+            vec![
+                (Point(0, 0), Direction::Up, CowSprite::White, vec![]),
+                (position, direction, CowSprite::Grey, vec![]),
+            ],
+        )
+    }
+
+    fn tick(cows: &mut Cows, board: &mut Board) {
+        cows.command_player(board, Command::Halt, DIMENSIONS);
+    }
+
+    #[test]
+    fn every_cow_sprite_has_its_own_row() {
+        // sprites.png is 256 pixels tall, in rows of 16.
+        const SHEET_ROWS: u8 = 16;
+        let rows: Vec<u8> = (0..=u8::MAX)
+            .map_while(CowSprite::from_u8)
+            .map(CowSprite::sprite_row)
+            .collect();
+        assert_eq!(rows.len(), 3);
+        for (index, row) in rows.iter().enumerate() {
+            assert!(*row < SHEET_ROWS, "row {} is off the sprite sheet", row);
+            assert!(!rows[..index].contains(row), "row {} is used twice", row);
+        }
+    }
+
+    #[test]
+    fn signs_turn_cows_without_moving_them() {
+        let mut board = empty_board();
+        board.set_ground_cell(Point(3, 3), GroundCell::Sign(Direction::Up));
+        let mut cows = one_cow(Point(3, 3), Direction::Right);
+
+        tick(&mut cows, &mut board);
+        let cow = cows.get_cow(CowIndex(1));
+        assert_eq!(cow.position, Point(3, 3));
+        assert_eq!(cow.direction, Direction::Up);
+
+        tick(&mut cows, &mut board);
+        assert_eq!(cows.get_cow(CowIndex(1)).position, Point(3, 2));
+    }
+
+    fn cow_data(positions: &[Point<i32>]) -> Vec<CowData> {
+        positions
+            .iter()
+            .map(|position| (*position, Direction::Right, CowSprite::White, vec![]))
+            .collect()
+    }
+
+    #[test]
+    fn swappers_trade_cows_on_opposite_sides() {
+        let mut board = empty_board();
+        board.set_ground_cell(Point(4, 4), GroundCell::Swapper);
+        let positions = [Point(0, 0), Point(3, 4), Point(5, 4), Point(4, 3), Point(4, 6)];
+        let mut cows = Cows::new(0, cow_data(&positions));
+
+        cows.swap_across(&board);
+        let swapped = [Point(0, 0), Point(5, 4), Point(3, 4), Point(4, 3), Point(4, 6)];
+        assert_eq!(cows.positions(), swapped.to_vec());
+    }
+
+    #[test]
+    fn blocks_placed_on_converters_land_where_they_point() {
+        let mut board = empty_board();
+        board.set_ground_cell(Point(1, 1), GroundCell::ColouredBlock(Colour::Red));
+        board.set_ground_cell(Point(1, 4), GroundCell::Converter(Colour::Blue, Direction::Right));
+        let mut cows = Cows::new(
+            0,
+            vec![
+                (Point(1, 1), Direction::Up, CowSprite::White, vec![1]),
+                (Point(1, 4), Direction::Up, CowSprite::Grey, vec![]),
+            ],
+        );
+
+        tick(&mut cows, &mut board);
+        assert_eq!(
+            *board.get_ground_cell(&Point(1, 4)),
+            GroundCell::Converter(Colour::Blue, Direction::Right)
+        );
+        assert_eq!(*board.get_ground_cell(&Point(2, 4)), GroundCell::ColouredBlock(Colour::Blue));
+    }
+
+    #[test]
+    fn every_player_follows_the_command() {
+        let mut board = empty_board();
+        let mut cows =
+            Cows::try_with_players(0, vec![1], cow_data(&[Point(1, 1), Point(1, 4)])).unwrap();
+        cows.command_player(&mut board, Command::Walk(Direction::Right), DIMENSIONS);
+        assert_eq!(cows.positions(), vec![Point(2, 1), Point(2, 4)]);
+    }
+
+    #[test]
+    fn players_are_listed_once() {
+        let data = cow_data(&[Point(1, 1), Point(1, 4)]);
+        match Cows::try_with_players(0, vec![1, 1], data.clone()) {
+            Err(OwnershipError::DuplicatePlayer(1)) => {}
+            result => panic!("Expected a duplicate player, got {:?}", result),
+        }
+        match Cows::try_with_players(0, vec![0], data) {
+            Err(OwnershipError::DuplicatePlayer(0)) => {}
+            result => panic!("Expected a duplicate player, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn players_cannot_be_owned() {
+        let mut data = cow_data(&[Point(1, 1), Point(1, 4), Point(1, 6)]);
+        data[2].3 = vec![1];
+        match Cows::try_with_players(0, vec![1], data.clone()) {
+            Err(OwnershipError::OwnedPlayer(1)) => {}
+            result => panic!("Expected an owned player, got {:?}", result),
+        }
+        data[2].3 = vec![0];
+        match Cows::try_new(0, data) {
+            Err(OwnershipError::OwnedPlayer(0)) => {}
+            result => panic!("Expected an owned player, got {:?}", result),
+        }
+    }
+
+    /// The player, a cow owning the cow below it, and a free cow.
+    fn family() -> Cows {
+        let mut data = cow_data(&[Point(0, 0), Point(1, 1), Point(1, 2), Point(5, 5)]);
+        data[1].3 = vec![2];
+        Cows::new(0, data)
+    }
+
+    fn free_cows(cows: &Cows) -> Vec<Point<i32>> {
+        let mut positions: Vec<_> =
+            cows.parents.iter().map(|index| cows.get_cow(*index).position).collect();
+        positions.sort_by_key(|position| (position.x(), position.y()));
+        positions
+    }
+
+    fn new_cow(position: Point<i32>) -> Cow {
+        Cow::new(position, Direction::Right, vec![], CowSprite::Grey)
+    }
+
+    #[test]
+    fn added_cows_join_their_owner() {
+        let mut cows = family();
+        let owned = cows.add_cow(new_cow(Point(6, 6)), Some(CowIndex(2)));
+        assert_eq!(owned, CowIndex(4));
+        assert_eq!(cows.ownership_chain(owned), vec![CowIndex(4), CowIndex(2), CowIndex(1)]);
+
+        let free = cows.add_cow(new_cow(Point(7, 7)), None);
+        assert_eq!(cows.parent_of(free), None);
+        assert_eq!(free_cows(&cows), vec![Point(1, 1), Point(5, 5), Point(7, 7)]);
+        assert!(cows.validate().is_ok());
+    }
+
+    #[test]
+    fn removed_owners_let_their_cows_go() {
+        let mut cows = family();
+        let removed = cows.remove_cow(CowIndex(1)).unwrap();
+        assert_eq!(removed.position, Point(1, 1));
+
+        assert_eq!(cows.positions(), vec![Point(0, 0), Point(1, 2), Point(5, 5)]);
+        assert_eq!(cows.parent_of(CowIndex(1)), None);
+        assert_eq!(free_cows(&cows), vec![Point(1, 2), Point(5, 5)]);
+        assert!(cows.validate().is_ok());
+    }
+
+    #[test]
+    fn indices_after_a_removed_cow_shift_down() {
+        let mut cows = family();
+        let owned = cows.add_cow(new_cow(Point(6, 6)), Some(CowIndex(3)));
+        cows.remove_cow(CowIndex(2)).unwrap();
+
+        assert_eq!(cows.positions(), vec![Point(0, 0), Point(1, 1), Point(5, 5), Point(6, 6)]);
+        assert_eq!(owned, CowIndex(4));
+        assert_eq!(cows.parent_of(CowIndex(3)), Some(CowIndex(2)));
+        assert!(cows.get_cow(CowIndex(1)).children.is_empty());
+        assert_eq!(free_cows(&cows), vec![Point(1, 1), Point(5, 5)]);
+        assert!(cows.validate().is_ok());
+    }
+
+    #[test]
+    fn players_and_missing_cows_cannot_be_removed() {
+        let data = cow_data(&[Point(0, 0), Point(1, 1), Point(2, 2)]);
+        let mut cows = Cows::try_with_players(0, vec![2], data).unwrap();
+        cows.remove_cow(CowIndex(1)).unwrap();
+        assert_eq!(cows.other_players, vec![CowIndex(1)]);
+
+        match cows.remove_cow(CowIndex(1)) {
+            Err(OwnershipError::IsPlayer(1)) => {}
+            result => panic!("Expected a player, got {:?}", result.map(|cow| cow.position)),
+        }
+        match cows.remove_cow(CowIndex(2)) {
+            Err(OwnershipError::OutOfRange(2)) => {}
+            result => panic!("Expected no cow, got {:?}", result.map(|cow| cow.position)),
+        }
+    }
+
+    fn walk_bounce(board: &mut Board, cow: &mut Cow) -> bool {
+        let cows = [cow.position];
+        let limits = PushLimits { dimensions: DIMENSIONS, cows: &cows };
+        cow.walk_bounce(board, &limits)
+    }
+
+    #[test]
+    fn walking_into_a_wall_bounces() {
+        let mut board = empty_board();
+        board.set_ground_cell(Point(4, 3), GroundCell::Wall(Surroundings::new()));
+        let mut cow = new_cow(Point(3, 3));
+
+        assert!(walk_bounce(&mut board, &mut cow));
+        assert_eq!(cow.position, Point(2, 3));
+        assert_eq!(cow.direction, Direction::Left);
+    }
+
+    #[test]
+    fn walking_into_open_space_does_not_bounce() {
+        let mut board = empty_board();
+        let mut cow = new_cow(Point(3, 3));
+
+        assert!(!walk_bounce(&mut board, &mut cow));
+        assert_eq!(cow.position, Point(4, 3));
+        assert_eq!(cow.direction, Direction::Right);
+    }
+}
@@ -1,15 +1,40 @@
+use std::collections::VecDeque;
+
 use crate::component::{NextScene, Object};
+use crate::direction::Direction;
 use crate::point::Point;
+use crate::sprite_layout::SpriteLayout;
 use crate::{component, js_ffi, util, Assets, Context2D, SpriteSheet};
 
-use super::cell::{cell_cursor, CellPalette, CellType};
-use super::{Level, LevelState, StateStack, SuccessState};
+use super::cell::{cell_cursor, CellGraphic, CellPalette, CellType, Signals};
+use super::{board, KeyInput, KeyRepeat, KeyboardCommand, Level, LevelState, StateStack, SuccessState};
 
 #[derive(Debug, Clone)]
 pub struct CowLevel {
     states: StateStack<LevelState>,
     animation_time: f64,
     palette: CellPalette<CellType>,
+    // the index into the state history being replayed, if any.
+    replay: Option<usize>,
+    signals: Signals,
+    // milliseconds spent playing since the level was entered.
+    time_spent: f64,
+    // the grid index under the mouse, when editing.
+    hovered: Option<Point<i32>>,
+    // clicks flood fill the ground rather than set a single cell.
+    fill_mode: bool,
+    // in debug builds, whether clicks edit the board rather than moving the
+    // player.
+    edit_mode: bool,
+    shows_hint: bool,
+    // when editing, whether lines are drawn between the cells.
+    shows_grid: bool,
+    // multiplies `dt` when advancing `animation_time`.
+    animation_speed: f64,
+    key_repeat: KeyRepeat,
+    // commands given while a move was animating, applied in order once it
+    // finishes.
+    queued_commands: VecDeque<KeyboardCommand>,
 }
 
 impl CowLevel {
@@ -17,6 +42,12 @@ impl CowLevel {
     pub const LEVEL_HEIGHT: i32 = 16;
     pub const ANIMATION_TIME: f64 = 100.0;
     pub const COOLDOWN_TIME: f64 = 50.0;
+    const HINT_ALPHA: f64 = 0.6;
+    const MAX_QUEUED_COMMANDS: usize = 2;
+    const CONTINUE_KEYS: [&'static str; 2] = ["Space", "Enter"];
+    // kept clear of the palette's keys, so that picking a colour never
+    // edits the board as well.
+    const EDIT_KEYS: [&'static str; 5] = ["KeyM", "KeyT", "Delete", "KeyO", "Backspace"];
     pub const BOUNDING_RECT: component::Rect = component::Rect {
         top_left: Point(0, 0),
         dimensions: Point(
@@ -25,17 +56,281 @@ impl CowLevel {
         ),
     };
     fn from_state(state: LevelState) -> Self {
+        let palette = CellPalette::new(state.palette());
         CowLevel {
             states: StateStack::new(state),
             animation_time: 0.0,
-            palette: CellPalette::new(CellType::full_palette()),
+            palette,
+            replay: None,
+            signals: Signals::default(),
+            time_spent: 0.0,
+            hovered: None,
+            fill_mode: false,
+            edit_mode: true,
+            shows_hint: false,
+            shows_grid: false,
+            animation_speed: 1.0,
+            key_repeat: KeyRepeat::default(),
+            queued_commands: VecDeque::new(),
         }
     }
     pub fn from_str(string: &'static str) -> Self {
-        CowLevel::from_state(ron::de::from_str::<LevelState>(string).unwrap())
+        CowLevel::try_from_str(string).unwrap()
+    }
+    /// Parses a level from a user supplied string, such as a shared puzzle.
+    /// The string may be RON or a level code.
+    pub fn try_from_str(string: &str) -> Result<Self, String> {
+        // level codes never contain brackets, and RON levels always do.
+        let state = if string.contains('(') {
+            ron::de::from_str::<LevelState>(string)
+                .map_err(|error| format!("Could not read level: {}", error))?
+        } else {
+            LevelState::from_code(string)
+                .map_err(|error| format!("Could not read level code: {}", error))?
+        };
+        state
+            .validate_cows()
+            .map_err(|error| format!("Invalid level: {}", error))?;
+
+        let dimensions = state.dimensions;
+        LevelState::check_dimensions(dimensions)?;
+        if !state.is_inside(dimensions) {
+            return Err(format!(
+                "Level does not fit inside a {}x{} board",
+                dimensions.x(),
+                dimensions.y()
+            ));
+        }
+
+        if state.palette().is_empty() {
+            return Err("Level does not allow any cell types".to_owned());
+        }
+
+        Ok(CowLevel::from_state(state))
+    }
+    /// Shortens an exported RON level into a level code for sharing.
+    pub fn level_code(ron: &str) -> Result<String, String> {
+        let state = ron::de::from_str::<LevelState>(ron)
+            .map_err(|error| format!("Could not read level: {}", error))?;
+        Ok(state.to_code())
+    }
+    /// The commands the player has made since the start, leaving out any that
+    /// have been undone.
+    pub fn command_history(&self) -> Vec<KeyboardCommand> {
+        self.states
+            .history()
+            .iter()
+            .filter_map(|state| state.last_command)
+            .collect()
+    }
+    /// The move list of the current solve, one letter per command.
+    pub fn solution_string(&self) -> String {
+        self.command_history()
+            .into_iter()
+            .map(KeyboardCommand::to_char)
+            .collect()
+    }
+    /// The next move of the author's solution, if the level has one and the
+    /// player has kept to it so far.
+    pub fn hint(&self) -> Option<KeyboardCommand> {
+        let solution = self.states.current_state().solution.as_ref()?;
+        let history = self.command_history();
+        if !solution.starts_with(&history) {
+            return None;
+        }
+        solution.get(history.len()).copied()
+    }
+    /// Shows or hides the hint. Levels without a solution have no hint to
+    /// show, so the key does nothing there.
+    fn toggle_hint(&mut self) {
+        if self.states.current_state().solution.is_some() {
+            self.shows_hint = !self.shows_hint;
+        }
+    }
+    /// Draws an arrow next to the player pointing the way of the hint.
+    fn draw_hint(&self, context: &Context2D, assets: &Assets) {
+        let direction = match self.hint() {
+            Some(KeyboardCommand::Direction(direction)) => direction,
+            Some(KeyboardCommand::Space) | None => return,
+        };
+        let mut target = self.states.current_state().player_position();
+        target.increment_2d(direction);
+
+        let arrow = SpriteLayout::current().arrow;
+        let sprite = Point(arrow.x() + direction as u8, arrow.y());
+        let Point(x, y) = target * CellGraphic::CELL_SIZE;
+
+        util::with_saved_context(context, || {
+            context.set_global_alpha(Self::HINT_ALPHA);
+            assets.blocks.draw(context, sprite, Point(f64::from(x), f64::from(y)));
+        });
+    }
+    fn is_editing(&self) -> bool {
+        crate::DEBUG && self.edit_mode
+    }
+    // tapping the player waits, and tapping a cell next to it walks there.
+    // nothing happens once the level is over or while replaying.
+    fn command_towards(&self, index: Point<i32>) -> Option<KeyboardCommand> {
+        let state = self.states.current_state();
+        if self.replay.is_some() || !matches!(state.success_state(), SuccessState::Running) {
+            return None;
+        }
+        let player = state.player_position();
+        match player.manhattan_distance(index) {
+            0 => Some(KeyboardCommand::Space),
+            1 => Direction::all()
+                .iter()
+                .copied()
+                .find(|direction| {
+                    let mut adjacent = player;
+                    adjacent.increment_2d(*direction);
+                    adjacent == index
+                })
+                .map(KeyboardCommand::Direction),
+            _ => None,
+        }
+    }
+    /// Mirrors (M), rotates (T) or clears (Delete) the board, or adds (O) or
+    /// removes (Backspace) the cow under the mouse, as one undoable edit.
+    fn edit_board(&mut self, code: &str) {
+        let mut state = self.states.current_state().clone();
+        state.last_command = None;
+        match (code, self.hovered) {
+            ("KeyM", _) => state.mirror_horizontal(),
+            ("KeyT", _) => state.rotate_180(),
+            ("Delete", _) => state.board.clear(),
+            ("KeyO", Some(index)) if state.add_cow_at(index) => {}
+            ("Backspace", Some(index)) if state.remove_cow_at(index) => {}
+            _ => return,
+        }
+        self.states.push_state(state);
+    }
+    /// Switches between editing and playing. Play starts afresh from the
+    /// board as it is now.
+    fn toggle_edit_mode(&mut self) {
+        self.edit_mode = !self.edit_mode;
+        self.hovered = None;
+        if !self.edit_mode {
+            let mut state = self.states.current_state().clone();
+            state.last_command = None;
+            self.states = StateStack::new(state);
+            self.animation_time = 0.0;
+            self.time_spent = 0.0;
+            self.queued_commands.clear();
+        }
+    }
+    fn draw_solved(&self, context: &Context2D) {
+        let rect = CowLevel::BOUNDING_RECT;
+        let x = rect.top_left.x() + rect.dimensions.x() / 2;
+        let y = rect.top_left.y() + rect.dimensions.y() - SpriteSheet::STANDARD_HEIGHT;
+
+        util::with_saved_context(context, || {
+            context.set_font("10px KongText");
+            context.set_text_align("center");
+            util::draw_text_outlined(
+                context,
+                "Solved! SPACE to continue, P to replay",
+                x.into(),
+                y.into(),
+                "white",
+                "black",
+            );
+        });
+    }
+    fn draw_mode(&self, context: &Context2D) {
+        let rect = CowLevel::BOUNDING_RECT;
+        let x = rect.top_left.x() + rect.dimensions.x() - SpriteSheet::STANDARD_WIDTH / 2;
+        let y = rect.top_left.y() + SpriteSheet::STANDARD_HEIGHT;
+        let text = if self.edit_mode { "edit" } else { "play" };
+
+        util::with_saved_context(context, || {
+            context.set_font("10px KongText");
+            context.set_text_align("right");
+            context.set_fill_style_str("black");
+            context.fill_text(text, x.into(), y.into()).unwrap();
+        });
+    }
+    /// Names the cow under the mouse, and each cow that owns it in turn.
+    fn draw_owners(&self, context: &Context2D, grid_index: Point<i32>) {
+        let chain = self.states.current_state().ownership_chain_at(grid_index);
+        let (cow, owners) = match chain.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        let mut text = format!("COW {}", cow);
+        if !owners.is_empty() {
+            let owners: Vec<String> = owners.iter().map(ToString::to_string).collect();
+            text += &format!(", owned by {}", owners.join(" < "));
+        }
+        let rect = CowLevel::BOUNDING_RECT;
+        let x = rect.top_left.x() + rect.dimensions.x() - SpriteSheet::STANDARD_WIDTH / 2;
+        let y = rect.top_left.y() + SpriteSheet::STANDARD_HEIGHT * 2;
+
+        util::with_saved_context(context, || {
+            context.set_font("10px KongText");
+            context.set_text_align("right");
+            context.set_fill_style_str("black");
+            context.fill_text(&text, x.into(), y.into()).unwrap();
+        });
+    }
+    fn push_command(&mut self, command: KeyboardCommand) {
+        let mut current_state = self.states.current_state().clone();
+        current_state.command(command.into());
+        current_state.last_command = Some(command);
+
+        self.states.push_state(current_state);
+
+        self.animation_time = 0.0;
+    }
+    // fresh presses wait for the current move to finish, but held keys only
+    // repeat between moves, so holding a key never runs ahead of the cow.
+    fn handle_input(&mut self, input: Option<KeyInput>) {
+        match input {
+            Some(KeyInput::Pressed(command))
+                if self.queued_commands.len() < Self::MAX_QUEUED_COMMANDS =>
+            {
+                self.queued_commands.push_back(command);
+            }
+            Some(KeyInput::Held(command))
+                if self.is_finished_animating() && self.queued_commands.is_empty() =>
+            {
+                self.queued_commands.push_back(command);
+            }
+            _ => {}
+        }
+        if self.is_finished_animating() {
+            if let Some(command) = self.queued_commands.pop_front() {
+                self.push_command(command);
+            }
+        }
     }
     fn purge_states(&mut self) {
         self.states.purge_states();
+        self.queued_commands.clear();
+    }
+    /// Plays the solution back from the first state. Does nothing unless the
+    /// level has been solved.
+    pub fn enter_replay(&mut self) {
+        if let SuccessState::Succeeded = self.states.current_state().success_state() {
+            self.replay = Some(0);
+            self.animation_time = 0.0;
+        }
+    }
+    fn step_replay(&mut self, index: usize, keyboard_state: &js_ffi::KeyboardState) {
+        if keyboard_state.is_pressed("KeyP") || keyboard_state.is_pressed("Escape") {
+            self.replay = None;
+            return;
+        }
+        self.advance_replay(index);
+    }
+    // moves on to the next state once the last move has been shown, and
+    // stays on the final state.
+    fn advance_replay(&mut self, index: usize) {
+        let is_last = index + 1 >= self.states.history().len();
+        if self.is_finished_animating() && !is_last {
+            self.replay = Some(index + 1);
+            self.animation_time = 0.0;
+        }
     }
 }
 impl Level for CowLevel {
@@ -46,81 +341,432 @@ impl Level for CowLevel {
 impl component::Component for CowLevel {
     type DrawArgs = ();
     fn bounding_rect(&self) -> component::Rect {
-        Self::BOUNDING_RECT
+        self.states.current_state().bounding_rect()
     }
     fn click(&mut self, point: Point<i32>) -> bool {
-        if !crate::DEBUG || !self.in_boundary(point) {
+        if !self.in_boundary(point) {
             return false;
         }
+        if !self.is_editing() {
+            let command = self.command_towards(board::get_grid_index(point));
+            self.handle_input(command.map(KeyInput::Pressed));
+            return command.is_some();
+        }
         if self.palette.click(point) {
             return true;
         }
 
         let value = self.palette.value();
-        self.states
-            .current_state_mut()
-            .set_cell_at_point(point, value);
+        if !self.states.current_state().is_allowed(value.0) {
+            return false;
+        }
+        let state = self.states.current_state_mut();
+        if self.fill_mode {
+            state.fill_at_point(point, value);
+        } else {
+            state.set_cell_at_point(point, value, self.palette.is_blanks_only());
+        }
 
         true
     }
+    fn apply_solution(&mut self, solution: &str) -> Result<(), String> {
+        let commands = solution
+            .chars()
+            .filter(|character| !character.is_whitespace())
+            .map(|character| {
+                KeyboardCommand::from_char(character)
+                    .ok_or_else(|| format!("'{}' is not a move", character))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.purge_states();
+        self.replay = None;
+        for command in commands {
+            if !self.states.current_state().success_state().is_running() {
+                break;
+            }
+            self.push_command(command);
+        }
+        Ok(())
+    }
+    fn success_state(&self) -> Option<SuccessState> {
+        Some(self.states.current_state().success_state())
+    }
+    fn undo_keys(&self) -> &'static [&'static str] {
+        component::undo_key_codes(self.is_editing())
+    }
+    fn hover(&mut self, point: Point<i32>) {
+        self.hovered = if !self.is_editing() || !self.in_boundary(point) || self.palette.in_boundary(point) {
+            None
+        } else {
+            Some(board::get_grid_index(point))
+        };
+    }
     fn draw(&self, context: &Context2D, assets: &Assets, _args: ()) {
         let anim_progress = util::clamp(self.animation_time / CowLevel::ANIMATION_TIME, 0.0, 1.0);
 
         self.fill_bg(context, super::BG_FILL);
 
+        if let Some(index) = self.replay {
+            let history = self.states.history();
+            let old_state = history[index.saturating_sub(1)];
+            history[index].draw(context, assets, old_state, anim_progress);
+            return;
+        }
+
         self.states
             .current_state()
             .draw(context, assets, self.states.last_state(), anim_progress);
 
-        if crate::DEBUG {
+        if self.shows_hint && self.is_finished_animating() {
+            self.draw_hint(context, assets);
+        }
+        let is_solved = self.states.current_state().success_state() == SuccessState::Succeeded;
+        if is_solved && self.is_finished_animating() {
+            self.draw_solved(context);
+        }
+
+        if self.is_editing() && self.shows_grid {
+            self.states.current_state().draw_grid(context);
+        }
+        if self.is_editing() {
+            if let Some(grid_index) = self.hovered {
+                self.palette.draw_preview(context, assets, grid_index);
+                self.draw_owners(context, grid_index);
+            }
             self.palette.fill_bg(context, cell_cursor::BG_COLOUR);
             self.palette.draw(context, assets, ())
         }
+        if crate::DEBUG {
+            self.draw_mode(context);
+        }
     }
     fn called_into(&mut self, _object: Object) {
         self.purge_states();
+        self.time_spent = 0.0;
+    }
+    fn set_signals(&mut self, signals: Signals) {
+        self.signals = signals;
+    }
+    #[cfg(debug_assertions)]
+    fn check_invariants(&self) {
+        self.bounding_rect().check_invariants();
+        self.states.check_invariants();
+        self.states.current_state().check_invariants();
+    }
+    fn on_resize(&mut self, dimensions: Point<i32>) {
+        self.palette.on_resize(dimensions)
+    }
+    fn set_animation_speed(&mut self, speed: f64) {
+        self.animation_speed = speed;
     }
     fn step(&mut self, dt: f64, keyboard_state: &js_ffi::KeyboardState) -> NextScene {
-        self.animation_time += dt;
+        self.animation_time += dt * self.animation_speed;
+        // undo and restart can bring back states from before a signal changed.
+        self.states.current_state_mut().set_signals(self.signals);
+
+        if let Some(index) = self.replay {
+            self.step_replay(index, keyboard_state);
+            return NextScene::Continue;
+        }
 
         // undo and redo should still be possible after failure
         if self.keyboard_event(keyboard_state, &["KeyR", "Escape"]) {
             self.purge_states();
         }
 
-        if self.keyboard_event(keyboard_state, &["KeyU", "KeyZ", "Backslash"]) {
+        let is_palette_key = self.is_editing() && self.palette.handle_keyboard(keyboard_state);
+        if !is_palette_key && self.keyboard_event(keyboard_state, self.undo_keys()) {
             self.states.pop_state();
             self.animation_time = 0.0;
+            self.queued_commands.clear();
             return NextScene::Continue;
         }
 
         if keyboard_state.is_pressed("KeyL") {
             self.states.current_state().log_level();
         }
+        if keyboard_state.is_pressed("KeyH") {
+            self.toggle_hint();
+        }
+        if crate::DEBUG && keyboard_state.is_pressed("KeyE") {
+            self.toggle_edit_mode();
+        }
+        if self.is_editing() && keyboard_state.is_pressed("KeyI") {
+            self.shows_grid = !self.shows_grid;
+        }
+        if self.is_editing() && keyboard_state.is_pressed("KeyF") {
+            self.fill_mode = !self.fill_mode;
+        }
+        if self.is_editing() {
+            for code in Self::EDIT_KEYS.iter() {
+                if keyboard_state.is_pressed(code) {
+                    self.edit_board(code);
+                }
+            }
+        }
 
         // block character movement on success or failure.
         match self.states.current_state().success_state() {
             SuccessState::Succeeded => {
-                if !self.is_finished_animating() {
+                if keyboard_state.is_pressed("KeyP") {
+                    self.enter_replay();
+                    return NextScene::Continue;
+                }
+                // the level stays open until the player moves on, so that
+                // there is time to watch the replay first.
+                let is_confirmed = Self::CONTINUE_KEYS
+                    .iter()
+                    .any(|code| keyboard_state.is_pressed(code));
+                if !self.is_finished_animating() || !is_confirmed {
                     return NextScene::Continue;
                 }
-                return NextScene::Return(Object::Bool(true));
+                let moves = self.states.history().len() - 1;
+                let result = Object::completion(true, moves as i64)
+                    .with("time", Object::Float(self.time_spent / 1000.0))
+                    .with("commands", Object::Str(self.solution_string()));
+                return NextScene::Return(result);
             }
             SuccessState::Failed => {
                 return NextScene::Continue;
             }
-            SuccessState::Running => {}
+            SuccessState::Running => self.time_spent += dt,
         };
 
-        if let Some(command) = self.get_keyboard_command(keyboard_state) {
-            let mut current_state = self.states.current_state().clone();
-            current_state.command(command.into());
+        let input = self.key_repeat.step(dt, keyboard_state);
+        self.handle_input(input);
 
-            self.states.push_state(current_state);
+        NextScene::Continue
+    }
+}
 
-            self.animation_time = 0.0;
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::Component;
 
-        NextScene::Continue
+    fn level_with_dimensions(dimensions: Point<i32>) -> String {
+        let mut state = LevelState::new();
+        state.dimensions = dimensions;
+        ron::ser::to_string(&state).unwrap()
+    }
+
+    fn blank_level() -> CowLevel {
+        let mut level = CowLevel::from_str(include_str!("../level_data/blank_level.ron"));
+        finish_animating(&mut level);
+        level
+    }
+
+    fn finish_animating(level: &mut CowLevel) {
+        level.animation_time = CowLevel::ANIMATION_TIME + CowLevel::COOLDOWN_TIME + 1.0;
+    }
+
+    const RIGHT: KeyboardCommand = KeyboardCommand::Direction(Direction::Right);
+
+    #[test]
+    fn following_the_hints_solves_shipped_levels() {
+        let levels = [
+            include_str!("../level_data/level_0_0.ron"),
+            include_str!("../level_data/level_0_2.ron"),
+            include_str!("../level_data/level_0_3.ron"),
+        ];
+        for string in levels.iter() {
+            let mut level = CowLevel::from_str(string);
+            while let Some(command) = level.hint() {
+                level.push_command(command);
+            }
+            let state = level.states.current_state().success_state();
+            assert!(matches!(state, SuccessState::Succeeded));
+        }
+    }
+
+    #[test]
+    fn hints_need_a_solution() {
+        let mut level = blank_level();
+        level.toggle_hint();
+        assert!(!level.shows_hint);
+
+        let mut level = CowLevel::from_str(include_str!("../level_data/level_0_0.ron"));
+        level.toggle_hint();
+        assert!(level.shows_hint);
+    }
+
+    #[test]
+    fn press_during_animation_applies_on_the_next_ready_frame() {
+        let mut level = blank_level();
+        level.handle_input(Some(KeyInput::Pressed(RIGHT)));
+        assert_eq!(level.command_history(), vec![RIGHT]);
+
+        level.handle_input(Some(KeyInput::Pressed(RIGHT)));
+        assert_eq!(level.command_history(), vec![RIGHT]);
+        level.handle_input(None);
+        assert_eq!(level.command_history(), vec![RIGHT]);
+
+        finish_animating(&mut level);
+        level.handle_input(None);
+        assert_eq!(level.command_history(), vec![RIGHT, RIGHT]);
+    }
+
+    #[test]
+    fn held_keys_do_not_queue_during_animation() {
+        let mut level = blank_level();
+        level.handle_input(Some(KeyInput::Held(RIGHT)));
+        assert_eq!(level.command_history(), vec![RIGHT]);
+
+        level.handle_input(Some(KeyInput::Held(RIGHT)));
+        level.handle_input(Some(KeyInput::Held(RIGHT)));
+        finish_animating(&mut level);
+        level.handle_input(None);
+        assert_eq!(level.command_history(), vec![RIGHT]);
+    }
+
+    #[test]
+    fn queued_presses_are_bounded() {
+        let mut level = blank_level();
+        level.handle_input(Some(KeyInput::Pressed(RIGHT)));
+        for _ in 0..5 {
+            level.handle_input(Some(KeyInput::Pressed(RIGHT)));
+        }
+        assert_eq!(level.queued_commands.len(), CowLevel::MAX_QUEUED_COMMANDS);
+    }
+
+    #[test]
+    fn replay_visits_each_state_in_order() {
+        use crate::level::cell::{OverlayCell, Surroundings};
+
+        let mut state = LevelState::new();
+        let mut target = state.player_position();
+        target.increment_2d(Direction::Right);
+        state.board.set_overlay_cell(target, OverlayCell::Success(Surroundings::new()));
+        for cow in state.cows.positions() {
+            if cow != state.player_position() {
+                state.board.set_overlay_cell(cow, OverlayCell::Success(Surroundings::new()));
+            }
+        }
+        let mut level = CowLevel::from_state(state);
+        level.enter_replay();
+        assert_eq!(level.replay, None);
+
+        level.push_command(RIGHT);
+        level.enter_replay();
+        assert_eq!(level.replay, Some(0));
+
+        let mut visited = vec![0];
+        for _ in 0..3 {
+            finish_animating(&mut level);
+            level.advance_replay(level.replay.unwrap());
+            visited.push(level.replay.unwrap());
+        }
+        assert_eq!(visited, vec![0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn z_only_undoes_while_playing() {
+        let mut level = blank_level();
+        level.edit_mode = false;
+        assert!(level.undo_keys().contains(&"KeyZ"));
+
+        level.edit_mode = true;
+        assert_eq!(level.undo_keys().contains(&"KeyZ"), !crate::DEBUG);
+        assert!(level.palette.handle_key("KeyZ"));
+    }
+
+    fn saved_board(level: &CowLevel) -> String {
+        ron::ser::to_string(&level.states.current_state().board).unwrap()
+    }
+
+    #[test]
+    fn cycling_the_colour_leaves_the_board_intact() {
+        let mut level = blank_level();
+        level.edit_mode = true;
+        let before = saved_board(&level);
+        for code in CowLevel::EDIT_KEYS.iter() {
+            assert!(!level.palette.handle_key(code));
+        }
+
+        assert!(level.palette.handle_key("KeyC"));
+        level.edit_board("KeyC");
+        assert_eq!(saved_board(&level), before);
+        assert_eq!(level.states.history().len(), 1);
+    }
+
+    #[test]
+    fn delete_clears_the_board_as_one_edit() {
+        use crate::level::cell::{GroundCell, Surroundings};
+
+        let mut level = blank_level();
+        let wall = GroundCell::Wall(Surroundings::new());
+        level.states.current_state_mut().board.set_ground_cell(Point(3, 3), wall);
+        let edited = saved_board(&level);
+
+        level.edit_board("Delete");
+        assert_ne!(saved_board(&level), edited);
+        assert_eq!(level.states.history().len(), 2);
+        level.states.pop_state();
+        assert_eq!(saved_board(&level), edited);
+    }
+
+    #[test]
+    fn cows_under_the_mouse_are_added_and_removed_as_edits() {
+        let mut level = blank_level();
+        let player = level.states.current_state().player_position();
+        let spot = Point(player.x() + 2, player.y());
+        let cows = |level: &CowLevel| level.states.current_state().cows.positions();
+        let before = cows(&level);
+
+        level.edit_board("KeyO");
+        assert_eq!(level.states.history().len(), 1);
+
+        level.hovered = Some(spot);
+        level.edit_board("KeyO");
+        level.edit_board("KeyO");
+        let mut added = before.clone();
+        added.push(spot);
+        assert_eq!(cows(&level), added);
+        assert_eq!(level.states.current_state().ownership_chain_at(spot), vec![before.len()]);
+        assert_eq!(level.states.history().len(), 2);
+
+        level.edit_board("Backspace");
+        assert_eq!(cows(&level), before);
+        level.hovered = Some(player);
+        level.edit_board("Backspace");
+        assert_eq!(cows(&level), before);
+        assert_eq!(level.states.history().len(), 3);
+    }
+
+    #[test]
+    fn tapping_next_to_the_player_walks_there() {
+        let level = blank_level();
+        let player = level.states.current_state().player_position();
+        let mut right = player;
+        right.increment_2d(Direction::Right);
+        let mut up = player;
+        up.increment_2d(Direction::Up);
+
+        assert_eq!(level.command_towards(player), Some(KeyboardCommand::Space));
+        assert_eq!(level.command_towards(right), Some(RIGHT));
+        assert_eq!(level.command_towards(up), Some(KeyboardCommand::Direction(Direction::Up)));
+        assert_eq!(level.command_towards(player + Point(1, 1)), None);
+        assert_eq!(level.command_towards(player + Point(2, 0)), None);
+    }
+
+    #[test]
+    fn rejects_boards_bigger_than_the_screen() {
+        let too_wide = level_with_dimensions(Point(CowLevel::LEVEL_WIDTH + 1, 4));
+        assert!(CowLevel::try_from_str(&too_wide).is_err());
+        let too_tall = level_with_dimensions(Point(4, CowLevel::LEVEL_HEIGHT + 1));
+        assert!(CowLevel::try_from_str(&too_tall).is_err());
+    }
+
+    #[test]
+    fn rejects_empty_boards() {
+        let empty = level_with_dimensions(Point(0, CowLevel::LEVEL_HEIGHT));
+        assert!(CowLevel::try_from_str(&empty).is_err());
+    }
+
+    #[test]
+    fn accepts_full_size_boards() {
+        let full = level_with_dimensions(LevelState::default_dimensions());
+        assert!(CowLevel::try_from_str(&full).is_ok());
     }
 }
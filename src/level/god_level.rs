@@ -1,16 +1,83 @@
-use crate::component::{NextScene, Object, Translation};
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::component::{Button, ConfirmDialog, IconSheet, NextScene, Object, Translation};
 use crate::point::Point;
-use crate::util;
+use crate::sprite_layout::SpriteLayout;
+use crate::{console_log, util};
 use crate::{component, Assets, Context2D, KeyboardState, SpriteSheet};
 
-use super::cell::{cell_cursor, CellGraphic, CellPalette, CellType, PaletteResult};
+use super::cell::{cell_cursor, CellPalette, CellType, PaletteResult, Signals};
 use super::cow_level::CowLevel;
 use super::{LevelState, SuccessState};
 
 mod test;
 
-use test::{MetaTestResult, TestResult};
-pub use test::{Test, TestTarget};
+use test::{MetaTestResult, TestResult, TestSummary};
+pub use test::{RandomTests, Test, TestTarget};
+
+/// A reason a god level's board can't run all of its tests.
+#[derive(Clone, Debug)]
+pub enum DesignError {
+    NotEnoughInputCells { have: usize, need: usize },
+    NotEnoughOutputCells { have: usize, need: usize },
+    ColourCycle,
+}
+impl DesignError {
+    fn message(&self) -> &'static str {
+        match self {
+            DesignError::NotEnoughInputCells { .. } => "Too few input cells",
+            DesignError::NotEnoughOutputCells { .. } => "Too few output cells",
+            DesignError::ColourCycle => "Converters loop",
+        }
+    }
+}
+impl std::fmt::Display for DesignError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            DesignError::NotEnoughInputCells { have, need } => {
+                write!(formatter, "There are {} input cells but a test needs {}", have, need)
+            }
+            DesignError::NotEnoughOutputCells { have, need } => {
+                write!(formatter, "There are {} output cells but a test needs {}", have, need)
+            }
+            DesignError::ColourCycle => {
+                write!(formatter, "Some converters point around a loop, so their blocks never land")
+            }
+        }
+    }
+}
+
+/// Everything needed to play a god level: the starting board, the tests and
+/// the brief.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GodLevelData {
+    initial_state: LevelState,
+    tests: Vec<Test>,
+    #[serde(default)]
+    description: String,
+}
+impl GodLevelData {
+    pub fn to_ron(&self) -> String {
+        ron::ser::to_string(self).unwrap()
+    }
+    pub fn from_ron(string: &str) -> Result<Self, String> {
+        let data = ron::de::from_str::<GodLevelData>(string)
+            .map_err(|error| format!("Could not read god level: {}", error))?;
+        data.initial_state
+            .validate_cows()
+            .map_err(|error| format!("Invalid level: {}", error))?;
+        if data.tests.is_empty() {
+            return Err("God level has no tests".to_owned());
+        }
+        Ok(data)
+    }
+    /// The brief shown before the level is played.
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+}
 
 pub struct GodLevel {
     name: &'static str,
@@ -20,10 +87,20 @@ pub struct GodLevel {
     speed: f64,
     tests: Vec<Test>,
     current_test: usize,
+    // the reports of the tests run so far, in order.
+    results: Vec<MetaTestResult>,
+    // the grid index under the mouse, while editing.
+    hovered: Option<Point<i32>>,
+    profile: String,
+    // while stopped, whether lines are drawn between the cells.
+    shows_grid: bool,
+    // asks before the saved board is wiped.
+    confirm_reset: Option<ConfirmDialog>,
 }
 impl GodLevel {
     const MIN_SPEED: f64 = 500.0;
     const MAX_SPEED_SCALE: f64 = 100.0;
+    const RESET_MESSAGE: &'static str = "Clear the board and\nforget the saved one?";
     pub fn new(name: &'static str, tests: Vec<Test>) -> Self {
         let palette = CellPalette::new(CellType::full_palette());
         GodLevel {
@@ -34,7 +111,118 @@ impl GodLevel {
             speed: 1.0,
             tests,
             current_test: 0,
+            results: Vec::new(),
+            hovered: None,
+            shows_grid: false,
+            profile: util::DEFAULT_PROFILE.to_owned(),
+            confirm_reset: None,
+        }
+    }
+    /// Adds generated tests after the hand-written ones.
+    pub fn with_random_tests(mut self, random: RandomTests) -> Self {
+        self.tests.extend(random.generate());
+        self
+    }
+    /// Starts a god level from a board and tests read together.
+    pub fn from_data(name: &'static str, data: GodLevelData) -> Self {
+        let mut level = GodLevel::new(name, data.tests);
+        level.initial_state = data.initial_state;
+        level
+    }
+    /// The board and tests, ready to be shared.
+    pub fn to_data(&self, description: &str) -> GodLevelData {
+        GodLevelData {
+            initial_state: self.initial_state.clone(),
+            tests: self.tests.clone(),
+            description: description.to_owned(),
+        }
+    }
+    /// Runs the tests against the current board without drawing, stopping at
+    /// the first failure like a watched run does.
+    fn simulate_tests(&self) -> Vec<MetaTestResult> {
+        let mut results = Vec::new();
+        for test in self.tests.iter() {
+            let result = simulate(&self.initial_state, test);
+            let meta_result = MetaTestResult::new(test.clone(), result);
+            let is_passed = meta_result.is_passed();
+            results.push(meta_result);
+            if !is_passed {
+                break;
+            }
+        }
+        results
+    }
+    /// The length of the longest input of any test.
+    fn max_input_len(&self) -> usize {
+        self.tests.iter().map(|test| test.input().len()).max().unwrap_or(0)
+    }
+    /// The length of the longest output of any test.
+    fn max_output_len(&self) -> usize {
+        self.tests.iter().map(Test::required_output_len).max().unwrap_or(0)
+    }
+    /// Checks the board has room for the input and output of every test, and
+    /// that no converters loop. Multipliers, sequencers and recorders add
+    /// outputs of their own, so boards with any of them may need fewer output
+    /// cells.
+    pub fn validate_design(&self) -> Result<(), DesignError> {
+        if self.initial_state.has_colour_cycle() {
+            return Err(DesignError::ColourCycle);
+        }
+        let have = self.initial_state.input_space();
+        let need = self.max_input_len();
+        if have < need {
+            return Err(DesignError::NotEnoughInputCells { have, need });
+        }
+        if self.initial_state.has_extra_outputs() {
+            return Ok(());
         }
+        let have = self.initial_state.output_space();
+        let need = self.max_output_len();
+        if have < need {
+            return Err(DesignError::NotEnoughOutputCells { have, need });
+        }
+        Ok(())
+    }
+    /// Writes how many input and output cells are placed against how many
+    /// the tests need.
+    fn draw_cell_space(&self, context: &Context2D) {
+        let input_text = format!(
+            "input: {}/{}",
+            self.initial_state.input_space(),
+            self.max_input_len()
+        );
+        let output_text = format!(
+            "output: {}/{}",
+            self.initial_state.output_space(),
+            self.max_output_len()
+        );
+        let panel = component::Component::bounding_rect(&self.control_panel);
+        let x = panel.top_left.x() + panel.dimensions.x() + SpriteSheet::STANDARD_WIDTH / 2;
+        let y = panel.top_left.y() + SpriteSheet::STANDARD_HEIGHT;
+
+        util::with_saved_context(context, || {
+            context.set_font("10px KongText");
+            context.set_fill_style_str("black");
+            context.fill_text(&input_text, x.into(), y.into()).unwrap();
+            context
+                .fill_text(&output_text, x.into(), (y + SpriteSheet::STANDARD_HEIGHT).into())
+                .unwrap();
+        });
+    }
+    fn speed_text(&self) -> String {
+        format!("x{}", self.speed.round())
+    }
+    fn draw_speed(&self, context: &Context2D) {
+        let rect = CowLevel::BOUNDING_RECT;
+        let x = rect.top_left.x() + rect.dimensions.x() - SpriteSheet::STANDARD_WIDTH / 2;
+        let y = rect.top_left.y() + SpriteSheet::STANDARD_HEIGHT;
+
+        util::with_saved_context(context, || {
+            context.set_font("10px KongText");
+            context.set_text_align("right");
+            context.set_fill_style_str("black");
+            context.fill_text(&self.speed_text(), x.into(), y.into()).unwrap();
+        });
     }
     fn is_success(&self) -> bool {
         self.current_test >= self.tests.len()
@@ -54,19 +242,93 @@ impl GodLevel {
     fn reset_tests(&mut self) {
         self.running_state.stop();
         self.current_test = 0;
+        self.results.clear();
+    }
+    /// Keeps the report on screen, if it is for a test that hasn't been
+    /// recorded yet.
+    fn record_report(&mut self) {
+        if let GodLevelStatus::Report(result) = &self.running_state {
+            if self.results.len() < self.current_test {
+                self.results.push(result.clone());
+            }
+        }
+    }
+    /// Moves on from a report: to the next test if it passed, or to the
+    /// summary if it failed.
+    fn close_report(&mut self) {
+        self.record_report();
+        let is_passed = match &self.running_state {
+            GodLevelStatus::Report(result) => result.is_passed(),
+            _ => return,
+        };
+        if is_passed || self.results.is_empty() {
+            self.running_state.close_report(is_passed);
+        } else {
+            self.show_summary();
+        }
+    }
+    fn show_summary(&mut self) {
+        let summary = TestSummary::new(self.results.clone(), self.tests.len());
+        self.running_state = GodLevelStatus::Summary(summary);
+    }
+    fn close_summary(&mut self) {
+        self.running_state = match &self.running_state {
+            GodLevelStatus::Summary(summary) if summary.is_passed() => GodLevelStatus::Complete,
+            GodLevelStatus::Summary(_) => GodLevelStatus::Stopped,
+            _ => return,
+        };
+    }
+    /// Records the latest report and moves on from a finished test: to the
+    /// next one, or to the summary once every test has passed. Reports and
+    /// summaries stay until `is_confirmed`.
+    fn follow_status(&mut self, is_confirmed: bool) -> NextScene {
+        self.record_report();
+        match self.running_state {
+            GodLevelStatus::Report(_) if is_confirmed => self.close_report(),
+            GodLevelStatus::Summary(_) if is_confirmed => self.close_summary(),
+            GodLevelStatus::Succeeded if self.is_success() => self.show_summary(),
+            GodLevelStatus::Succeeded => self.next_test(),
+            GodLevelStatus::Complete => return NextScene::Return(Object::Bool(true)),
+            _ => {}
+        }
+        NextScene::Continue
+    }
+    /// Puts back the blank board, without touching the save.
+    fn clear_state(&mut self) {
+        self.initial_state = LevelState::new();
+        self.running_state = GodLevelStatus::new();
+        self.current_test = 0;
+        self.results.clear();
+    }
+    /// Forgets the saved board and starts again from a blank one.
+    fn reset_save(&mut self) {
+        if util::get_storage().remove_item(&self.storage_key()).is_err() {
+            crate::console_error!("Could not access local storage");
+        }
+        self.clear_state();
+    }
+    fn storage_key(&self) -> String {
+        util::profile_key(&self.profile, self.name)
+    }
+    /// Switches the order that test inputs are laid out in. It is saved with
+    /// the board when the tests are next played.
+    fn cycle_input_ordering(&mut self) {
+        let board = &mut self.initial_state.board;
+        let ordering = board.input_ordering().next();
+        board.set_input_ordering(ordering);
     }
     fn save_state(&self) {
         let local_storage = util::get_storage();
         let state_str = ron::ser::to_string(&self.initial_state).unwrap();
 
-        if local_storage.set_item(self.name, &state_str).is_err() {
+        if local_storage.set_item(&self.storage_key(), &state_str).is_err() {
             crate::console_error!("Could not save to local storage");
         }
     }
     fn restore_state(&mut self) {
         let local_storage = util::get_storage();
 
-        match local_storage.get_item(self.name) {
+        match local_storage.get_item(&self.storage_key()) {
             Err(_) => crate::console_error!("Could not access local storage"),
             Ok(None) => {},
             Ok(Some(string)) => {
@@ -75,6 +337,7 @@ impl GodLevel {
                 self.initial_state = state;
                 self.running_state = GodLevelStatus::new();
                 self.current_test = 0;
+                self.results.clear();
             },
         }
     }
@@ -89,17 +352,33 @@ impl GodLevel {
 
                 self.save_state();
                 self.current_test = 0;
+                self.results.clear();
+                if let Err(error) = self.validate_design() {
+                    console_log!("{}", error);
+                    let test = self.tests[0].clone();
+                    self.running_state.report(test, TestResult::Error(error.message()));
+                    return;
+                }
                 self.next_test();
             }
             ControlButton::Stop => {
                 self.reset_tests();
             }
             ControlButton::Pause => self.running_state.pause(),
+            ControlButton::Reset => {
+                let dialog = ConfirmDialog::centred_in(Self::RESET_MESSAGE, CowLevel::BOUNDING_RECT);
+                self.confirm_reset = Some(dialog);
+            }
         }
     }
 }
 impl component::Component for GodLevel {
     type DrawArgs = ();
+    fn set_profile(&mut self, profile: &str) {
+        self.profile = profile.to_owned();
+        self.clear_state();
+        self.restore_state();
+    }
     fn called_into(&mut self, _object: Object) {
         self.restore_state();
         self.reset_tests();
@@ -108,6 +387,9 @@ impl component::Component for GodLevel {
         CowLevel::BOUNDING_RECT
     }
     fn click(&mut self, point: Point<i32>) -> bool {
+        if let Some(dialog) = self.confirm_reset.as_mut() {
+            return dialog.click(point);
+        }
         if !self.in_boundary(point) {
             return false;
         }
@@ -119,19 +401,49 @@ impl component::Component for GodLevel {
         }
 
         match &mut self.running_state {
-            GodLevelStatus::Report(result) => {
-                let result = result.clone();
-                self.running_state.close_report(&result);
+            GodLevelStatus::Report(_) => {
+                self.close_report();
+                true
+            },
+            GodLevelStatus::Summary(_) => {
+                self.close_summary();
                 true
             },
             GodLevelStatus::Stopped => {
                 let value = self.control_panel.cell_palette_value();
-                self.initial_state.set_cell_at_point(point, value);
+                let blanks_only = self.control_panel.is_blanks_only();
+                self.initial_state.set_cell_at_point(point, value, blanks_only);
                 true
             },
             _ => false
         }
     }
+    fn success_state(&self) -> Option<SuccessState> {
+        if self.is_success() {
+            return Some(SuccessState::Succeeded);
+        }
+        match &self.running_state {
+            GodLevelStatus::Report(result) if !result.is_passed() => Some(SuccessState::Failed),
+            GodLevelStatus::Summary(summary) if !summary.is_passed() => Some(SuccessState::Failed),
+            _ => Some(SuccessState::Running),
+        }
+    }
+    fn test_progress(&self) -> Option<(usize, usize)> {
+        Some((self.current_test, self.tests.len()))
+    }
+    fn on_resize(&mut self, dimensions: Point<i32>) {
+        self.control_panel.on_resize(dimensions)
+    }
+    fn hover(&mut self, point: Point<i32>) {
+        self.hovered = match self.running_state {
+            GodLevelStatus::Stopped
+                if self.in_boundary(point) && !self.control_panel.in_boundary(point) =>
+            {
+                Some(super::board::get_grid_index(point))
+            }
+            _ => None,
+        };
+    }
     fn draw(&self, context: &Context2D, assets: &Assets, _args: ()) {
         self.fill_bg(context, super::BG_FILL);
 
@@ -142,12 +454,37 @@ impl component::Component for GodLevel {
                 .draw(context, assets, &self.initial_state, 0.0);
         }
 
-        if !self.running_state.is_report() {
+        if !self.running_state.covers_board() {
+            self.initial_state.draw_io_order(context);
+        }
+        if let GodLevelStatus::Playing(_, _) | GodLevelStatus::Paused(_, _) = self.running_state {
+            self.draw_speed(context);
+        }
+        if self.running_state.is_stopped() && self.shows_grid {
+            self.initial_state.draw_grid(context);
+        }
+        if let (GodLevelStatus::Stopped, Some(grid_index)) = (&self.running_state, self.hovered) {
+            self.control_panel.draw_preview(context, assets, grid_index);
+        }
+        if !self.running_state.covers_board() {
             self.control_panel.fill_bg(context, cell_cursor::BG_COLOUR);
             self.control_panel.draw(context, assets, ());
+            self.draw_cell_space(context);
+        }
+        if let Some(dialog) = &self.confirm_reset {
+            dialog.draw(context, assets, ());
         }
     }
     fn step(&mut self, dt: f64, keyboard_state: &KeyboardState) -> NextScene {
+        if let Some(dialog) = self.confirm_reset.as_mut() {
+            if let NextScene::Return(Object::Bool(answer)) = dialog.step(dt, keyboard_state) {
+                self.confirm_reset = None;
+                if answer {
+                    self.reset_save();
+                }
+            }
+            return NextScene::Continue;
+        }
         if keyboard_state.is_pressed("Add") || keyboard_state.is_pressed("ArrowUp") {
             self.speed += 1.0;
         }
@@ -155,17 +492,81 @@ impl component::Component for GodLevel {
             self.speed -= 1.0;
         }
         self.speed = util::clamp(self.speed, 1.0, Self::MAX_SPEED_SCALE);
+        if self.running_state.is_stopped() && keyboard_state.is_pressed("KeyV") {
+            self.results = self.simulate_tests();
+            self.show_summary();
+        }
+        if crate::DEBUG && keyboard_state.is_pressed("KeyG") {
+            console_log!("{}", self.to_data("").to_ron());
+        }
+        if self.running_state.is_stopped() {
+            self.control_panel.handle_keyboard(keyboard_state);
+        }
+        if self.running_state.is_stopped() && keyboard_state.is_pressed("KeyI") {
+            self.shows_grid = !self.shows_grid;
+        }
+        if self.running_state.is_stopped() && keyboard_state.is_pressed("KeyO") {
+            self.cycle_input_ordering();
+        }
 
+        // a report that appears this frame waits for the next key press.
+        let was_covered = self.running_state.covers_board();
         self.running_state.step(dt * self.speed, keyboard_state);
-        if self.running_state.is_succeeded() {
-            if self.is_success() {
-                return NextScene::Return(Object::Bool(true));
-            } else {
-                self.next_test();
-                return NextScene::Continue;
-            }
+
+        let is_confirmed = was_covered
+            && (keyboard_state.is_pressed("Space") || keyboard_state.is_pressed("Enter"));
+        self.follow_status(is_confirmed)
+    }
+    fn set_signals(&mut self, signals: Signals) {
+        self.initial_state.set_signals(signals);
+        if let GodLevelStatus::Playing(_, state) | GodLevelStatus::Paused(_, state) = &mut self.running_state {
+            state.run.state.set_signals(signals);
+        }
+    }
+    fn serialize_state(&self) -> Option<String> {
+        Some(ron::ser::to_string(&self.initial_state).unwrap())
+    }
+    #[cfg(debug_assertions)]
+    fn check_invariants(&self) {
+        self.bounding_rect().check_invariants();
+        self.initial_state.check_invariants();
+        if let GodLevelStatus::Playing(_, state) | GodLevelStatus::Paused(_, state) = &self.running_state {
+            state.run.state.check_invariants();
         }
-        NextScene::Continue
+    }
+}
+
+/// Runs a test to completion without drawing anything.
+fn simulate(initial: &LevelState, test: &Test) -> TestResult {
+    let mut run = match prepare_test(initial.clone(), test) {
+        Ok(state) => TestRun::new(state),
+        Err(result) => return result,
+    };
+    loop {
+        if let Some(result) = run.result() {
+            return result;
+        }
+        run.advance();
+    }
+}
+
+// loads the test's input into the state, or gives the reason it can't run.
+fn prepare_test(mut state: LevelState, test: &Test) -> Result<LevelState, TestResult> {
+    if state.validate_cows().is_err() {
+        return Err(TestResult::Error("COWs own each other"));
+    }
+    match state.set_inputs(test.input()) {
+        Ok(()) => Ok(state),
+        Err(_) => Err(TestResult::NotEnoughInputSpace),
+    }
+}
+
+// the result of a run, if the state has finished.
+fn finished_result(state: &LevelState) -> Option<TestResult> {
+    match state.success_state() {
+        SuccessState::Failed => Some(TestResult::Reject),
+        SuccessState::Succeeded => Some(TestResult::AcceptWith(state.get_outputs())),
+        SuccessState::Running => None,
     }
 }
 
@@ -177,6 +578,9 @@ enum GodLevelStatus {
     Playing(Test, Box<GodLevelRunningState>),
     Report(MetaTestResult),
     Succeeded,
+    Summary(TestSummary),
+    // every test passed and the summary has been seen.
+    Complete,
 }
 impl GodLevelStatus {
     fn new() -> Self {
@@ -185,23 +589,25 @@ impl GodLevelStatus {
     fn stop(&mut self) {
         *self = Self::Stopped;
     }
-    fn start(&mut self, mut state: LevelState, test: Test) {
+    fn start(&mut self, state: LevelState, test: Test) {
         assert!(self.is_stopped());
-        if let Ok(()) = state.set_inputs(test.input()) {
-            *self = Self::Playing(test, Box::new(GodLevelRunningState::new(state)));
-        } else {
-            let result = MetaTestResult::new(test, TestResult::NotEnoughInputSpace);
-            *self = Self::Report(result);
+        match prepare_test(state, &test) {
+            Ok(state) => {
+                *self = Self::Playing(test, Box::new(GodLevelRunningState::new(state)));
+            }
+            Err(result) => self.report(test, result),
         }
     }
+    fn report(&mut self, test: Test, result: TestResult) {
+        *self = Self::Report(MetaTestResult::new(test, result));
+    }
     fn pause(&mut self) {
         let status = std::mem::replace(self, Self::Stopped);
         *self = match status {
             Self::Stopped => Self::Stopped,
             Self::Playing(test, state) => Self::Paused(test, state),
             Self::Paused(test, state) => Self::Paused(test, state),
-            Self::Report(result) => Self::Report(result),
-            Self::Succeeded => Self::Succeeded,
+            other => other,
         }
     }
     fn play(&mut self) {
@@ -210,14 +616,7 @@ impl GodLevelStatus {
             Self::Stopped => panic!("Play used on stopped variant. Use start instead."),
             Self::Playing(test, state) => Self::Playing(test, state),
             Self::Paused(test, state) => Self::Playing(test, state),
-            Self::Report(result) => Self::Report(result),
-            Self::Succeeded => Self::Succeeded,
-        }
-    }
-    fn is_succeeded(&self) -> bool {
-        match self {
-            Self::Succeeded => true,
-            _ => false,
+            other => other,
         }
     }
     fn is_stopped(&self) -> bool {
@@ -233,21 +632,36 @@ impl GodLevelStatus {
             Self::Paused(_, _) => true,
             Self::Report(_) => true,
             Self::Succeeded => false,
+            Self::Summary(_) => true,
+            Self::Complete => false,
         }
     }
-    fn is_report(&self) -> bool {
+    // whether a report or summary is drawn over the whole board.
+    fn covers_board(&self) -> bool {
         match self {
-            Self::Report(_) => true,
+            Self::Report(_) | Self::Summary(_) => true,
             _ => false,
         }
     }
-    fn close_report(&mut self, result: &MetaTestResult) {
-        if result.is_passed() {
+    fn close_report(&mut self, is_passed: bool) {
+        if is_passed {
             *self = Self::Succeeded;
         } else {
             *self = Self::Stopped;
         }
     }
+    // steps a playing test, and shows its report once it finishes.
+    fn advance(&mut self, dt: f64) {
+        if let Self::Playing(test, state) = self {
+            state.step(dt);
+            if !state.is_complete() {
+                return;
+            }
+            if let Some(result) = state.result() {
+                *self = Self::Report(MetaTestResult::new(test.clone(), result));
+            }
+        }
+    }
 }
 impl component::Component for GodLevelStatus {
     type DrawArgs = ();
@@ -263,72 +677,115 @@ impl component::Component for GodLevelStatus {
             Self::Report(result) => {
                 result.draw(context, assets, ());
             }
-            Self::Succeeded => {}
+            Self::Summary(summary) => {
+                summary.draw(context, assets, ());
+            }
+            Self::Succeeded | Self::Complete => {}
         }
     }
     fn step(&mut self, dt: f64, keyboard: &KeyboardState) -> NextScene {
         match self {
             Self::Stopped => NextScene::Continue,
-            Self::Paused(_, _) => NextScene::Continue,
-            Self::Playing(ref test, ref mut state) => {
-                state.step(dt);
-                if !state.is_complete() {
-                    return NextScene::Continue;
-                }
-
-                if let Some(result) = state.result() {
-                    let result = MetaTestResult::new(test.clone(), result);
-                    *self = Self::Report(result);
-                }
+            Self::Paused(_, _) | Self::Playing(_, _) if keyboard.is_pressed("Escape") => {
+                self.stop();
                 NextScene::Continue
             }
-            Self::Report(result) => {
-                let result = result.clone();
-                if keyboard.is_pressed("Space") || keyboard.is_pressed("Enter") {
-                    self.close_report(&result);
-                }
+            _ => {
+                self.advance(dt);
                 NextScene::Continue
             }
-            Self::Succeeded => NextScene::Continue,
         }
     }
 }
 
+/// A test being stepped through, whether it is watched or simulated.
+#[derive(Clone, Debug)]
+struct TestRun {
+    state: LevelState,
+    total_steps: u32,
+    // every state stepped through, to stop a run that loops early.
+    seen: HashSet<u64>,
+    is_deadlocked: bool,
+}
+impl TestRun {
+    // a run that takes this many steps is assumed to never finish.
+    const MAX_TOTAL_STEPS: u32 = 10_000;
+
+    fn new(state: LevelState) -> Self {
+        let mut seen = HashSet::new();
+        let is_deadlocked = state.is_deadlocked(&mut seen);
+        TestRun {
+            state,
+            total_steps: 0,
+            seen,
+            is_deadlocked,
+        }
+    }
+    /// A run that loops back to an earlier state times out straight away,
+    /// since it would never finish.
+    fn is_timed_out(&self) -> bool {
+        let is_too_long = self.is_deadlocked || self.total_steps >= Self::MAX_TOTAL_STEPS;
+        is_too_long && self.state.success_state().is_running()
+    }
+    fn is_running(&self) -> bool {
+        self.state.success_state().is_running() && !self.is_timed_out()
+    }
+    fn result(&self) -> Option<TestResult> {
+        if self.is_timed_out() {
+            return Some(TestResult::Timeout);
+        }
+        finished_result(&self.state)
+    }
+    fn advance(&mut self) {
+        self.state.auto();
+        self.total_steps += 1;
+        self.is_deadlocked = self.state.is_deadlocked(&mut self.seen);
+    }
+}
+
 #[derive(Clone, Debug)]
 struct GodLevelRunningState {
-    current_state: LevelState,
+    run: TestRun,
     old_state: LevelState,
     animation_time: f64,
+    // the most calls to `auto` in one frame, if limited.
+    step_cap: Option<u32>,
+    steps_this_frame: u32,
 }
 impl GodLevelRunningState {
+    const MAX_STEPS_PER_FRAME: u32 = 64;
+
     fn new(initial_state: LevelState) -> Self {
         GodLevelRunningState {
-            current_state: initial_state.clone(),
-            old_state: initial_state,
+            old_state: initial_state.clone(),
+            run: TestRun::new(initial_state),
             animation_time: GodLevel::MIN_SPEED,
+            step_cap: Some(Self::MAX_STEPS_PER_FRAME),
+            steps_this_frame: 0,
         }
     }
     fn result(&self) -> Option<TestResult> {
-        match self.current_state.success_state() {
-            SuccessState::Failed => Some(TestResult::Reject),
-            SuccessState::Succeeded => {
-                Some(TestResult::AcceptWith(self.current_state.get_outputs()))
-            }
-            SuccessState::Running => None,
-        }
+        self.run.result()
     }
 
     /// is complete if all cows are in a success zone or one is in a failure zone.
     fn is_complete(&self) -> bool {
-        !self.current_state.success_state().is_running() && self.animation_time > GodLevel::MIN_SPEED
+        !self.run.is_running() && self.animation_time > GodLevel::MIN_SPEED
     }
 
     fn step(&mut self, dt: f64) {
         self.animation_time += dt;
-        while self.animation_time > GodLevel::MIN_SPEED && self.current_state.success_state().is_running() {
+        self.steps_this_frame = 0;
+        while self.animation_time > GodLevel::MIN_SPEED && self.run.is_running() {
+            if self.step_cap.is_some_and(|cap| self.steps_this_frame >= cap) {
+                // drop the backlog rather than catching up later.
+                self.animation_time = GodLevel::MIN_SPEED;
+                break;
+            }
             self.animation_time -= GodLevel::MIN_SPEED;
-            self.old_state.clone_from(&self.current_state);
-            self.current_state.auto();
+            self.old_state.clone_from(&self.run.state);
+            self.run.advance();
+            self.steps_this_frame += 1;
         }
     }
 }
@@ -339,8 +796,22 @@ impl component::Component for GodLevelRunningState {
     }
     fn draw(&self, context: &Context2D, assets: &Assets, _args: ()) {
         let anim_progress = util::clamp(self.animation_time / GodLevel::MIN_SPEED, 0.0, 1.0);
-        self.current_state
+        self.run
+            .state
             .draw(context, assets, &self.old_state, anim_progress);
+        self.run.state.draw_bounces(context, anim_progress);
+
+        if crate::DEBUG {
+            let text = format!(
+                "steps: {} ({} this frame)",
+                self.run.total_steps, self.steps_this_frame
+            );
+            util::with_saved_context(context, || {
+                context.set_font("10px KongText");
+                context.set_fill_style_str("black");
+                context.fill_text(&text, 10.0, 20.0).unwrap();
+            });
+        }
     }
 }
 
@@ -349,6 +820,7 @@ pub enum ControlButton {
     Play,
     Pause,
     Stop,
+    Reset,
 }
 #[derive(Clone, Debug)]
 struct ControlPanel {
@@ -359,17 +831,26 @@ impl ControlPanel {
     const HALF_HEIGHT: i32 = SpriteSheet::STANDARD_HEIGHT / 2;
     const HALF_WIDTH: i32 = SpriteSheet::STANDARD_WIDTH / 2;
     const PALETTE_OFFSET: Point<i32> = Point(0, Self::HALF_HEIGHT * 3);
-    const PLAY_BUTTON: CellGraphic =
-        CellGraphic::new(Point(Self::HALF_WIDTH, Self::HALF_HEIGHT), Point(15, 0));
-    const PAUSE_BUTTON: CellGraphic =
-        CellGraphic::new(Point(Self::HALF_WIDTH * 3, Self::HALF_HEIGHT), Point(14, 0));
-    const STOP_BUTTON: CellGraphic =
-        CellGraphic::new(Point(Self::HALF_WIDTH * 7, Self::HALF_HEIGHT), Point(13, 0));
+    const PLAY_BUTTON: Button = Self::button(1, Point(15, 0));
+    const PAUSE_BUTTON: Button = Self::button(3, Point(14, 0));
+    const STOP_BUTTON: Button = Self::button(7, Point(13, 0));
+    const RESET_HALF_WIDTHS: i32 = 9;
     const CONTROL_DIMENSIONS: component::Rect = component::Rect {
         top_left: Point(0, 0),
-        dimensions: Point(Self::HALF_WIDTH * 10, Self::HALF_HEIGHT * 3),
+        dimensions: Point(Self::HALF_WIDTH * 12, Self::HALF_HEIGHT * 3),
     };
 
+    const fn button(half_widths: i32, icon: Point<u8>) -> Button {
+        let top_left = Point(Self::HALF_WIDTH * half_widths, Self::HALF_HEIGHT);
+        Button::new(
+            component::Rect::new(top_left, component::Rect::ONE_BY_ONE),
+            component::Rect::indexed(icon, component::Rect::ONE_BY_ONE),
+            IconSheet::Blocks,
+        )
+    }
+    fn reset_button() -> Button {
+        Self::button(Self::RESET_HALF_WIDTHS, SpriteLayout::current().eraser_icon)
+    }
     fn new(cell_palette: CellPalette<CellType>) -> Self {
         ControlPanel {
             cell_palette: Translation::new(Self::PALETTE_OFFSET, cell_palette),
@@ -379,9 +860,18 @@ impl ControlPanel {
     fn cell_palette_value(&self) -> PaletteResult<CellType> {
         self.cell_palette.value()
     }
+    fn is_blanks_only(&self) -> bool {
+        self.cell_palette.is_blanks_only()
+    }
+    fn handle_keyboard(&mut self, keyboard_state: &KeyboardState) -> bool {
+        self.cell_palette.handle_keyboard(keyboard_state)
+    }
     fn last_press(&self) -> Option<ControlButton> {
         self.last_press
     }
+    fn draw_preview(&self, context: &Context2D, assets: &Assets, grid_index: Point<i32>) {
+        self.cell_palette.draw_preview(context, assets, grid_index)
+    }
 }
 impl component::Component for ControlPanel {
     type DrawArgs = ();
@@ -404,6 +894,10 @@ impl component::Component for ControlPanel {
             self.last_press = Some(ControlButton::Stop);
             return true;
         }
+        if Self::reset_button().in_boundary(point) {
+            self.last_press = Some(ControlButton::Reset);
+            return true;
+        }
 
         self.last_press = None;
         self.cell_palette.click(point)
@@ -412,7 +906,244 @@ impl component::Component for ControlPanel {
         Self::PLAY_BUTTON.draw(context, assets, ());
         Self::PAUSE_BUTTON.draw(context, assets, ());
         Self::STOP_BUTTON.draw(context, assets, ());
+        Self::reset_button().draw(context, assets, ());
 
         self.cell_palette.draw(context, assets, ());
     }
+    fn on_resize(&mut self, dimensions: Point<i32>) {
+        self.cell_palette.on_resize(dimensions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::direction::Direction;
+    use crate::level::board::InputOrdering;
+    use crate::level::cell::{Colour, GroundCell, OverlayCell, Surroundings};
+
+    fn level(tests: Vec<Test>) -> GodLevel {
+        GodLevel::new("test", tests)
+    }
+
+    fn place_overlay(level: &mut GodLevel, cell: OverlayCell, count: i32) {
+        for x in 0..count {
+            level.initial_state.board.set_overlay_cell(Point(x, 0), cell);
+        }
+    }
+
+    #[test]
+    fn reset_asks_first() {
+        let mut level = level(vec![Test::new(vec![], TestTarget::Accept)]);
+        level.initial_state.board.set_ground_cell(Point(1, 1), GroundCell::Swapper);
+        level.control_button_press(ControlButton::Reset);
+        assert!(level.confirm_reset.is_some());
+        assert_eq!(level.initial_state.ground_cell_at(Point(1, 1)), GroundCell::Swapper);
+
+        // the dialog takes every click, so the board can't be edited.
+        assert!(component::Component::click(&mut level, Point(2, 2)));
+        assert_eq!(level.initial_state.ground_cell_at(Point(2, 2)), GroundCell::Empty);
+        assert!(level.confirm_reset.is_some());
+    }
+
+    #[test]
+    fn too_few_input_cells() {
+        let tests = vec![Test::new(vec![Colour::Red, Colour::Blue], TestTarget::Accept)];
+        let mut level = level(tests);
+        place_overlay(&mut level, OverlayCell::Input(Surroundings::new()), 1);
+        match level.validate_design() {
+            Err(DesignError::NotEnoughInputCells { have: 1, need: 2 }) => {}
+            result => panic!("Expected too few input cells, got {:?}", result),
+        }
+
+        place_overlay(&mut level, OverlayCell::Input(Surroundings::new()), 2);
+        assert!(level.validate_design().is_ok());
+    }
+
+    #[test]
+    fn too_few_output_cells() {
+        let target = TestTarget::AcceptWith(vec![Colour::Red, Colour::Red]);
+        let mut level = level(vec![Test::new(vec![], target)]);
+        match level.validate_design() {
+            Err(DesignError::NotEnoughOutputCells { have: 0, need: 2 }) => {}
+            result => panic!("Expected too few output cells, got {:?}", result),
+        }
+
+        place_overlay(&mut level, OverlayCell::Output(Surroundings::new()), 2);
+        assert!(level.validate_design().is_ok());
+    }
+
+    #[test]
+    fn extra_outputs_need_no_output_cells() {
+        let extra_outputs = [
+            GroundCell::Multiplier(None),
+            GroundCell::Sequencer(None),
+            GroundCell::Recorder,
+        ];
+        for cell in extra_outputs.iter() {
+            let target = TestTarget::AcceptWith(vec![Colour::Red, Colour::Red]);
+            let mut level = level(vec![Test::new(vec![], target)]);
+            level.initial_state.board.set_ground_cell(Point(4, 4), *cell);
+            assert!(level.validate_design().is_ok());
+        }
+    }
+
+    #[test]
+    fn looping_converters_fail_the_design() {
+        let mut level = level(vec![Test::new(vec![], TestTarget::Accept)]);
+        let board = &mut level.initial_state.board;
+        board.set_ground_cell(Point(4, 4), GroundCell::Converter(Colour::Blue, Direction::Right));
+        board.set_ground_cell(Point(5, 4), GroundCell::Converter(Colour::Red, Direction::Left));
+        match level.validate_design() {
+            Err(DesignError::ColourCycle) => {}
+            result => panic!("Expected a colour cycle, got {:?}", result),
+        }
+    }
+
+    fn set_overlay(level: &mut GodLevel, point: Point<i32>, cell: OverlayCell) {
+        level.initial_state.board.set_overlay_cell(point, cell);
+    }
+
+    fn assert_all_pass(level: &GodLevel) {
+        let results = level.simulate_tests();
+        assert_eq!(results.len(), level.tests.len());
+        for result in results.iter() {
+            assert!(result.is_passed(), "{:?}", result);
+        }
+    }
+
+    // the tests of "level_1_0": accept everything.
+    #[test]
+    fn accept_all_level_passes_headlessly() {
+        let mut level = level(vec![Test::new(vec![], TestTarget::Accept)]);
+        // the player walks right onto the green, and its child stays on it.
+        set_overlay(&mut level, Point(25, 4), OverlayCell::Success(Surroundings::new()));
+        set_overlay(&mut level, Point(8, 12), OverlayCell::Success(Surroundings::new()));
+        assert_all_pass(&level);
+    }
+
+    // the tests of "level_1_1": accept red, reject blue.
+    #[test]
+    fn red_or_blue_level_passes_headlessly() {
+        let mut level = level(vec![
+            Test::new(vec![Colour::Red], TestTarget::Accept),
+            Test::new(vec![Colour::Blue], TestTarget::Reject),
+        ]);
+        // the player climbs onto a red input and carries on up, or walks
+        // right into the red zone past a blue one.
+        let arrow = GroundCell::SelfColouredArrow(Colour::Red, Direction::Up);
+        level.initial_state.board.set_ground_cell(Point(24, 4), arrow);
+        set_overlay(&mut level, Point(24, 3), OverlayCell::Input(Surroundings::new()));
+        set_overlay(&mut level, Point(24, 2), OverlayCell::Success(Surroundings::new()));
+        set_overlay(&mut level, Point(25, 4), OverlayCell::Failure(Surroundings::new()));
+        set_overlay(&mut level, Point(8, 12), OverlayCell::Success(Surroundings::new()));
+        assert_all_pass(&level);
+
+        // the same board fails when the tests are swapped round.
+        level.tests = vec![Test::new(vec![Colour::Blue], TestTarget::Accept)];
+        let results = level.simulate_tests();
+        assert_eq!(results.len(), 1);
+        assert!(!results[0].is_passed());
+    }
+
+    #[test]
+    fn simulations_stop_at_the_first_failure() {
+        let mut level = level(vec![
+            Test::new(vec![], TestTarget::Reject),
+            Test::new(vec![], TestTarget::Accept),
+        ]);
+        set_overlay(&mut level, Point(25, 4), OverlayCell::Success(Surroundings::new()));
+        set_overlay(&mut level, Point(8, 12), OverlayCell::Success(Surroundings::new()));
+        assert_eq!(level.simulate_tests().len(), 1);
+    }
+
+    #[test]
+    fn watched_runs_match_simulations() {
+        let mut level = level(vec![Test::new(vec![], TestTarget::Accept)]);
+        set_overlay(&mut level, Point(26, 4), OverlayCell::Success(Surroundings::new()));
+        set_overlay(&mut level, Point(8, 12), OverlayCell::Success(Surroundings::new()));
+        let test = &level.tests[0];
+
+        let mut watched = GodLevelRunningState::new(level.initial_state.clone());
+        while !watched.is_complete() {
+            watched.step(GodLevel::MIN_SPEED);
+        }
+        assert_eq!(watched.run.total_steps, 2);
+        assert_eq!(watched.result(), Some(simulate(&level.initial_state, test)));
+    }
+
+    #[test]
+    fn shipped_god_levels_load() {
+        let data = GodLevelData::from_ron(include_str!("../level_data/level_1_0.ron")).unwrap();
+        assert!(!data.description().is_empty());
+        let level = GodLevel::from_data("level_1_0", data);
+        assert_eq!(level.tests.len(), 1);
+    }
+
+    #[test]
+    fn god_levels_need_tests() {
+        let mut data = GodLevelData::from_ron(include_str!("../level_data/level_1_0.ron")).unwrap();
+        data.tests.clear();
+        assert!(GodLevelData::from_ron(&data.to_ron()).is_err());
+    }
+
+    #[test]
+    fn the_editor_cycles_the_input_ordering() {
+        let mut level = level(vec![Test::new(vec![], TestTarget::Accept)]);
+        level.cycle_input_ordering();
+        assert_eq!(level.initial_state.board.input_ordering(), &InputOrdering::ColumnMajor);
+        level.cycle_input_ordering();
+        assert_eq!(level.initial_state.board.input_ordering(), &InputOrdering::RowMajor);
+    }
+
+    // plays every test as if watched, confirming each report, until the
+    // summary appears.
+    fn watch_tests(level: &mut GodLevel) -> TestSummary {
+        level.next_test();
+        loop {
+            if let GodLevelStatus::Summary(summary) = &level.running_state {
+                return summary.clone();
+            }
+            let was_covered = level.running_state.covers_board();
+            level.running_state.advance(GodLevel::MIN_SPEED);
+            level.follow_status(was_covered);
+        }
+    }
+
+    fn passes(results: &[MetaTestResult]) -> Vec<bool> {
+        results.iter().map(MetaTestResult::is_passed).collect()
+    }
+
+    #[test]
+    fn watched_results_are_collected_in_order() {
+        let mut level = level(vec![
+            Test::new(vec![], TestTarget::Accept),
+            Test::new(vec![], TestTarget::Accept),
+            Test::new(vec![], TestTarget::Reject),
+            Test::new(vec![], TestTarget::Accept),
+        ]);
+        set_overlay(&mut level, Point(26, 4), OverlayCell::Success(Surroundings::new()));
+        set_overlay(&mut level, Point(8, 12), OverlayCell::Success(Surroundings::new()));
+
+        let summary = watch_tests(&mut level);
+        assert_eq!(passes(&level.results), vec![true, true, false]);
+        assert_eq!(passes(&level.results), passes(&level.simulate_tests()));
+        assert_eq!(summary.passed(), 2);
+        assert!(!summary.is_passed());
+    }
+
+    #[test]
+    fn runs_that_bounce_between_two_walls_time_out_early() {
+        let level = level(vec![Test::new(vec![], TestTarget::Accept)]);
+        let mut state = level.initial_state.clone();
+        for x in &[22, 27] {
+            state.board.set_ground_cell(Point(*x, 4), GroundCell::Wall(Surroundings::new()));
+        }
+        let mut run = TestRun::new(state);
+        while run.is_running() {
+            run.advance();
+        }
+        assert_eq!(run.result(), Some(TestResult::Timeout));
+        assert!(run.total_steps < TestRun::MAX_TOTAL_STEPS);
+    }
 }
@@ -1,8 +1,10 @@
+use serde::{Deserialize, Serialize};
+
 use crate::{Context2D, Assets, SpriteSheet, Point, util};
 use crate::component::{Component, Rect};
 use crate::level::cell::{Colour};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Test {
     input: Vec<Colour>,
     output: TestTarget,
@@ -14,19 +16,55 @@ impl Test {
     pub fn input(&self) -> &[Colour] {
         &self.input
     }
+    /// The number of output cells needed to check this test's output.
+    pub fn required_output_len(&self) -> usize {
+        match &self.output {
+            TestTarget::AcceptWith(output) => output.len(),
+            TestTarget::Reject | TestTarget::Accept => 0,
+        }
+    }
 }
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum TestTarget {
     Reject,
     Accept,
     AcceptWith(Vec<Colour>),
 }
 
-#[derive(Clone, Debug)]
+/// Tests with random inputs, each checked against `spec`. The inputs come
+/// from `seed`, so a level generates the same tests every time it is built.
+#[derive(Clone, Copy)]
+pub struct RandomTests {
+    pub count: usize,
+    pub max_len: usize,
+    pub colours: &'static [Colour],
+    pub spec: fn(&[Colour]) -> TestTarget,
+    pub seed: u64,
+}
+impl RandomTests {
+    pub fn generate(&self) -> Vec<Test> {
+        assert!(!self.colours.is_empty());
+        let mut rng = util::Rng::new(self.seed);
+        (0..self.count)
+            .map(|_| {
+                let len = rng.below(self.max_len + 1);
+                let input: Vec<Colour> = (0..len)
+                    .map(|_| self.colours[rng.below(self.colours.len())])
+                    .collect();
+                let output = (self.spec)(&input);
+                Test::new(input, output)
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum TestResult {
     Reject,
     AcceptWith(Vec<Colour>),
     NotEnoughInputSpace,
+    Timeout,
+    Error(&'static str),
 }
 
 #[derive(Clone, Debug)]
@@ -46,6 +84,44 @@ impl<'a> MetaTestResult {
             (_, _) => false,
         }
     }
+    /// The first index where the expected and found outputs differ, if they
+    /// differ at all. When one is a prefix of the other, that is the length
+    /// of the shorter one.
+    fn first_divergence(ideal: &[Colour], real: &[Colour]) -> Option<usize> {
+        ideal
+            .iter()
+            .zip(real.iter())
+            .position(|(ideal, real)| ideal != real)
+            .or_else(|| {
+                if ideal.len() == real.len() {
+                    None
+                } else {
+                    Some(ideal.len().min(real.len()))
+                }
+            })
+    }
+    fn divergence(&self) -> Option<usize> {
+        match (&self.test.output, &self.result) {
+            (TestTarget::AcceptWith(ideal), TestResult::AcceptWith(real)) => {
+                Self::first_divergence(ideal, real)
+            }
+            (_, _) => None,
+        }
+    }
+    /// Outlines the cell at `index` of a row drawn by `draw_colours`. An index
+    /// just past the end outlines the empty space after the row.
+    fn draw_mismatch(context: &Context2D, colours: &[Colour], index: usize, offset: Point<f64>) {
+        if colours.is_empty() {
+            return;
+        }
+        let cell_width = f64::from(SpriteSheet::STANDARD_WIDTH);
+        let left = offset.x() - cell_width * colours.len() as f64 / 2.0;
+        let x = left + cell_width * index as f64;
+
+        context.set_stroke_style_str("#bb0015");
+        context.set_line_width(2.0);
+        context.stroke_rect(x, offset.y(), cell_width, f64::from(SpriteSheet::STANDARD_HEIGHT));
+    }
     fn draw_colours(context: &Context2D, assets: &Assets, colours: &[Colour], offset: Point<f64>) {
         if colours.is_empty() {
             context.save();
@@ -77,6 +153,11 @@ impl<'a> MetaTestResult {
     const LEFT_COLUMN: f64 = Self::CENTRE * 0.5;
     const RIGHT_COLUMN: f64 = Self::CENTRE * 1.5;
 
+    const BLACK: &'static str = "black";
+    const GREEN: &'static str = "#47a624";
+    const RED: &'static str = "#bb0015";
+    const OUTLINE: &'static str = "white";
+
     const TOP_MARGIN: f64 = 60.0;
     const RESULT_TOP: f64 = 90.0;
     const INPUT_TOP: f64 = 110.0;
@@ -93,88 +174,181 @@ impl Component for MetaTestResult {
             .draw_with_rect(context, &Self::REPORT_BG, &Self::BOUNDING_RECT);
 
         util::with_saved_context(context, || {
+            let draw_text = |text: &str, x: f64, y: f64, fill: &str| {
+                util::draw_text_outlined(context, text, x, y, fill, Self::OUTLINE);
+            };
+
             context.set_font("25px KongText");
             context.set_text_align("center");
-            let black = wasm_bindgen::JsValue::from_str("black");
-            let green = wasm_bindgen::JsValue::from_str("#47a624");
-            let red = wasm_bindgen::JsValue::from_str("#bb0015");
-            
-            context.set_fill_style(&black);
-            context
-                .fill_text("Report:", Self::CENTRE, Self::TOP_MARGIN)
-                .unwrap();
+            draw_text("Report:", Self::CENTRE, Self::TOP_MARGIN, Self::BLACK);
 
             let (colour, text) = if self.is_passed() {
-                (&green, "Pass!")
+                (Self::GREEN, "Pass!")
             }
             else {
-                (&red, "Fail!")
+                (Self::RED, "Fail!")
             };
-            context.set_fill_style(colour);
-            context
-                .fill_text(text, Self::CENTRE, Self::RESULT_TOP)
-                .unwrap();
+            draw_text(text, Self::CENTRE, Self::RESULT_TOP, colour);
 
             context.set_font("15px KongText");
-            context.set_fill_style(&black);
-            context
-                .fill_text("Input:", Self::CENTRE, Self::INPUT_TOP)
-                .unwrap();
-            context
-                .fill_text("Expected:", Self::LEFT_COLUMN, Self::SUBHEADING_TOP)
-                .unwrap();
-            context
-                .fill_text("Found:", Self::RIGHT_COLUMN, Self::SUBHEADING_TOP)
-                .unwrap();
+            draw_text("Input:", Self::CENTRE, Self::INPUT_TOP, Self::BLACK);
+            draw_text("Expected:", Self::LEFT_COLUMN, Self::SUBHEADING_TOP, Self::BLACK);
+            draw_text("Found:", Self::RIGHT_COLUMN, Self::SUBHEADING_TOP, Self::BLACK);
             
             Self::draw_colours(context, assets, &self.test.input, Point(Self::CENTRE, Self::INPUT_TOP + 3.0));
 
+            let verdict_top = Self::SUBHEADING_TOP + 20.0;
             match &self.test.output{
                 TestTarget::Reject => {
-                    context.set_fill_style(&red);
-                    context
-                        .fill_text("Reject", Self::LEFT_COLUMN, Self::SUBHEADING_TOP + 20.0)
-                        .unwrap();
+                    draw_text("Reject", Self::LEFT_COLUMN, verdict_top, Self::RED);
                 },
                 TestTarget::Accept => {
-                    context.set_fill_style(&green);
-                    context
-                        .fill_text("Accept", Self::LEFT_COLUMN, Self::SUBHEADING_TOP + 20.0)
-                        .unwrap();
+                    draw_text("Accept", Self::LEFT_COLUMN, verdict_top, Self::GREEN);
                 },
                 TestTarget::AcceptWith(ideal) => {
-                    context.set_fill_style(&green);
-                    context
-                        .fill_text("Accept", Self::LEFT_COLUMN, Self::SUBHEADING_TOP + 20.0)
-                        .unwrap();
+                    draw_text("Accept", Self::LEFT_COLUMN, verdict_top, Self::GREEN);
                     Self::draw_colours(context, assets, ideal, Point(Self::LEFT_COLUMN, Self::SUBHEADING_TOP + 23.0));
                 },
             }
 
+            if let (Some(index), TestTarget::AcceptWith(ideal), TestResult::AcceptWith(real)) =
+                (self.divergence(), &self.test.output, &self.result)
+            {
+                let top = Self::SUBHEADING_TOP + 23.0;
+                Self::draw_mismatch(context, ideal, index, Point(Self::LEFT_COLUMN, top));
+                Self::draw_mismatch(context, real, index, Point(Self::RIGHT_COLUMN, top));
+            }
+
             match &self.result {
                 TestResult::Reject => {
-                    context.set_fill_style(&red);
-                    context
-                        .fill_text("Reject", Self::RIGHT_COLUMN, Self::SUBHEADING_TOP + 20.0)
-                        .unwrap();
+                    draw_text("Reject", Self::RIGHT_COLUMN, verdict_top, Self::RED);
                 },
                 TestResult::AcceptWith(result) => {
-                    context.set_fill_style(&green);
-                    context
-                        .fill_text("Accept", Self::RIGHT_COLUMN, Self::SUBHEADING_TOP + 20.0)
-                        .unwrap();
+                    draw_text("Accept", Self::RIGHT_COLUMN, verdict_top, Self::GREEN);
                     Self::draw_colours(context, assets, result, Point(Self::RIGHT_COLUMN, Self::SUBHEADING_TOP + 23.0));
                 }
                 TestResult::NotEnoughInputSpace => {
-                    context.set_fill_style(&black);
-                    context
-                        .fill_text("Not enough", Self::RIGHT_COLUMN, Self::SUBHEADING_TOP + 20.0)
-                        .unwrap();
-                    context
-                        .fill_text("room.", Self::RIGHT_COLUMN, Self::SUBHEADING_TOP + 40.0)
-                        .unwrap();
+                    draw_text("Not enough", Self::RIGHT_COLUMN, verdict_top, Self::BLACK);
+                    draw_text("room.", Self::RIGHT_COLUMN, Self::SUBHEADING_TOP + 40.0, Self::BLACK);
+                }
+                TestResult::Timeout => {
+                    draw_text("Timed out", Self::RIGHT_COLUMN, verdict_top, Self::RED);
+                }
+                TestResult::Error(message) => {
+                    draw_text("Error", Self::RIGHT_COLUMN, verdict_top, Self::RED);
+                    context.set_font("10px KongText");
+                    draw_text(message, Self::RIGHT_COLUMN, Self::SUBHEADING_TOP + 40.0, Self::RED);
                 }
             }
         });
     }
-}
\ No newline at end of file
+}
+
+/// How every test went in one run of a god level. The run stops at the first
+/// failure, so any tests after it were not run.
+#[derive(Clone, Debug)]
+pub struct TestSummary {
+    results: Vec<MetaTestResult>,
+    total: usize,
+}
+impl TestSummary {
+    pub fn new(results: Vec<MetaTestResult>, total: usize) -> Self {
+        TestSummary { results, total }
+    }
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|result| result.is_passed()).count()
+    }
+    pub fn is_passed(&self) -> bool {
+        self.passed() == self.total
+    }
+
+    const MAX_ROWS: usize = 8;
+    const FIRST_ROW: f64 = 120.0;
+    const ROW_HEIGHT: f64 = 14.0;
+}
+impl Component for TestSummary {
+    type DrawArgs = ();
+    fn bounding_rect(&self) -> Rect {
+        MetaTestResult::BOUNDING_RECT
+    }
+    fn draw(&self, context: &Context2D, assets: &Assets, _args: ()) {
+        assets
+            .misc
+            .draw_with_rect(context, &MetaTestResult::REPORT_BG, &MetaTestResult::BOUNDING_RECT);
+
+        util::with_saved_context(context, || {
+            let black = "black";
+            let green = "#47a624";
+            let red = "#bb0015";
+            let centre = MetaTestResult::CENTRE;
+
+            context.set_font("25px KongText");
+            context.set_text_align("center");
+            context.set_fill_style_str(black);
+            context
+                .fill_text("Summary:", centre, MetaTestResult::TOP_MARGIN)
+                .unwrap();
+
+            context.set_font("15px KongText");
+            context.set_fill_style_str(if self.is_passed() { green } else { red });
+            let passed = format!("{}/{} passed", self.passed(), self.total);
+            context
+                .fill_text(&passed, centre, MetaTestResult::RESULT_TOP)
+                .unwrap();
+
+            context.set_font("10px KongText");
+            for index in 0..self.total.min(Self::MAX_ROWS) {
+                let (colour, outcome) = match self.results.get(index) {
+                    Some(result) if result.is_passed() => (green, "Pass"),
+                    Some(_) => (red, "Fail"),
+                    None => (black, "Not run"),
+                };
+                let y = Self::FIRST_ROW + Self::ROW_HEIGHT * index as f64;
+                context.set_fill_style_str(colour);
+                context
+                    .fill_text(&format!("Test {}: {}", index + 1, outcome), centre, y)
+                    .unwrap();
+            }
+            if self.total > Self::MAX_ROWS {
+                let y = Self::FIRST_ROW + Self::ROW_HEIGHT * Self::MAX_ROWS as f64;
+                context.set_fill_style_str(black);
+                context.fill_text("...", centre, y).unwrap();
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn random(seed: u64) -> RandomTests {
+        RandomTests {
+            count: 20,
+            max_len: 6,
+            colours: &[Colour::Red, Colour::Blue],
+            spec: |input| TestTarget::AcceptWith(input.to_vec()),
+            seed,
+        }
+    }
+
+    fn inputs(tests: &[Test]) -> Vec<Vec<Colour>> {
+        tests.iter().map(|test| test.input().to_vec()).collect()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_inputs() {
+        let tests = random(7).generate();
+        assert_eq!(tests.len(), 20);
+        assert_eq!(inputs(&tests), inputs(&random(7).generate()));
+        assert_ne!(inputs(&tests), inputs(&random(8).generate()));
+    }
+
+    #[test]
+    fn generated_tests_follow_the_spec() {
+        for test in random(7).generate().iter() {
+            assert!(test.input().len() <= 6);
+            assert_eq!(test.required_output_len(), test.input().len());
+        }
+    }
+}
@@ -0,0 +1,543 @@
+use std::convert::TryFrom;
+
+use super::board::Board;
+use super::cell::{CellType, Colour, GateCondition, GroundCell, OverlayCell, Signals, Surroundings};
+use super::cow::{CowSprite, Cows};
+use super::{KeyboardCommand, LevelState};
+use crate::direction::Direction;
+use crate::point::Point;
+use crate::util;
+
+// Level codes are a compact binary form of a level, written out as base64 so
+// that levels can be shared as short strings. RON stays the format that
+// levels are kept in.
+//
+// The board is stored as runs of identical cells, row by row, which suits
+// boards that are mostly empty ground and long walls.
+
+const VERSION: u8 = 1;
+// stands in for an empty multiplier or sequencer.
+const NO_COLOUR: u8 = 0xFF;
+
+// The code of each cell type is its index here, so only ever add to the end.
+const CELL_TYPES: [CellType; 23] = [
+    CellType::Empty,
+    CellType::ColouredBlock,
+    CellType::Arrow,
+    CellType::ColouredArrow,
+    CellType::ArrowBlock,
+    CellType::RotateRight,
+    CellType::RotateLeft,
+    CellType::Fence,
+    CellType::Wall,
+    CellType::EvenGate,
+    CellType::OddGate,
+    CellType::SumGate,
+    CellType::SignalGate,
+    CellType::KeyGate,
+    CellType::Swapper,
+    CellType::Recorder,
+    CellType::Multiplier,
+    CellType::Sequencer,
+    CellType::PushBlock,
+    CellType::SelfColouredArrow,
+    CellType::Sign,
+    CellType::Overlay,
+    CellType::Converter,
+];
+
+impl LevelState {
+    /// Encodes the level as bytes. Only what the level's author sets is kept:
+    /// the board size and cells, the cows, the allowed cells and the
+    /// solution. Anything recorded while playing, and board options such as
+    /// the input ordering, go back to their defaults.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+        writer.byte(VERSION);
+        writer.point(self.dimensions);
+        writer.byte(self.animation_frame);
+
+        let (default_ground, default_overlay) = self.board.default_cells();
+        write_ground(&mut writer, default_ground);
+        write_overlay(&mut writer, default_overlay);
+        writer.runs(self.dimensions, |writer, point| {
+            write_ground(writer, self.ground_cell_at(point))
+        });
+        writer.runs(self.dimensions, |writer, point| {
+            write_overlay(writer, self.overlay_cell_at(point))
+        });
+
+        let (player, other_players, cow_data) = self.cows.to_data();
+        writer.number(player);
+        writer.number(other_players.len());
+        other_players.into_iter().for_each(|index| writer.number(index));
+        writer.number(cow_data.len());
+        for (position, direction, sprite, children) in cow_data {
+            writer.point(position);
+            writer.byte(direction.into());
+            writer.byte(sprite as u8);
+            writer.number(children.len());
+            children.into_iter().for_each(|index| writer.number(index));
+        }
+
+        writer.optional_list(self.allowed_cells.as_ref(), |writer, cell_type| {
+            let code = CELL_TYPES.iter().position(|other| other == cell_type).unwrap();
+            writer.number(code);
+        });
+        writer.optional_list(self.solution.as_ref(), |writer, command| {
+            writer.byte(command.to_char() as u8);
+        });
+        writer.bytes
+    }
+
+    /// Reads a level written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<LevelState, String> {
+        let mut reader = Reader::new(bytes);
+        let version = reader.byte()?;
+        if version != VERSION {
+            return Err(format!("Unknown level code version {}", version));
+        }
+        let dimensions = reader.point()?;
+        LevelState::check_dimensions(dimensions)?;
+        let animation_frame = reader.byte()? % LevelState::TOTAL_ANIMATION_FRAMES;
+
+        let default_ground = read_ground(&mut reader)?;
+        let default_overlay = read_overlay(&mut reader)?;
+        let mut board = Board::new(default_ground, default_overlay);
+        reader.runs(dimensions, read_ground, |point, cell| {
+            board.set_ground_cell(point, cell)
+        })?;
+        reader.runs(dimensions, read_overlay, |point, cell| {
+            board.set_overlay_cell(point, cell)
+        })?;
+
+        let player = reader.number()?;
+        let other_players = reader.list(|reader| reader.number())?;
+        let cow_data = reader.list(|reader| {
+            let position = reader.point()?;
+            let direction = reader.direction()?;
+            let sprite = reader.byte()?;
+            let sprite = CowSprite::from_u8(sprite)
+                .ok_or_else(|| format!("There is no COW sprite {}", sprite))?;
+            let children = reader.list(|reader| reader.number())?;
+            Ok((position, direction, sprite, children))
+        })?;
+        let cows = Cows::try_with_players(player, other_players, cow_data)
+            .map_err(|error| format!("Invalid level: {}", error))?;
+
+        let allowed_cells = reader.optional_list(|reader| {
+            let code = reader.number()?;
+            CELL_TYPES
+                .get(code)
+                .copied()
+                .ok_or_else(|| format!("There is no cell type {}", code))
+        })?;
+        let solution = reader.optional_list(|reader| {
+            let character = char::from(reader.byte()?);
+            KeyboardCommand::from_char(character)
+                .ok_or_else(|| format!("There is no command '{}'", character))
+        })?;
+        if !reader.is_finished() {
+            return Err("Level code has extra data at the end".to_owned());
+        }
+
+        Ok(LevelState {
+            board,
+            cows,
+            animation_frame,
+            dimensions,
+            allowed_cells,
+            solution,
+            signals: Signals::default(),
+            last_command: None,
+        })
+    }
+
+    /// The level as a base64 code, for sharing.
+    pub fn to_code(&self) -> String {
+        util::base64_encode(&self.to_bytes())
+    }
+
+    /// Reads a level from a code made by `to_code`.
+    pub fn from_code(code: &str) -> Result<LevelState, String> {
+        let bytes = util::base64_decode(code.trim()).ok_or("Level code is not valid")?;
+        LevelState::from_bytes(&bytes)
+    }
+}
+
+fn write_ground(writer: &mut Writer, cell: GroundCell) {
+    match cell {
+        GroundCell::Empty => writer.byte(0),
+        GroundCell::ColouredBlock(colour) => {
+            writer.byte(1);
+            writer.byte(colour.into());
+        }
+        GroundCell::Arrow(direction) => {
+            writer.byte(2);
+            writer.byte(direction.into());
+        }
+        GroundCell::ColouredArrow(colour, direction) => {
+            writer.byte(3);
+            writer.byte(colour.into());
+            writer.byte(direction.into());
+        }
+        GroundCell::ArrowBlock(direction) => {
+            writer.byte(4);
+            writer.byte(direction.into());
+        }
+        GroundCell::RotateRight => writer.byte(5),
+        GroundCell::RotateLeft => writer.byte(6),
+        // surroundings are worked out again as the cells are placed.
+        GroundCell::Fence(_) => writer.byte(7),
+        GroundCell::Wall(_) => writer.byte(8),
+        GroundCell::Gate(condition, is_open) => {
+            writer.byte(9);
+            write_gate_condition(writer, condition);
+            writer.byte(is_open as u8);
+        }
+        GroundCell::Swapper => writer.byte(10),
+        GroundCell::Recorder => writer.byte(11),
+        GroundCell::Multiplier(colour) => {
+            writer.byte(12);
+            writer.byte(colour.map_or(NO_COLOUR, u8::from));
+        }
+        GroundCell::Sequencer(colour) => {
+            writer.byte(13);
+            writer.byte(colour.map_or(NO_COLOUR, u8::from));
+        }
+        GroundCell::PushBlock(colour) => {
+            writer.byte(14);
+            writer.byte(colour.into());
+        }
+        GroundCell::SelfColouredArrow(colour, direction) => {
+            writer.byte(15);
+            writer.byte(colour.into());
+            writer.byte(direction.into());
+        }
+        GroundCell::Sign(direction) => {
+            writer.byte(16);
+            writer.byte(direction.into());
+        }
+        GroundCell::Converter(colour, direction) => {
+            writer.byte(17);
+            writer.byte(colour.into());
+            writer.byte(direction.into());
+        }
+    }
+}
+
+fn read_ground(reader: &mut Reader) -> Result<GroundCell, String> {
+    let cell = match reader.byte()? {
+        0 => GroundCell::Empty,
+        1 => GroundCell::ColouredBlock(reader.colour()?),
+        2 => GroundCell::Arrow(reader.direction()?),
+        3 => GroundCell::ColouredArrow(reader.colour()?, reader.direction()?),
+        4 => GroundCell::ArrowBlock(reader.direction()?),
+        5 => GroundCell::RotateRight,
+        6 => GroundCell::RotateLeft,
+        7 => GroundCell::Fence(Surroundings::new()),
+        8 => GroundCell::Wall(Surroundings::new()),
+        9 => GroundCell::Gate(read_gate_condition(reader)?, reader.byte()? != 0),
+        10 => GroundCell::Swapper,
+        11 => GroundCell::Recorder,
+        12 => GroundCell::Multiplier(reader.optional_colour()?),
+        13 => GroundCell::Sequencer(reader.optional_colour()?),
+        14 => GroundCell::PushBlock(reader.colour()?),
+        15 => GroundCell::SelfColouredArrow(reader.colour()?, reader.direction()?),
+        16 => GroundCell::Sign(reader.direction()?),
+        17 => GroundCell::Converter(reader.colour()?, reader.direction()?),
+        tag => return Err(format!("There is no ground cell {}", tag)),
+    };
+    Ok(cell)
+}
+
+fn write_overlay(writer: &mut Writer, cell: OverlayCell) {
+    let tag = match cell {
+        OverlayCell::Empty => 0,
+        OverlayCell::Success(_) => 1,
+        OverlayCell::Failure(_) => 2,
+        OverlayCell::Input(_) => 3,
+        OverlayCell::Output(_) => 4,
+    };
+    writer.byte(tag);
+}
+
+fn read_overlay(reader: &mut Reader) -> Result<OverlayCell, String> {
+    let cell = match reader.byte()? {
+        0 => OverlayCell::Empty,
+        1 => OverlayCell::Success(Surroundings::new()),
+        2 => OverlayCell::Failure(Surroundings::new()),
+        3 => OverlayCell::Input(Surroundings::new()),
+        4 => OverlayCell::Output(Surroundings::new()),
+        tag => return Err(format!("There is no overlay cell {}", tag)),
+    };
+    Ok(cell)
+}
+
+fn write_gate_condition(writer: &mut Writer, condition: GateCondition) {
+    let (tag, value) = match condition {
+        GateCondition::Even(colour) => (0, colour.into()),
+        GateCondition::Odd(colour) => (1, colour.into()),
+        GateCondition::AtLeast(threshold) => (2, threshold),
+        GateCondition::Signal(channel) => (3, channel),
+        GateCondition::PlayerCarries(colour) => (4, colour.into()),
+    };
+    writer.byte(tag);
+    writer.byte(value);
+}
+
+fn read_gate_condition(reader: &mut Reader) -> Result<GateCondition, String> {
+    let condition = match reader.byte()? {
+        0 => GateCondition::Even(reader.colour()?),
+        1 => GateCondition::Odd(reader.colour()?),
+        2 => GateCondition::AtLeast(reader.byte()?),
+        3 => GateCondition::Signal(reader.byte()?),
+        4 => GateCondition::PlayerCarries(reader.colour()?),
+        tag => return Err(format!("There is no gate condition {}", tag)),
+    };
+    Ok(condition)
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+impl Writer {
+    fn byte(&mut self, byte: u8) {
+        self.bytes.push(byte);
+    }
+    // seven bits at a time, lowest first, with the top bit set on all but
+    // the last byte.
+    fn number(&mut self, mut number: usize) {
+        while number >= 0x80 {
+            self.byte((number & 0x7F) as u8 | 0x80);
+            number >>= 7;
+        }
+        self.byte(number as u8);
+    }
+    // small negative numbers are kept short by interleaving them with the
+    // positive ones.
+    fn signed(&mut self, number: i32) {
+        self.number(((number << 1) ^ (number >> 31)) as u32 as usize);
+    }
+    fn point(&mut self, point: Point<i32>) {
+        self.signed(point.x());
+        self.signed(point.y());
+    }
+    fn optional_list<T, F>(&mut self, list: Option<&Vec<T>>, mut write: F)
+    where
+        F: FnMut(&mut Writer, &T),
+    {
+        match list {
+            None => self.byte(0),
+            Some(list) => {
+                self.byte(1);
+                self.number(list.len());
+                list.iter().for_each(|item| write(self, item));
+            }
+        }
+    }
+    // every cell inside `dimensions` as runs of cells that encode the same.
+    fn runs<F>(&mut self, dimensions: Point<i32>, write: F)
+    where
+        F: Fn(&mut Writer, Point<i32>),
+    {
+        let mut run: Option<(Vec<u8>, usize)> = None;
+        for y in 0..dimensions.y() {
+            for x in 0..dimensions.x() {
+                let mut cell = Writer::default();
+                write(&mut cell, Point(x, y));
+                run = match run {
+                    Some((bytes, length)) if bytes == cell.bytes => Some((bytes, length + 1)),
+                    Some((bytes, length)) => {
+                        self.run(&bytes, length);
+                        Some((cell.bytes, 1))
+                    }
+                    None => Some((cell.bytes, 1)),
+                };
+            }
+        }
+        if let Some((bytes, length)) = run {
+            self.run(&bytes, length);
+        }
+    }
+    fn run(&mut self, bytes: &[u8], length: usize) {
+        self.number(length);
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, position: 0 }
+    }
+    fn is_finished(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+    fn byte(&mut self) -> Result<u8, String> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or("Level code ends too early")?;
+        self.position += 1;
+        Ok(byte)
+    }
+    fn number(&mut self) -> Result<usize, String> {
+        let mut number = 0usize;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= 32 {
+                return Err("Level code has a number that is too big".to_owned());
+            }
+            number |= usize::from(byte & 0x7F) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(number);
+            }
+            shift += 7;
+        }
+    }
+    fn signed(&mut self) -> Result<i32, String> {
+        let number = self.number()? as u32;
+        Ok((number >> 1) as i32 ^ -((number & 1) as i32))
+    }
+    fn point(&mut self) -> Result<Point<i32>, String> {
+        Ok(Point(self.signed()?, self.signed()?))
+    }
+    fn colour(&mut self) -> Result<Colour, String> {
+        let byte = self.byte()?;
+        Colour::from_u8(byte).ok_or_else(|| format!("There is no colour {}", byte))
+    }
+    fn optional_colour(&mut self) -> Result<Option<Colour>, String> {
+        match self.byte()? {
+            NO_COLOUR => Ok(None),
+            byte => Colour::from_u8(byte)
+                .map(Some)
+                .ok_or_else(|| format!("There is no colour {}", byte)),
+        }
+    }
+    fn direction(&mut self) -> Result<Direction, String> {
+        let byte = self.byte()?;
+        Direction::from_u8(byte).ok_or_else(|| format!("There is no direction {}", byte))
+    }
+    fn list<T, F>(&mut self, mut read: F) -> Result<Vec<T>, String>
+    where
+        F: FnMut(&mut Reader<'a>) -> Result<T, String>,
+    {
+        let length = self.number()?;
+        // every item takes at least a byte, so this can't ask for too much.
+        if length > self.bytes.len() - self.position {
+            return Err("Level code ends too early".to_owned());
+        }
+        (0..length).map(|_| read(self)).collect()
+    }
+    fn optional_list<T, F>(&mut self, read: F) -> Result<Option<Vec<T>>, String>
+    where
+        F: FnMut(&mut Reader<'a>) -> Result<T, String>,
+    {
+        match self.byte()? {
+            0 => Ok(None),
+            _ => self.list(read).map(Some),
+        }
+    }
+    fn runs<T, R, F>(&mut self, dimensions: Point<i32>, read: R, mut place: F) -> Result<(), String>
+    where
+        T: Copy,
+        R: Fn(&mut Reader<'a>) -> Result<T, String>,
+        F: FnMut(Point<i32>, T),
+    {
+        let total = usize::try_from(dimensions.x())
+            .ok()
+            .zip(usize::try_from(dimensions.y()).ok())
+            .and_then(|(x, y)| x.checked_mul(y))
+            .ok_or("Level code has a board that is too big")?;
+        let mut index = 0;
+        while index < total {
+            let length = self.number()?;
+            match index.checked_add(length) {
+                Some(end) if length > 0 && end <= total => {}
+                _ => return Err("Level code has cells outside the board".to_owned()),
+            }
+            let cell = read(self)?;
+            for index in index..index + length {
+                let x = (index % dimensions.x() as usize) as i32;
+                let y = (index / dimensions.x() as usize) as i32;
+                place(Point(x, y), cell);
+            }
+            index += length;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEVELS: [&str; 9] = [
+        include_str!("../level_data/blank_level.ron"),
+        include_str!("../level_data/blank_level_4_cows.ron"),
+        include_str!("../level_data/level_0_0.ron"),
+        include_str!("../level_data/level_0_1.ron"),
+        include_str!("../level_data/level_0_2.ron"),
+        include_str!("../level_data/level_0_3.ron"),
+        include_str!("../level_data/level_0_4.ron"),
+        include_str!("../level_data/level_0_5.ron"),
+        include_str!("../level_data/level_0_6.ron"),
+    ];
+
+    fn header(dimensions: Point<i32>) -> Writer {
+        let mut writer = Writer::default();
+        writer.byte(VERSION);
+        writer.point(dimensions);
+        writer.byte(0);
+        write_ground(&mut writer, GroundCell::Empty);
+        write_overlay(&mut writer, OverlayCell::Empty);
+        writer
+    }
+
+    #[test]
+    fn codes_round_trip_every_level() {
+        for level in LEVELS.iter() {
+            let state: LevelState = ron::de::from_str(level).unwrap();
+            let decoded = LevelState::from_code(&state.to_code()).unwrap();
+            assert_eq!(
+                ron::ser::to_string(&decoded).unwrap(),
+                ron::ser::to_string(&state).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_boards_bigger_than_the_screen() {
+        let max = LevelState::default_dimensions();
+        for dimensions in &[Point(max.x() + 1, max.y()), Point(i32::MAX, i32::MAX)] {
+            let writer = header(*dimensions);
+            assert!(LevelState::from_bytes(&writer.bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_runs_past_the_end_of_the_board() {
+        let mut writer = header(Point(2, 2));
+        writer.number(usize::MAX);
+        write_ground(&mut writer, GroundCell::Empty);
+        assert!(LevelState::from_bytes(&writer.bytes).is_err());
+
+        let mut writer = header(Point(2, 2));
+        writer.number(5);
+        write_ground(&mut writer, GroundCell::Empty);
+        assert!(LevelState::from_bytes(&writer.bytes).is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_codes() {
+        let state: LevelState = ron::de::from_str(LEVELS[2]).unwrap();
+        let bytes = state.to_bytes();
+        assert!(LevelState::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -1,14 +1,14 @@
 use serde::{Deserialize, Serialize};
 
-use crate::component::{NextScene, Object};
+use crate::component::{ConfirmDialog, NextScene, Object};
 use crate::direction::Direction;
 use crate::point::Point;
 use crate::{component, util, Assets, Context2D, KeyboardState};
 
-use super::cell::{cell_cursor, OverworldCell, OverworldCellType, Surroundings};
+use super::cell::{cell_cursor, Cell, CellGraphic, OverworldCell, OverworldCellType, Surroundings};
 use super::cow::Cow;
 use super::cow_level::CowLevel;
-use super::{board, cell, KeyboardCommand, Level, LevelState};
+use super::{board, cell, KeyboardCommand, Level, LevelState, PushLimits};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct OverworldLevelState {
@@ -56,6 +56,12 @@ impl OverworldLevelState {
     fn get_player_position(&self) -> Point<i32> {
         self.player.get_position()
     }
+    /// The size of the board in cells, so that the player is always on it.
+    fn dimensions(&self) -> Point<i32> {
+        let extent = self.board.extent();
+        let Point(x, y) = self.get_player_position() + Point(1, 1);
+        Point(extent.x().max(x), extent.y().max(y))
+    }
     fn get_current_cell(&self) -> &cell::OverworldCell {
         let position = self.get_player_position();
         self.board.get_cell(&position)
@@ -73,21 +79,77 @@ impl OverworldLevelState {
     fn command(&mut self, command: KeyboardCommand) {
         self.animation_frame = (self.animation_frame + 1) % LevelState::TOTAL_ANIMATION_FRAMES;
         match command {
-            KeyboardCommand::Direction(direction) => self.player.walk_stop(&self.board, direction),
+            KeyboardCommand::Direction(direction) => {
+                self.player.walk_stop(&mut self.board, direction, &PushLimits::NONE)
+            }
             KeyboardCommand::Space => {}
         }
     }
 }
 
+/// A level reachable from the overworld, and how well the player has done
+/// on it. Only the player's progress is saved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct LevelInfo {
+    #[serde(skip)]
+    pub scene: usize,
+    pub is_solved: bool,
+    pub best_moves: Option<u32>,
+    /// In seconds.
+    #[serde(default)]
+    pub best_time: Option<f64>,
+}
+impl LevelInfo {
+    fn new(scene: usize) -> Self {
+        LevelInfo {
+            scene,
+            ..LevelInfo::default()
+        }
+    }
+    /// Marks the level as solved, keeping the move count and time only if
+    /// they beat the best so far.
+    pub fn solve(&mut self, moves: Option<u32>, time: Option<f64>) {
+        self.is_solved = true;
+        self.best_moves = match (self.best_moves, moves) {
+            (Some(best), Some(moves)) => Some(best.min(moves)),
+            (best, None) => best,
+            (None, moves) => moves,
+        };
+        self.best_time = match (self.best_time, time) {
+            (Some(best), Some(time)) => Some(best.min(time)),
+            (best, None) => best,
+            (None, time) => time,
+        };
+    }
+    fn restore_progress(&mut self, saved: &LevelInfo) {
+        self.is_solved = saved.is_solved;
+        self.best_moves = saved.best_moves;
+        self.best_time = saved.best_time;
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct OverworldLevel {
     cell_palette: cell::CellPalette<cell::OverworldCellType>,
     name: &'static str,
+    profile: String,
+    // the board as loaded, before any saves.
+    initial_state: OverworldLevelState,
     state: OverworldLevelState,
     old_position: Point<i32>,
     animation_time: f64,
-    levels: [usize; 16],
+    levels: [LevelInfo; 16],
     to_reveal_next: Vec<Point<i32>>,
+    // cells cleared by the reveal and how far each has faded in, from 0 to 1.
+    revealing: Vec<(Point<i32>, f64)>,
+    shows_minimap: bool,
+    // multiplies `dt` when advancing `animation_time`.
+    animation_speed: f64,
+    // the player before each move since the board was last saved, oldest
+    // first.
+    history: Vec<Cow>,
+    // asks before the save is wiped.
+    confirm_reset: Option<ConfirmDialog>,
 }
 impl Default for OverworldLevel {
     fn default() -> Self {
@@ -97,11 +159,18 @@ impl Default for OverworldLevel {
         OverworldLevel {
             cell_palette: cell::CellPalette::new(OverworldCellType::full_palette()),
             name: "",
+            profile: util::DEFAULT_PROFILE.to_owned(),
+            initial_state: state.clone(),
             state,
             old_position,
             animation_time: 0.0,
-            levels: [usize::max_value(); 16],
+            levels: [LevelInfo::new(usize::MAX); 16],
             to_reveal_next: Vec::new(),
+            revealing: Vec::new(),
+            shows_minimap: false,
+            animation_speed: 1.0,
+            history: Vec::new(),
+            confirm_reset: None,
         }
     }
 }
@@ -115,7 +184,14 @@ impl component::Component for OverworldLevel {
     fn bounding_rect(&self) -> component::Rect {
         CowLevel::BOUNDING_RECT
     }
+    // the palette is always out in debug builds.
+    fn undo_keys(&self) -> &'static [&'static str] {
+        component::undo_key_codes(crate::DEBUG)
+    }
     fn click(&mut self, point: Point<i32>) -> bool {
+        if let Some(dialog) = self.confirm_reset.as_mut() {
+            return dialog.click(point);
+        }
         if !crate::DEBUG || !self.in_boundary(point) {
             return false;
         }
@@ -129,34 +205,83 @@ impl component::Component for OverworldLevel {
         true
     }
     fn draw(&self, context: &Context2D, assets: &Assets, _args: ()) {
-        let anim_progress = util::clamp(self.animation_time / CowLevel::ANIMATION_TIME, 0.0, 1.0);
+        let anim_progress = if assets.reduces_motion() {
+            1.0
+        } else {
+            util::clamp(self.animation_time / CowLevel::ANIMATION_TIME, 0.0, 1.0)
+        };
         self.fill_bg(context, super::BG_FILL);
 
         self.state
             .draw(context, assets, (self.old_position, anim_progress));
+        self.draw_revealing(context, assets);
+
+        if let Some(info) = self.level_info_at(self.state.get_player_position()) {
+            self.draw_level_info(context, info);
+        }
+        if self.shows_minimap {
+            self.draw_minimap(context, assets, Self::MINIMAP_RECT);
+        }
 
         if crate::DEBUG {
             self.cell_palette.fill_bg(context, cell_cursor::BG_COLOUR);
             self.cell_palette.draw(context, assets, ());
         }
+        if let Some(dialog) = &self.confirm_reset {
+            dialog.draw(context, assets, ());
+        }
+    }
+    fn animate(&mut self, dt: f64) {
+        for (_, progress) in self.revealing.iter_mut() {
+            *progress += dt / Self::CELL_FADE_TIME;
+        }
+        self.revealing.retain(|(_, progress)| *progress < 1.0);
+    }
+    fn on_resize(&mut self, dimensions: Point<i32>) {
+        self.cell_palette.on_resize(dimensions)
+    }
+    fn set_animation_speed(&mut self, speed: f64) {
+        self.animation_speed = speed;
     }
     fn step(&mut self, dt: f64, keyboard_state: &KeyboardState) -> NextScene {
-        self.animation_time += dt;
+        self.animation_time += dt * self.animation_speed;
 
         if !self.to_reveal_next.is_empty() {
             return self.reveal();
         }
+        if let Some(dialog) = self.confirm_reset.as_mut() {
+            if let NextScene::Return(Object::Bool(answer)) = dialog.step(dt, keyboard_state) {
+                self.confirm_reset = None;
+                if answer {
+                    self.reset_save();
+                }
+            }
+            return NextScene::Continue;
+        }
 
         if keyboard_state.is_pressed("KeyL") {
             self.log_level()
         }
+        if keyboard_state.is_pressed("KeyN") {
+            self.shows_minimap = !self.shows_minimap;
+        }
+        let is_palette_key = crate::DEBUG && self.cell_palette.handle_keyboard(keyboard_state);
+        if crate::DEBUG && keyboard_state.is_pressed("Delete") {
+            let dialog = ConfirmDialog::centred_in(Self::RESET_MESSAGE, self.bounding_rect());
+            self.confirm_reset = Some(dialog);
+            return NextScene::Continue;
+        }
+        if !is_palette_key && self.keyboard_event(keyboard_state, self.undo_keys()) {
+            self.undo();
+            return NextScene::Continue;
+        }
 
         if let Some(command) = self.get_keyboard_command(keyboard_state) {
             self.old_position = self.state.get_player_position();
             if command.is_space() {
                 match self.current_cell() {
                     OverworldCell::Level(id, _) => {
-                        let next_level = self.levels[usize::from(*id)];
+                        let next_level = self.levels[usize::from(*id)].scene;
                         return NextScene::Call(next_level, Object::Null);
                     },
                     OverworldCell::Finish => {
@@ -169,7 +294,11 @@ impl component::Component for OverworldLevel {
                     | OverworldCell::ClearPath(_) => {}
                 }
             }
+            let player = self.state.player.clone();
             self.state.command(command);
+            if self.state.get_player_position() != player.get_position() {
+                self.history.push(player);
+            }
 
             self.animation_time = 0.0;
         };
@@ -179,39 +308,159 @@ impl component::Component for OverworldLevel {
 
     fn returned_into(&mut self, object: Object) {
         assert!(self.to_reveal_next.is_empty());
-        if let Object::Bool(true) = object {
-            let point = self.state.get_player_position();
-            Self::add_adjacents(&mut self.to_reveal_next, point);
+        let is_solved = object == Object::Bool(true) || object.get_bool("solved") == Some(true);
+        if !is_solved {
+            return;
         }
+        let moves = object.get_int("moves").map(|moves| moves as u32);
+        let time = object.get_float("time");
+
+        if let OverworldCell::Level(id, _) = *self.current_cell() {
+            self.levels[usize::from(id)].solve(moves, time);
+            self.save_progress();
+        }
+
+        let point = self.state.get_player_position();
+        Self::add_adjacents(&mut self.to_reveal_next, point);
     }
     fn called_into(&mut self, _object: Object) {
         self.restore_state();
     }
+    fn set_profile(&mut self, profile: &str) {
+        self.profile = profile.to_owned();
+        self.clear_state();
+        self.restore_state();
+    }
+    fn serialize_state(&self) -> Option<String> {
+        Some(ron::ser::to_string(&self.state).unwrap())
+    }
 }
 impl OverworldLevel {
     const CELL_REVEAL_TIME: f64 = 300.0;
+    const CELL_FADE_TIME: f64 = 450.0;
+    const RESET_MESSAGE: &'static str = "Wipe the saved overworld\nand all its progress?";
+    const MINIMAP_RECT: component::Rect = component::Rect {
+        top_left: Point(CowLevel::LEVEL_WIDTH * 12, 8),
+        dimensions: Point(CowLevel::LEVEL_WIDTH * 3, CowLevel::LEVEL_HEIGHT * 3),
+    };
     fn log_level(&self) {
         crate::console_log!("{}", ron::ser::to_string(&self.state).unwrap());
     }
     pub fn from_data(name: &'static str, string: &str, connections: [usize; 16]) -> Self {
         let state: OverworldLevelState = ron::de::from_str(string).unwrap();
         let position = state.get_player_position();
+        let mut levels = [LevelInfo::default(); 16];
+        for (info, scene) in levels.iter_mut().zip(connections.iter()) {
+            *info = LevelInfo::new(*scene);
+        }
 
         OverworldLevel {
             cell_palette: cell::CellPalette::new(OverworldCellType::full_palette()),
             name,
+            profile: util::DEFAULT_PROFILE.to_owned(),
+            initial_state: state.clone(),
             state,
             old_position: position,
             animation_time: 0.0,
-            levels: connections,
+            levels,
             to_reveal_next: Vec::new(),
+            revealing: Vec::new(),
+            shows_minimap: false,
+            animation_speed: 1.0,
+            history: Vec::new(),
+            confirm_reset: None,
+        }
+    }
+    /// The level on the tile at the given grid position, if there is one.
+    pub fn level_info_at(&self, point: Point<i32>) -> Option<&LevelInfo> {
+        match self.state.get_cell(&point) {
+            OverworldCell::Level(id, _) => self.levels.get(usize::from(*id)),
+            _ => None,
+        }
+    }
+    fn draw_level_info(&self, context: &Context2D, info: &LevelInfo) {
+        let text = match (info.is_solved, info.best_moves, info.best_time) {
+            (true, Some(moves), Some(time)) => {
+                format!("SOLVED - best: {} moves, {:.1}s", moves, time)
+            }
+            (true, Some(moves), None) => format!("SOLVED - best: {} moves", moves),
+            (true, None, _) => "SOLVED".to_owned(),
+            (false, _, _) => "UNSOLVED".to_owned(),
+        };
+        let rect = CowLevel::BOUNDING_RECT;
+
+        util::with_saved_context(context, || {
+            context.set_font("15px KongText");
+            context.set_text_align("center");
+            context.set_fill_style_str("white");
+            context
+                .fill_text(
+                    &text,
+                    f64::from(rect.dimensions.x() / 2),
+                    f64::from(rect.dimensions.y() - 10),
+                )
+                .unwrap();
+        });
+    }
+    fn progress_key(&self) -> String {
+        util::profile_key(&self.profile, &format!("{}_progress", self.name))
+    }
+    /// Puts back the board as loaded and forgets all progress, without
+    /// touching the save.
+    fn clear_state(&mut self) {
+        self.to_reveal_next.clear();
+        self.revealing.clear();
+        for info in self.levels.iter_mut() {
+            info.restore_progress(&LevelInfo::default());
+        }
+        self.state = self.initial_state.clone();
+        self.old_position = self.state.get_player_position();
+        self.animation_time = 0.0;
+        self.history.clear();
+    }
+    /// Forgets the saved board and progress and starts again.
+    fn reset_save(&mut self) {
+        let local_storage = util::get_storage();
+        for key in [self.storage_key(), self.progress_key()].iter() {
+            if local_storage.remove_item(key).is_err() {
+                crate::console_error!("Could not access local storage");
+            }
+        }
+        self.clear_state();
+    }
+    fn storage_key(&self) -> String {
+        util::profile_key(&self.profile, self.name)
+    }
+    fn restore_progress(&mut self) {
+        let local_storage = util::get_storage();
+
+        match local_storage.get_item(&self.progress_key()) {
+            Err(_) => crate::console_error!("Could not access local storage"),
+            Ok(None) => {}
+            Ok(Some(string)) => match ron::de::from_str::<Vec<LevelInfo>>(&string) {
+                Ok(saved) => {
+                    for (info, saved) in self.levels.iter_mut().zip(saved.iter()) {
+                        info.restore_progress(saved);
+                    }
+                }
+                Err(_) => crate::console_error!("Could not read saved level progress"),
+            },
+        }
+    }
+    fn save_progress(&self) {
+        let local_storage = util::get_storage();
+        let progress_str = ron::ser::to_string(&self.levels.to_vec()).unwrap();
+
+        if local_storage.set_item(&self.progress_key(), &progress_str).is_err() {
+            crate::console_error!("Could not save to local storage");
         }
     }
     fn restore_state(&mut self) {
         assert!(self.to_reveal_next.is_empty());
+        self.restore_progress();
         let local_storage = util::get_storage();
 
-        match local_storage.get_item(self.name) {
+        match local_storage.get_item(&self.storage_key()) {
             Err(_) => crate::console_error!("Could not access local storage"),
             Ok(None) => {},
             Ok(Some(string)) => {
@@ -221,6 +470,7 @@ impl OverworldLevel {
                 self.state = state;
                 self.old_position = position;
                 self.animation_time = 0.0;
+                self.history.clear();
             },
         }
     }
@@ -228,10 +478,18 @@ impl OverworldLevel {
         let local_storage = util::get_storage();
         let state_str = ron::ser::to_string(&self.state).unwrap();
 
-        if local_storage.set_item(self.name, &state_str).is_err() {
+        if local_storage.set_item(&self.storage_key(), &state_str).is_err() {
             crate::console_error!("Could not save to local storage");
         }
     }
+    /// Moves the player back to where they were before their last move.
+    fn undo(&mut self) {
+        if let Some(player) = self.history.pop() {
+            self.old_position = self.state.get_player_position();
+            self.state.player = player;
+            self.animation_time = 0.0;
+        }
+    }
     fn current_cell(&self) -> &cell::OverworldCell {
         self.state.get_current_cell()
     }
@@ -249,17 +507,70 @@ impl OverworldLevel {
             if cell.can_be_cleared() {
                 self.state
                     .set_cell_at_index(*point, OverworldCell::ClearPath(Surroundings::new()));
+                self.revealing.push((*point, 0.0));
                 Self::add_adjacents(&mut new_reveals, *point);
             }
         }
 
         if new_reveals.is_empty() {
             self.save_state();
+            // the saved board has the player here, so don't walk back past it.
+            self.history.clear();
         }
 
         self.to_reveal_next = new_reveals;
         NextScene::Continue
     }
+    /// Draws the whole board shrunk into `rect`, with a dot where the player
+    /// is.
+    pub fn draw_minimap(&self, context: &Context2D, assets: &Assets, rect: component::Rect) {
+        let dimensions = self.state.dimensions();
+        let Point(width, height) = dimensions;
+        let cell = Point(
+            (rect.dimensions.x() / width).max(1),
+            (rect.dimensions.y() / height).max(1),
+        );
+        let Point(player_x, player_y) = self.state.get_player_position() * cell;
+
+        util::with_saved_context(context, || {
+            context
+                .translate(rect.top_left.x().into(), rect.top_left.y().into())
+                .unwrap();
+            context.set_fill_style_str(super::BG_FILL);
+            context.fill_rect(0.0, 0.0, (width * cell.x()).into(), (height * cell.y()).into());
+
+            self.state
+                .board
+                .draw_scaled(context, &assets.blocks, Point(0, 0), dimensions, cell);
+
+            context.set_fill_style_str("red");
+            context.fill_rect(
+                player_x.into(),
+                player_y.into(),
+                cell.x().into(),
+                cell.y().into(),
+            );
+        });
+    }
+    /// Fades newly cleared cells in by drawing what they were over them.
+    fn draw_revealing(&self, context: &Context2D, assets: &Assets) {
+        for (point, progress) in self.revealing.iter() {
+            let blocked = match self.state.get_cell(point) {
+                OverworldCell::ClearPath(surrounds) => OverworldCell::BlockedPath(*surrounds),
+                _ => continue,
+            };
+            let sprite = match blocked.get_sprite_sheet_index() {
+                Some(sprite) => sprite,
+                None => continue,
+            };
+            let Point(x, y) = *point * CellGraphic::CELL_SIZE;
+
+            util::with_saved_context(context, || {
+                context.set_global_alpha(1.0 - util::clamp(*progress, 0.0, 1.0));
+                assets.blocks.draw(context, sprite, Point(f64::from(x), f64::from(y)));
+            });
+        }
+    }
     fn add_adjacents(vector: &mut Vec<Point<i32>>, point: Point<i32>) {
         Direction::for_every(|direction| {
             let mut adjacent = point;
@@ -268,3 +579,35 @@ impl OverworldLevel {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::component::Component;
+    use crate::level::cow::CowSprite;
+
+    fn player_at(position: Point<i32>) -> Cow {
+        Cow::new(position, Direction::Right, vec![], CowSprite::White)
+    }
+
+    #[test]
+    fn minimaps_cover_boards_bigger_than_the_screen() {
+        let mut state = OverworldLevelState {
+            player: player_at(Point(2, 2)),
+            ..OverworldLevelState::default()
+        };
+        state.board.set_cell(Point(40, 3), OverworldCell::ClearPath(Surroundings::new()));
+        state.board.set_cell(Point(5, 20), OverworldCell::ClearPath(Surroundings::new()));
+        assert_eq!(state.dimensions(), Point(41, 21));
+
+        state.player = player_at(Point(50, 25));
+        assert_eq!(state.dimensions(), Point(51, 26));
+    }
+
+    #[test]
+    fn z_only_undoes_without_the_palette() {
+        let level = OverworldLevel::default();
+        assert!(level.undo_keys().contains(&"KeyU"));
+        assert_eq!(level.undo_keys().contains(&"KeyZ"), !crate::DEBUG);
+    }
+}
@@ -6,6 +6,7 @@ mod js_ffi;
 mod level;
 mod point;
 mod scene;
+mod sprite_layout;
 mod sprite_sheet;
 mod state_stack;
 mod tutorial;
@@ -55,16 +56,58 @@ pub struct Assets {
     blocks: SpriteSheet,
     sprites: SpriteSheet,
     misc: SpriteSheet,
+    // set by `LeapsAndBounds` before each draw.
+    colourblind: std::cell::Cell<bool>,
+    // set by `LeapsAndBounds` before each draw.
+    ghosts: std::cell::Cell<bool>,
+    // set by `LeapsAndBounds` before each draw.
+    reduced_motion: std::cell::Cell<bool>,
 }
 #[wasm_bindgen]
 impl Assets {
     pub fn new(blocks: Image, sprites: Image, misc: Image) -> Self {
+        Assets::with_sprite_dimensions(
+            blocks,
+            sprites,
+            misc,
+            SpriteSheet::STANDARD_WIDTH,
+            SpriteSheet::STANDARD_HEIGHT,
+        )
+    }
+    /// Assets whose sheets hold sprites of a size other than the standard
+    /// 16 by 16 pixels.
+    pub fn with_sprite_dimensions(
+        blocks: Image,
+        sprites: Image,
+        misc: Image,
+        sprite_width: i32,
+        sprite_height: i32,
+    ) -> Self {
+        assert!(sprite_width > 0 && sprite_height > 0);
+        let sheet = |image| SpriteSheet::new(image, sprite_width, sprite_height);
         Assets {
-            blocks: SpriteSheet::default_size_new(blocks),
-            sprites: SpriteSheet::default_size_new(sprites),
-            misc: SpriteSheet::default_size_new(misc),
+            blocks: sheet(blocks),
+            sprites: sheet(sprites),
+            misc: sheet(misc),
+            colourblind: std::cell::Cell::new(false),
+            ghosts: std::cell::Cell::new(false),
+            reduced_motion: std::cell::Cell::new(false),
         }
     }
+    pub fn all_loaded(&self) -> bool {
+        self.blocks.is_loaded() && self.sprites.is_loaded() && self.misc.is_loaded()
+    }
+}
+impl Assets {
+    fn is_colourblind(&self) -> bool {
+        self.colourblind.get()
+    }
+    fn shows_ghosts(&self) -> bool {
+        self.ghosts.get()
+    }
+    fn reduces_motion(&self) -> bool {
+        self.reduced_motion.get()
+    }
 }
 
 #[wasm_bindgen]
@@ -72,6 +115,73 @@ pub struct LeapsAndBounds {
     scenes: Scenes,
     keyboard_state: KeyboardState,
     audio: js_ffi::BasicAudioPlayer,
+    is_colourblind: bool,
+    shows_ghosts: bool,
+    // canvas pixels per game pixel.
+    scale: f64,
+    // prefixes the keys of everything saved, so players can keep apart.
+    profile: String,
+    animation_speed: f64,
+    reduces_motion: bool,
+    // the size of the view in game pixels.
+    dimensions: Point<i32>,
+}
+impl LeapsAndBounds {
+    const COLOURBLIND_KEY: &'static str = "colourblind_mode";
+    const REDUCED_MOTION_KEY: &'static str = "reduced_motion";
+    const ANIMATION_SPEED_KEY: &'static str = "animation_speed";
+    const MIN_ANIMATION_SPEED: f64 = 0.25;
+    const MAX_ANIMATION_SPEED: f64 = 4.0;
+    /// Scales must be positive and finite, or the game could not be drawn.
+    fn check_scale(scale: f64) -> Result<f64, String> {
+        if scale.is_finite() && scale > 0.0 {
+            Ok(scale)
+        } else {
+            Err(format!("The scale must be a positive number, not {}", scale))
+        }
+    }
+    /// Brings a speed into the allowed range. Speeds that aren't finite are
+    /// rejected, as clamping would let NaN through.
+    fn check_animation_speed(speed: f64) -> Result<f64, String> {
+        if !speed.is_finite() {
+            return Err(format!("The animation speed must be a number, not {}", speed));
+        }
+        Ok(util::clamp(speed, Self::MIN_ANIMATION_SPEED, Self::MAX_ANIMATION_SPEED))
+    }
+    fn to_game_point(&self, x: i32, y: i32) -> Point<i32> {
+        let scaled = |value: i32| (f64::from(value) / self.scale).floor() as i32;
+        Point(scaled(x), scaled(y))
+    }
+    fn restore_flag(key: &str) -> bool {
+        match util::get_storage().get_item(key) {
+            Ok(Some(value)) => value == "true",
+            Ok(None) => false,
+            Err(_) => {
+                console_error!("Could not access local storage");
+                false
+            }
+        }
+    }
+    fn save_flag(key: &str, flag: bool) {
+        let value = if flag { "true" } else { "false" };
+        if util::get_storage().set_item(key, value).is_err() {
+            console_error!("Could not save to local storage");
+        }
+    }
+    fn restore_animation_speed() -> f64 {
+        match util::get_storage().get_item(Self::ANIMATION_SPEED_KEY) {
+            Ok(Some(value)) => value
+                .parse::<f64>()
+                .map_err(|error| error.to_string())
+                .and_then(Self::check_animation_speed)
+                .unwrap_or(1.0),
+            Ok(None) => 1.0,
+            Err(_) => {
+                console_error!("Could not access local storage");
+                1.0
+            }
+        }
+    }
 }
 impl Default for LeapsAndBounds {
     fn default() -> Self {
@@ -86,23 +196,256 @@ impl LeapsAndBounds {
         #[cfg(debug_assertions)]
         console_error_panic_hook::set_once();
 
+        let animation_speed = Self::restore_animation_speed();
+        let reduces_motion = Self::restore_flag(Self::REDUCED_MOTION_KEY);
+        let mut scenes = Scenes::new();
+        scenes.set_animation_speed(animation_speed);
+        scenes.set_reduced_motion(reduces_motion);
+
         LeapsAndBounds {
-            scenes: Scenes::new(),
+            scenes,
             keyboard_state: KeyboardState::new(),
-            audio: BasicAudioPlayer::new()
+            audio: BasicAudioPlayer::new(),
+            is_colourblind: Self::restore_flag(Self::COLOURBLIND_KEY),
+            shows_ghosts: false,
+            scale: 1.0,
+            profile: util::DEFAULT_PROFILE.to_owned(),
+            animation_speed,
+            reduces_motion,
+            dimensions: level::cow_level::CowLevel::BOUNDING_RECT.dimensions,
         }
     }
     pub fn step(&mut self, dt: f64) {
+        self.scenes.animate(dt);
         self.scenes.step(dt, &self.keyboard_state);
         self.keyboard_state.tick();
     }
     pub fn draw(&self, context: &Context2D, assets: &Assets) {
         context.save();
-        self.scenes.draw(context, assets, ());
+        context.scale(self.scale, self.scale).unwrap();
+        assets.colourblind.set(self.is_colourblind);
+        assets.ghosts.set(self.shows_ghosts);
+        assets.reduced_motion.set(self.reduces_motion);
+        if assets.all_loaded() {
+            self.scenes.draw(context, assets, ());
+        } else {
+            Self::draw_loading(context);
+        }
         context.restore();
     }
+    fn draw_loading(context: &Context2D) {
+        let rect = level::cow_level::CowLevel::BOUNDING_RECT;
+
+        context.set_fill_style_str("black");
+        context.fill_rect(
+            rect.top_left.x().into(),
+            rect.top_left.y().into(),
+            rect.dimensions.x().into(),
+            rect.dimensions.y().into(),
+        );
+
+        context.set_font("25px KongText");
+        context.set_text_align("center");
+        context.set_fill_style_str("white");
+        context
+            .fill_text(
+                "Loading...",
+                f64::from(rect.dimensions.x() / 2),
+                f64::from(rect.dimensions.y() / 2),
+            )
+            .unwrap();
+    }
+    /// Writes each block's colour as a letter on top of it. The setting is
+    /// remembered between visits.
+    pub fn set_colourblind_mode(&mut self, is_colourblind: bool) {
+        self.is_colourblind = is_colourblind;
+        Self::save_flag(Self::COLOURBLIND_KEY, is_colourblind);
+    }
+    pub fn toggle_colourblind_mode(&mut self) {
+        self.set_colourblind_mode(!self.is_colourblind);
+    }
+    /// Draws a faded cow where each cow was before its last move, while it is
+    /// moving.
+    pub fn set_ghosts(&mut self, shows_ghosts: bool) {
+        self.shows_ghosts = shows_ghosts;
+    }
+    /// Sets how many canvas pixels each game pixel covers. Mouse positions
+    /// are given in canvas pixels. Anything but a positive number is
+    /// rejected, and the scale is left as it was.
+    pub fn set_scale(&mut self, scale: f64) -> Result<(), JsValue> {
+        self.scale = Self::check_scale(scale).map_err(|error| JsValue::from_str(&error))?;
+        Ok(())
+    }
+    /// Tells the game the canvas is now `width` by `height` canvas pixels, so
+    /// it can lay itself out again.
+    pub fn resize(&mut self, width: i32, height: i32) {
+        self.dimensions = self.to_game_point(width, height);
+        self.scenes.on_resize(self.dimensions);
+    }
+    /// Sets how fast cows move, where 2.0 is twice as fast as normal. The
+    /// speed is remembered between visits. Speeds that aren't finite are
+    /// rejected, and the speed is left as it was.
+    pub fn set_animation_speed(&mut self, speed: f64) -> Result<(), JsValue> {
+        let speed = Self::check_animation_speed(speed).map_err(|error| JsValue::from_str(&error))?;
+        self.animation_speed = speed;
+        self.scenes.set_animation_speed(speed);
+
+        if util::get_storage()
+            .set_item(Self::ANIMATION_SPEED_KEY, &speed.to_string())
+            .is_err()
+        {
+            console_error!("Could not save to local storage");
+        }
+        Ok(())
+    }
+    pub fn animation_speed(&self) -> f64 {
+        self.animation_speed
+    }
+    /// Skips the wipes between scenes and moves cows without sliding them.
+    /// The setting is remembered between visits.
+    pub fn set_reduced_motion(&mut self, reduces_motion: bool) {
+        self.reduces_motion = reduces_motion;
+        self.scenes.set_reduced_motion(reduces_motion);
+        Self::save_flag(Self::REDUCED_MOTION_KEY, reduces_motion);
+    }
+    pub fn reduces_motion(&self) -> bool {
+        self.reduces_motion
+    }
+    /// Switches to the named profile's saves, loading them.
+    pub fn set_profile(&mut self, name: &str) {
+        self.profile = name.to_owned();
+        self.scenes.set_profile(name);
+    }
+    pub fn profile(&self) -> String {
+        self.profile.clone()
+    }
+    pub fn mouse_move(&mut self, x: i32, y: i32) {
+        let point = self.to_game_point(x, y);
+        self.scenes.hover(point);
+    }
     pub fn left_click(&mut self, x: i32, y: i32) {
         self.audio.play_sound("thinking");
-        self.scenes.click(Point(x, y));
+        let point = self.to_game_point(x, y);
+        self.scenes.click(point);
+    }
+    /// Returns the RON string of the current scene's editable state, or
+    /// `None` if the current scene has nothing to export.
+    pub fn export_current_level(&self) -> Option<String> {
+        self.scenes.serialize_state()
+    }
+    /// Like `export_current_level`, as a shorter level code. `import_level`
+    /// reads either.
+    pub fn export_level_code(&self) -> Option<String> {
+        let ron = self.scenes.serialize_state()?;
+        level::cow_level::CowLevel::level_code(&ron).ok()
+    }
+    /// Turns a signal channel on or off. Signal gates are open while their
+    /// channel is on.
+    pub fn set_signal(&mut self, channel: u8, on: bool) {
+        self.scenes.set_signal(channel, on);
+    }
+    /// Whether the current level is failed (0), running (1) or succeeded
+    /// (2). Scenes that aren't levels count as running.
+    pub fn current_success_state(&self) -> u8 {
+        let state = self.scenes.success_state().unwrap_or(level::SuccessState::Running);
+        state as u8
+    }
+    /// The index of the test the current god level is on, if any.
+    pub fn current_test_index(&self) -> Option<u32> {
+        self.scenes.test_progress().map(|(index, _)| index as u32)
+    }
+    /// The number of tests in the current god level, if any.
+    pub fn total_tests(&self) -> Option<u32> {
+        self.scenes.test_progress().map(|(_, total)| total as u32)
+    }
+    /// How the player has used the current level so far, as RON, if it is a
+    /// level.
+    pub fn interaction_stats(&self) -> Option<String> {
+        let stats = self.scenes.interaction_stats()?;
+        Some(ron::ser::to_string(&stats).unwrap())
+    }
+    /// Describes the current state of the game, to attach to bug reports.
+    pub fn dump_state(&self) -> String {
+        self.scenes.dump_state()
+    }
+    /// Replays a move list such as "URDLW" from the start of the current
+    /// level.
+    pub fn apply_solution(&mut self, solution: &str) -> Result<(), JsValue> {
+        self.scenes
+            .apply_solution(solution)
+            .map_err(|error| JsValue::from_str(&error))
+    }
+    /// Loads a shared level string or level code and starts playing it.
+    pub fn import_level(&mut self, level: &str) -> Result<(), JsValue> {
+        self.scenes.import_level(level).map_err(|error| JsValue::from_str(&error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn only_positive_finite_scales_are_allowed() {
+        assert_eq!(LeapsAndBounds::check_scale(2.0), Ok(2.0));
+        for &scale in [0.0, -1.0, f64::NAN, f64::INFINITY].iter() {
+            assert!(LeapsAndBounds::check_scale(scale).is_err(), "{} was allowed", scale);
+        }
+    }
+
+    #[test]
+    fn animation_speeds_are_clamped_unless_they_are_not_finite() {
+        let check = LeapsAndBounds::check_animation_speed;
+        assert_eq!(check(2.0), Ok(2.0));
+        assert_eq!(check(100.0), Ok(LeapsAndBounds::MAX_ANIMATION_SPEED));
+        assert_eq!(check(0.0), Ok(LeapsAndBounds::MIN_ANIMATION_SPEED));
+        for &speed in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY].iter() {
+            assert!(check(speed).is_err(), "{} was allowed", speed);
+        }
+    }
+
+    /// Every file under `dir`, recursing into subdirectories.
+    fn source_files(dir: &Path) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                files.extend(source_files(&path));
+            } else {
+                files.push(path);
+            }
+        }
+        files
+    }
+
+    // There used to be a second copy of the cell, cow and board modules,
+    // each with its own Colour and Direction, that drifted from the ones the
+    // game uses.
+    #[test]
+    fn colour_and_direction_are_defined_once() {
+        let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        for legacy in ["cell.rs", "cow.rs", "board.rs"].iter() {
+            assert!(!src.join(legacy).exists(), "src/{} is back", legacy);
+        }
+
+        // private or crate-visible copies count too.
+        let defined_in = |definition: &str| -> Vec<PathBuf> {
+            source_files(&src)
+                .into_iter()
+                .filter(|path| {
+                    let text = std::fs::read_to_string(path).unwrap_or_default();
+                    text.lines().any(|line| {
+                        let line = line.trim_start();
+                        let line = line.strip_prefix("pub(crate) ").unwrap_or(line);
+                        let line = line.strip_prefix("pub ").unwrap_or(line);
+                        line.starts_with(definition)
+                    })
+                })
+                .map(|path| path.strip_prefix(&src).unwrap().to_path_buf())
+                .collect()
+        };
+        assert_eq!(defined_in("enum Colour "), vec![PathBuf::from("level/cell/colour.rs")]);
+        assert_eq!(defined_in("enum Direction "), vec![PathBuf::from("direction.rs")]);
     }
 }
@@ -13,6 +13,22 @@ impl<T: Copy> Point<T> {
         self.1
     }
 }
+impl<T> Point<T> {
+    /// Applies `func` to both coordinates.
+    pub fn map<U, F>(self, func: F) -> Point<U>
+    where
+        F: Fn(T) -> U,
+    {
+        Point(func(self.0), func(self.1))
+    }
+    /// Combines each coordinate with the matching one from `other`.
+    pub fn zip_with<U, R, F>(self, other: Point<U>, func: F) -> Point<R>
+    where
+        F: Fn(T, U) -> R,
+    {
+        Point(func(self.0, other.0), func(self.1, other.1))
+    }
+}
 impl<T: Ord> Ord for Point<T> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         self.1.cmp(&other.1).then_with(|| self.0.cmp(&other.0))
@@ -35,6 +51,34 @@ impl Point<i32> {
     pub fn is_inside(self, dimensions: Point<i32>) -> bool {
         self.x() >= 0 && self.x() < dimensions.x() && self.y() >= 0 && self.y() < dimensions.y()
     }
+    /// Divides each coordinate, or gives `None` if either part of `rhs` is
+    /// zero.
+    pub fn checked_div(self, rhs: Point<i32>) -> Option<Self> {
+        Some(Point(self.x().checked_div(rhs.x())?, self.y().checked_div(rhs.y())?))
+    }
+    /// Turns the point a quarter turn clockwise about `about`, with y
+    /// pointing down the screen.
+    pub fn rotate_cw_90(self, about: Point<i32>) -> Self {
+        let Point(x, y) = self - about;
+        about + Point(-y, x)
+    }
+    /// Turns the point half a turn about `about`.
+    pub fn rotate_180(self, about: Point<i32>) -> Self {
+        about + about - self
+    }
+    /// Turns a cell half a turn about the centre of a grid of the given
+    /// dimensions, so that the grid lands back on itself.
+    pub fn rotate_180_within(self, dimensions: Point<i32>) -> Self {
+        self.rotate_180(Point(0, 0)) + dimensions - Point(1, 1)
+    }
+    /// Reflects a cell left to right across a grid of the given width.
+    pub fn mirror_horizontal_within(self, width: i32) -> Self {
+        Point(width - 1 - self.x(), self.y())
+    }
+    pub fn manhattan_distance(self, other: Point<i32>) -> i32 {
+        let Point(x, y) = self - other;
+        x.abs() + y.abs()
+    }
 }
 impl<Rhs, T> std::ops::Mul<Point<Rhs>> for Point<T>
 where
@@ -79,7 +123,84 @@ where
 
 pub fn interpolate_2d(start: Point<i32>, end: Point<i32>, proportion: f64) -> Point<f64> {
     assert!(proportion >= 0.0 && proportion <= 1.0);
-    let x = interpolate(start.x().into(), end.x().into(), proportion);
-    let y = interpolate(start.y().into(), end.y().into(), proportion);
-    Point(x, y)
+    start.zip_with(end, |start, end| interpolate(start.into(), end.into(), proportion))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dividing_by_zero_gives_none() {
+        assert_eq!(Point(7, 9).checked_div(Point(2, 3)), Some(Point(3, 3)));
+        assert_eq!(Point(7, 9).checked_div(Point(0, 3)), None);
+        assert_eq!(Point(7, 9).checked_div(Point(2, 0)), None);
+        assert_eq!(Point(0, 0).checked_div(Point(0, 0)), None);
+    }
+
+    #[test]
+    fn quarter_turns_about_the_origin_go_clockwise() {
+        let origin = Point(0, 0);
+        assert_eq!(Point(1, 0).rotate_cw_90(origin), Point(0, 1));
+        assert_eq!(Point(0, 1).rotate_cw_90(origin), Point(-1, 0));
+        assert_eq!(Point(3, -2).rotate_180(origin), Point(-3, 2));
+
+        let point = Point(5, -7);
+        let full_turn = (0..4).fold(point, |point, _| point.rotate_cw_90(origin));
+        assert_eq!(full_turn, point);
+    }
+
+    #[test]
+    fn turns_about_a_pivot_keep_the_pivot_still() {
+        let pivot = Point(4, 2);
+        assert_eq!(pivot.rotate_cw_90(pivot), pivot);
+        assert_eq!(pivot.rotate_180(pivot), pivot);
+        assert_eq!(Point(6, 2).rotate_cw_90(pivot), Point(4, 4));
+        assert_eq!(Point(6, 3).rotate_180(pivot), Point(2, 1));
+
+        let point = Point(-3, 9);
+        let twice = point.rotate_cw_90(pivot).rotate_cw_90(pivot);
+        assert_eq!(twice, point.rotate_180(pivot));
+    }
+
+    #[test]
+    fn grids_land_back_on_themselves() {
+        let dimensions = Point(4, 3);
+        assert_eq!(Point(0, 0).rotate_180_within(dimensions), Point(3, 2));
+        assert_eq!(Point(1, 2).rotate_180_within(dimensions), Point(2, 0));
+        assert_eq!(Point(0, 2).mirror_horizontal_within(4), Point(3, 2));
+        for x in 0..dimensions.x() {
+            for y in 0..dimensions.y() {
+                let point = Point(x, y);
+                assert!(point.rotate_180_within(dimensions).is_inside(dimensions));
+                assert!(point.mirror_horizontal_within(4).is_inside(dimensions));
+            }
+        }
+    }
+
+    #[test]
+    fn manhattan_distance_is_symmetric() {
+        let points = [Point(0, 0), Point(3, -4), Point(-2, 7), Point(5, 5)];
+        for a in points.iter() {
+            assert_eq!(a.manhattan_distance(*a), 0);
+            for b in points.iter() {
+                assert_eq!(a.manhattan_distance(*b), b.manhattan_distance(*a));
+            }
+        }
+        assert_eq!(Point(3, -4).manhattan_distance(Point(-2, 7)), 16);
+    }
+
+    #[test]
+    fn map_applies_to_both_coordinates() {
+        assert_eq!(Point(3, -4).map(|n| n * 2), Point(6, -8));
+        assert_eq!(Point(3, -4).map(f64::from), Point(3.0, -4.0));
+        assert_eq!(Point(2.5, -1.5).map(f64::floor), Point(2.0, -2.0));
+    }
+
+    #[test]
+    fn zip_with_pairs_matching_coordinates() {
+        assert_eq!(Point(1, 2).zip_with(Point(10, 20), |a, b| a + b), Point(11, 22));
+        assert_eq!(Point(3, 4).zip_with(Point(0.5, 2.0), |a, b| f64::from(a) * b), Point(1.5, 8.0));
+        assert_eq!(Point(1, 5).zip_with(Point(3, 2), i32::max), Point(3, 5));
+    }
 }
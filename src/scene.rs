@@ -1,22 +1,52 @@
+use serde::Serialize;
+
 use crate::{Assets, Context2D};
 
-use crate::component::{Component, NextScene, Rect, Transition, ReturnButton, Brief};
+use crate::component::{
+    Brief, Component, Instrumented, InteractionStats, NextScene, Object, Rect, ReturnButton,
+    Transition,
+};
 use crate::js_ffi::KeyboardState;
-use crate::level::god_level::Test;
-use crate::level::{cow_level, overworld_level};
+use crate::level::cell::Colour;
+use crate::level::god_level::{GodLevelData, RandomTests, Test, TestTarget};
+use crate::level::cell::Signals;
+use crate::level::{cow_level, overworld_level, SuccessState};
 use crate::point::Point;
+use crate::util;
 
 use crate::tutorial;
 
+/// Everything needed to reproduce a bug report.
+#[derive(Serialize)]
+struct StateDump {
+    current_scene: usize,
+    scene_stack: Vec<usize>,
+    // the RON of the current scene's state, if it has any.
+    level: Option<String>,
+}
+
 pub struct Scenes {
     scenes: Vec<Box<dyn Component<DrawArgs = ()>>>,
+    // one per scene, for debugging.
+    names: Vec<&'static str>,
     current_scene: usize,
     scene_stack: Vec<usize>,
+    imported_scene: Option<usize>,
+    // the settings given to every scene, kept for scenes added later.
+    signals: Signals,
+    profile: String,
+    animation_speed: f64,
+    reduces_motion: bool,
+    dimensions: Option<Point<i32>>,
 }
 impl Component for Scenes {
     type DrawArgs = ();
     fn draw(&self, context: &Context2D, assets: &Assets, _args: ()) {
         self.scenes[self.current_scene].draw(context, assets, ());
+
+        if crate::DEBUG {
+            self.draw_scene_name(context);
+        }
     }
     fn bounding_rect(&self) -> Rect {
         self.scenes[self.current_scene].bounding_rect()
@@ -24,8 +54,66 @@ impl Component for Scenes {
     fn click(&mut self, point: Point<i32>) -> bool {
         self.scenes[self.current_scene].click(point)
     }
+    fn hover(&mut self, point: Point<i32>) {
+        self.scenes[self.current_scene].hover(point)
+    }
+    fn animate(&mut self, dt: f64) {
+        self.scenes[self.current_scene].animate(dt)
+    }
+    fn set_signals(&mut self, signals: Signals) {
+        self.signals = signals;
+        for scene in self.scenes.iter_mut() {
+            scene.set_signals(signals);
+        }
+    }
+    fn set_profile(&mut self, profile: &str) {
+        self.profile = profile.to_owned();
+        for scene in self.scenes.iter_mut() {
+            scene.set_profile(profile);
+        }
+    }
+    fn set_animation_speed(&mut self, speed: f64) {
+        self.animation_speed = speed;
+        for scene in self.scenes.iter_mut() {
+            scene.set_animation_speed(speed);
+        }
+    }
+    fn set_reduced_motion(&mut self, reduces_motion: bool) {
+        self.reduces_motion = reduces_motion;
+        for scene in self.scenes.iter_mut() {
+            scene.set_reduced_motion(reduces_motion);
+        }
+    }
+    fn on_resize(&mut self, dimensions: Point<i32>) {
+        self.dimensions = Some(dimensions);
+        for scene in self.scenes.iter_mut() {
+            scene.on_resize(dimensions);
+        }
+    }
+    fn success_state(&self) -> Option<SuccessState> {
+        self.scenes[self.current_scene].success_state()
+    }
+    fn test_progress(&self) -> Option<(usize, usize)> {
+        self.scenes[self.current_scene].test_progress()
+    }
+    fn interaction_stats(&self) -> Option<InteractionStats> {
+        self.scenes[self.current_scene].interaction_stats()
+    }
+    fn apply_solution(&mut self, solution: &str) -> Result<(), String> {
+        self.scenes[self.current_scene].apply_solution(solution)
+    }
+    fn serialize_state(&self) -> Option<String> {
+        self.scenes[self.current_scene].serialize_state()
+    }
+    #[cfg(debug_assertions)]
+    fn check_invariants(&self) {
+        self.scenes[self.current_scene].check_invariants()
+    }
     fn step(&mut self, dt: f64, keyboard_state: &KeyboardState) -> NextScene {
         let next_scene = self.scenes[self.current_scene].step(dt, keyboard_state);
+        #[cfg(debug_assertions)]
+        self.scenes[self.current_scene].check_invariants();
+
         match next_scene {
             NextScene::Continue => NextScene::Continue,
             NextScene::Return(object) => {
@@ -38,16 +126,20 @@ impl Component for Scenes {
                 }
             }
             NextScene::Call(next_scene, object) => {
+                if !self.is_valid_target(next_scene) {
+                    return NextScene::Continue;
+                }
                 self.scene_stack.push(self.current_scene);
                 self.current_scene = next_scene;
                 self.scenes[self.current_scene].called_into(object);
-                assert!(self.current_scene < self.scenes.len());
                 NextScene::Continue
             }
             NextScene::Jump(next_scene, object) => {
+                if !self.is_valid_target(next_scene) {
+                    return NextScene::Continue;
+                }
                 self.current_scene = next_scene;
                 self.scenes[self.current_scene].jumped_into(object);
-                assert!(self.current_scene < self.scenes.len());
                 NextScene::Continue
             }
         }
@@ -55,6 +147,18 @@ impl Component for Scenes {
 }
 
 impl Scenes {
+    // a bad scene index stays on the current scene rather than crashing.
+    fn is_valid_target(&self, index: usize) -> bool {
+        if index < self.scenes.len() {
+            return true;
+        }
+        crate::console_error!(
+            "Scene {} does not exist, staying on scene {}",
+            index,
+            self.current_scene
+        );
+        false
+    }
     pub fn new() -> Self {
         use crate::level::cell::Colour::*;
         use crate::level::god_level::TestTarget::*;
@@ -94,15 +198,7 @@ impl Scenes {
             //8
             cow_level(include_str!("level_data/level_0_3.ron")),
             // 9
-            god_level(
-                "level_1_0",
-                "ACCEPT all cases. (Send\n\
-                all COWs to the GREEN\n\
-                zone.)",
-                vec![
-                    Test::new(vec![], Accept),
-                ]
-            ),
+            god_level_from_data("level_1_0", include_str!("level_data/level_1_0.ron")),
             // 10 accept if all red
             god_level(
                 "level_1_2",
@@ -199,7 +295,7 @@ impl Scenes {
             // 24
             tutorial(10, tutorial::SPEED_TUTORIAL),
             // 25
-            god_level(
+            fuzzed_god_level(
                 "level_1_5",
                 "COPY the INPUT to the\n\
                 OUTPUT. The INPUT may\n\
@@ -211,7 +307,14 @@ impl Scenes {
                     Test::new(vec![], AcceptWith(vec![])),
                     Test::new(vec![Red, Red, Blue, Blue, Red, Blue, Red, Blue], AcceptWith(vec![Red, Red, Blue, Blue, Red, Blue, Red, Blue])),
                     Test::new(vec![Red, Red, Red, Red, Red, Red, Red, Red], AcceptWith(vec![Red, Red, Red, Red, Red, Red, Red, Red])),
-                ]
+                ],
+                RandomTests {
+                    count: 5,
+                    max_len: 8,
+                    colours: &[Red, Blue],
+                    spec: copy_input,
+                    seed: 15,
+                }
             ),
             // 26
             tutorial(27, tutorial::OUTPUT_TUTORIAL),
@@ -282,18 +385,151 @@ impl Scenes {
             ),
         ];
 
+        let names = vec![
+            "overworld_0",        // 0
+            "level_0_0",          // 1
+            "level_0_1",          // 2
+            "level_0_2",          // 3
+            "level_1_1",          // 4
+            "level_0_0_tutorial", // 5
+            "beginning_tutorial", // 6
+            "level_0_1_tutorial", // 7
+            "level_0_3",          // 8
+            "level_1_0",          // 9
+            "level_1_2",          // 10
+            "main_overworld",     // 11
+            "level_2_0",          // 12
+            "level_2_1",          // 13
+            "blank_level",        // 14
+            "level_0_4_tutorial", // 15
+            "level_0_4",          // 16
+            "level_0_5",          // 17
+            "incomplete_level",   // 18
+            "level_0_6",          // 19
+            "overworld_1",        // 20
+            "god_level_tutorial", // 21
+            "input_tutorial",     // 22
+            "level_1_4",          // 23
+            "speed_tutorial",     // 24
+            "level_1_5",          // 25
+            "output_tutorial",    // 26
+            "level_1_3",          // 27
+            "level_1_6",          // 28
+            "overworld_2",        // 29
+            "level_1_3",          // 30
+            "level_1_3",          // 31
+        ];
+        assert_eq!(names.len(), scenes.len());
+
         // MAX BRIEF COLUMN WIDTH: 44
         Scenes {
             scenes,
+            names,
             current_scene: 6,
             scene_stack: Vec::new(),
+            imported_scene: None,
+            signals: Signals::default(),
+            profile: util::DEFAULT_PROFILE.to_owned(),
+            animation_speed: 1.0,
+            reduces_motion: false,
+            dimensions: None,
+        }
+    }
+
+    // brings a scene added after start up in line with the others.
+    fn apply_settings(&self, scene: &mut Box<dyn Component<DrawArgs = ()>>) {
+        scene.set_signals(self.signals);
+        scene.set_profile(&self.profile);
+        scene.set_animation_speed(self.animation_speed);
+        scene.set_reduced_motion(self.reduces_motion);
+        if let Some(dimensions) = self.dimensions {
+            scene.on_resize(dimensions);
         }
     }
+
+    /// Swaps the current scene for another, which is entered as if it had
+    /// been called. The scene stack is left as it is, so the new scene
+    /// returns to wherever the old one would have.
+    pub fn replace_current(&mut self, mut scene: Box<dyn Component<DrawArgs = ()>>) {
+        self.apply_settings(&mut scene);
+        self.scenes[self.current_scene] = scene;
+        self.scenes[self.current_scene].called_into(Object::Null);
+    }
+
+    /// Loads a level from a RON string or level code and enters it. The level replaces any
+    /// previously imported level, and returns to the current scene when done.
+    /// Importing while playing an imported level swaps it for the new one.
+    pub fn import_level(&mut self, string: &str) -> Result<(), String> {
+        let level = cow_level::CowLevel::try_from_str(string)?;
+        let mut scene = wrap_cow_level(level);
+
+        if self.imported_scene == Some(self.current_scene) {
+            self.replace_current(scene);
+            return Ok(());
+        }
+        self.apply_settings(&mut scene);
+
+        let index = match self.imported_scene {
+            Some(index) => {
+                self.scenes[index] = scene;
+                index
+            }
+            None => {
+                self.scenes.push(scene);
+                self.names.push("imported_level");
+                self.scenes.len() - 1
+            }
+        };
+        self.imported_scene = Some(index);
+
+        self.scene_stack.push(self.current_scene);
+        self.current_scene = index;
+        self.scenes[self.current_scene].called_into(Object::Null);
+        Ok(())
+    }
+
+    /// The name of the scene being played, for debugging.
+    pub fn current_scene_name(&self) -> &'static str {
+        self.names[self.current_scene]
+    }
+
+    // the current scene's index and name, in the bottom left corner.
+    fn draw_scene_name(&self, context: &Context2D) {
+        let label = format!("{} {}", self.current_scene, self.current_scene_name());
+        let rect = self.bounding_rect();
+        let left = f64::from(rect.top_left.x()) + 4.0;
+        let bottom = f64::from(rect.top_left.y() + rect.dimensions.y()) - 4.0;
+        util::with_saved_context(context, || {
+            context.set_font("11px KongText");
+            context.set_text_align("left");
+            context.set_text_baseline("bottom");
+            util::draw_text_outlined(context, &label, left, bottom, "white", "black");
+        });
+    }
+
+    /// The scene graph position and the current scene's state as RON.
+    pub fn dump_state(&self) -> String {
+        let dump = StateDump {
+            current_scene: self.current_scene,
+            scene_stack: self.scene_stack.clone(),
+            level: self.serialize_state(),
+        };
+        ron::ser::to_string(&dump).unwrap()
+    }
+
+    pub fn set_signal(&mut self, channel: u8, is_on: bool) {
+        let mut signals = self.signals;
+        signals.set(channel, is_on);
+        self.set_signals(signals);
+    }
 }
 
 fn cow_level(string: &'static str) -> Box<dyn Component<DrawArgs = ()>> {
-    let level = cow_level::CowLevel::from_str(string);
-    Box::new(Transition::new(ReturnButton::new(level)))
+    wrap_cow_level(cow_level::CowLevel::from_str(string))
+}
+
+fn wrap_cow_level(level: cow_level::CowLevel) -> Box<dyn Component<DrawArgs = ()>> {
+    Box::new(Transition::new(ReturnButton::new(Instrumented::new(level))))
 }
 
 fn overworld_level_no_return(
@@ -315,12 +551,138 @@ fn overworld_level(
 
 fn god_level(name: &'static str, description: &'static str, tests: Vec<Test>) -> Box<dyn Component<DrawArgs = ()>> {
     let level = crate::level::god_level::GodLevel::new(name, tests);
+    let level = Instrumented::new(level);
+    Box::new(Transition::new(Brief::new(description, ReturnButton::new(level))))
+}
+
+fn god_level_from_data(name: &'static str, string: &'static str) -> Box<dyn Component<DrawArgs = ()>> {
+    let data = GodLevelData::from_ron(string).unwrap();
+    let description = data.description().to_owned();
+    let level = crate::level::god_level::GodLevel::from_data(name, data);
+    let level = Instrumented::new(level);
+    Box::new(Transition::new(Brief::new(&description, ReturnButton::new(level))))
+}
+
+fn fuzzed_god_level(
+    name: &'static str,
+    description: &'static str,
+    tests: Vec<Test>,
+    random: RandomTests,
+) -> Box<dyn Component<DrawArgs = ()>> {
+    let level = crate::level::god_level::GodLevel::new(name, tests).with_random_tests(random);
+    let level = Instrumented::new(level);
     Box::new(Transition::new(Brief::new(description, ReturnButton::new(level))))
 }
 
+fn copy_input(input: &[Colour]) -> TestTarget {
+    TestTarget::AcceptWith(input.to_vec())
+}
+
 fn tutorial(
     destination: usize,
     screens: &'static [tutorial::Screen],
 ) -> Box<dyn Component<DrawArgs = ()>> {
     Box::new(Transition::new(tutorial::Tutorial::new(destination, screens)))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // what a `Probe` has been told.
+    #[derive(Debug, Default, PartialEq)]
+    struct Settings {
+        signals: Option<Signals>,
+        profile: Option<String>,
+        animation_speed: Option<f64>,
+        reduces_motion: Option<bool>,
+        dimensions: Option<Point<i32>>,
+    }
+
+    struct Probe(Rc<RefCell<Settings>>);
+    impl Component for Probe {
+        type DrawArgs = ();
+        fn bounding_rect(&self) -> Rect {
+            cow_level::CowLevel::BOUNDING_RECT
+        }
+        fn draw(&self, _context: &Context2D, _assets: &Assets, _args: ()) {}
+        fn set_signals(&mut self, signals: Signals) {
+            self.0.borrow_mut().signals = Some(signals);
+        }
+        fn set_profile(&mut self, profile: &str) {
+            self.0.borrow_mut().profile = Some(profile.to_owned());
+        }
+        fn set_animation_speed(&mut self, speed: f64) {
+            self.0.borrow_mut().animation_speed = Some(speed);
+        }
+        fn set_reduced_motion(&mut self, reduces_motion: bool) {
+            self.0.borrow_mut().reduces_motion = Some(reduces_motion);
+        }
+        fn on_resize(&mut self, dimensions: Point<i32>) {
+            self.0.borrow_mut().dimensions = Some(dimensions);
+        }
+    }
+
+    fn probe() -> (Box<dyn Component<DrawArgs = ()>>, Rc<RefCell<Settings>>) {
+        let settings = Rc::new(RefCell::new(Settings::default()));
+        (Box::new(Probe(settings.clone())), settings)
+    }
+
+    #[test]
+    fn replacing_keeps_the_scene_stack() {
+        let mut scenes = Scenes::new();
+        scenes.scene_stack = vec![0, 11];
+        let current = scenes.current_scene;
+        let (scene, _) = probe();
+        scenes.replace_current(scene);
+        assert_eq!(scenes.scene_stack, vec![0, 11]);
+        assert_eq!(scenes.current_scene, current);
+    }
+
+    #[test]
+    fn replaced_scenes_get_every_setting() {
+        let mut scenes = Scenes::new();
+        let mut signals = Signals::default();
+        signals.set(3, true);
+        scenes.set_signals(signals);
+        // the built in scenes load saves on a profile change, so only the
+        // setting is changed here.
+        scenes.profile = "second".to_owned();
+        scenes.set_animation_speed(2.0);
+        scenes.set_reduced_motion(true);
+        scenes.on_resize(Point(640, 480));
+
+        let (scene, settings) = probe();
+        scenes.replace_current(scene);
+        let expected = Settings {
+            signals: Some(signals),
+            profile: Some("second".to_owned()),
+            animation_speed: Some(2.0),
+            reduces_motion: Some(true),
+            dimensions: Some(Point(640, 480)),
+        };
+        assert_eq!(*settings.borrow(), expected);
+    }
+
+    #[test]
+    fn scenes_before_any_resize_are_not_resized() {
+        let mut scenes = Scenes::new();
+        let (scene, settings) = probe();
+        scenes.replace_current(scene);
+        assert_eq!(settings.borrow().dimensions, None);
+        assert_eq!(settings.borrow().animation_speed, Some(1.0));
+    }
+
+    #[test]
+    fn levels_record_interactions() {
+        let mut scenes = Scenes::new();
+        // scene 0 is an overworld, scenes 1 and 4 are a cow and a god level.
+        assert_eq!(scenes.interaction_stats(), None);
+        for &level in [1, 4].iter() {
+            scenes.current_scene = level;
+            assert_eq!(scenes.interaction_stats(), Some(InteractionStats::default()));
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use crate::level::cell::Surroundings;
+use crate::point::Point;
+
+/// Describes where each sprite lives on the sprite sheets. Cells that come in
+/// several variants (colours, directions, surroundings) are given by the
+/// coordinate of their first variant, and the variants follow along the row.
+#[derive(Clone, Debug)]
+pub struct SpriteLayout {
+    // blocks sheet
+    pub coloured_block: Point<u8>,
+    pub arrow_block: Point<u8>,
+    pub rotate_right: Point<u8>,
+    pub rotate_left: Point<u8>,
+    pub coloured_arrow: Point<u8>,
+    pub empty: Point<u8>,
+    pub arrow: Point<u8>,
+    pub blocked_path: Point<u8>,
+    pub clear_path: Point<u8>,
+    pub output: Point<u8>,
+    pub input: Point<u8>,
+    pub failure: Point<u8>,
+    pub success: Point<u8>,
+    pub fence: Point<u8>,
+    pub wall: Point<u8>,
+    /// walls with filled in junctions, see `SpriteLayout::wall_sprite`.
+    pub wall_corner: Point<u8>,
+    pub gate_open: Point<u8>,
+    pub gate_closed: Point<u8>,
+    pub swapper: Point<u8>,
+    pub recorder: Point<u8>,
+    pub multiplier: Point<u8>,
+    pub sequencer: Point<u8>,
+    pub push_block: Point<u8>,
+    pub self_coloured_arrow: Point<u8>,
+    pub sign: Point<u8>,
+    pub converter: Point<u8>,
+    /// levels 0 to 15 are laid out in a 16x4 block, four colours per level.
+    pub level: Point<u8>,
+    pub finish: Point<u8>,
+
+    // palette icons
+    pub eraser_icon: Point<u8>,
+    pub overlay_icon: Point<u8>,
+
+    // sprites sheet
+    /// one row per `CowSprite`, four frames per direction.
+    pub cow: Point<u8>,
+}
+impl SpriteLayout {
+    pub const STANDARD: SpriteLayout = SpriteLayout {
+        coloured_block: Point(0, 0),
+        arrow_block: Point(0, 1),
+        rotate_right: Point(0, 2),
+        rotate_left: Point(1, 2),
+        coloured_arrow: Point(0, 3),
+        empty: Point(0, 4),
+        arrow: Point(0, 7),
+        blocked_path: Point(0, 8),
+        clear_path: Point(0, 9),
+        output: Point(0, 10),
+        input: Point(0, 11),
+        failure: Point(0, 12),
+        success: Point(0, 13),
+        fence: Point(0, 14),
+        wall: Point(0, 15),
+        wall_corner: Point(0, 32),
+        // one gate per `GateCondition` kind.
+        gate_open: Point(5, 26),
+        gate_closed: Point(0, 26),
+        // borrows the colour cycling icon until there is swapper artwork.
+        swapper: Point(4, 0),
+        // borrows the clear path until there is recorder artwork.
+        recorder: Point(0, 9),
+        // borrows the output marker until there is multiplier artwork.
+        multiplier: Point(0, 10),
+        // borrows the input marker until there is sequencer artwork.
+        sequencer: Point(0, 11),
+        push_block: Point(0, 5),
+        self_coloured_arrow: Point(0, 24),
+        sign: Point(4, 5),
+        converter: Point(0, 25),
+        level: Point(0, 16),
+        finish: Point(4, 1),
+
+        eraser_icon: Point(8, 0),
+        overlay_icon: Point(9, 0),
+
+        cow: Point(0, 0),
+    };
+
+    /// The layout used to draw the game.
+    pub const fn current() -> &'static SpriteLayout {
+        &Self::STANDARD
+    }
+
+    /// Offsets a sprite along its row.
+    pub fn along_row(base: Point<u8>, offset: u8) -> Point<u8> {
+        Point(base.x() + offset, base.y())
+    }
+
+    /// The wall row draws an inner corner at every junction. Walls with any
+    /// junction filled in by their diagonal neighbour instead use a variant
+    /// after `wall_corner`, sixteen to a row, ordered by cardinal
+    /// surroundings and then by inner corners.
+    pub fn wall_sprite(&self, surrounds: Surroundings) -> Point<u8> {
+        let cardinals = surrounds.cardinals();
+        let inner_corners = surrounds.inner_corners();
+        if inner_corners == surrounds.junctions() {
+            return Self::along_row(self.wall, cardinals);
+        }
+        // every subset of a cell's junctions but the full set has a variant.
+        let variants = |junctions: u8| (1u8 << junctions.count_ones()) - 1;
+        let before: u8 = (0..cardinals)
+            .map(|earlier| variants(Surroundings::junctions_of(earlier)))
+            .sum();
+        let junctions = surrounds.junctions();
+        let rank = (0..inner_corners)
+            .filter(|corners| corners & !junctions == 0)
+            .count() as u8;
+        let index = before + rank;
+        Point(self.wall_corner.x() + index % 16, self.wall_corner.y() + index / 16)
+    }
+
+    pub fn level_sprite(&self, level_num: u8, colour: u8) -> Point<u8> {
+        assert!(level_num <= 0x0F);
+        let x_offset = level_num - (level_num % 4) + colour;
+        let y_offset = level_num % 4;
+        Point(self.level.x() + x_offset, self.level.y() + y_offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::direction::Direction;
+    use std::collections::HashSet;
+
+    // every cardinal surroundings with every set of its junctions filled.
+    fn every_wall() -> Vec<Surroundings> {
+        let mut walls = Vec::new();
+        for cardinals in 0..16u8 {
+            for filled in 0..16u8 {
+                if filled & !Surroundings::junctions_of(cardinals) != 0 {
+                    continue;
+                }
+                let mut surrounds = Surroundings::new();
+                for direction in Direction::all().iter() {
+                    let bit = 1 << *direction as u8;
+                    surrounds.set_surround(*direction, cardinals & bit != 0);
+                    surrounds.set_diagonal(*direction, filled & bit != 0);
+                }
+                walls.push(surrounds);
+            }
+        }
+        walls
+    }
+
+    #[test]
+    fn every_wall_has_its_own_sprite() {
+        let layout = SpriteLayout::current();
+        let walls = every_wall();
+        assert_eq!(walls.len(), 47);
+
+        let sprites: HashSet<_> = walls.iter().map(|wall| layout.wall_sprite(*wall)).collect();
+        assert_eq!(sprites.len(), walls.len());
+        let corner_count = walls.len() - 16;
+        for sprite in sprites {
+            let is_plain = sprite.y() == layout.wall.y() && sprite.x() < 16;
+            let corner_index = sprite
+                .y()
+                .checked_sub(layout.wall_corner.y())
+                .map(|row| usize::from(row) * 16 + usize::from(sprite.x()));
+            let is_corner = corner_index.is_some_and(|index| index < corner_count);
+            assert!(is_plain || is_corner, "{:?} is off the wall rows", sprite);
+        }
+    }
+}
@@ -2,6 +2,9 @@ use crate::component::Rect;
 use crate::point::Point;
 use crate::{Context2D, Image};
 
+/// A grid of equally sized sprites. Sprites are always drawn at the standard
+/// size, so a sheet drawn at a higher resolution is scaled down to fit, and
+/// source rectangles are given as if the sheet were the standard size.
 #[derive(Debug, Clone)]
 pub struct SpriteSheet {
     image: Image,
@@ -20,16 +23,30 @@ impl SpriteSheet {
             sprite_width,
         }
     }
-    pub fn default_size_new(image: Image) -> Self {
-        SpriteSheet::new(
-            image,
-            SpriteSheet::STANDARD_HEIGHT,
-            SpriteSheet::STANDARD_HEIGHT,
-        )
+    /// The size of one sprite in the sheet's image, in pixels.
+    pub fn sprite_dimensions(&self) -> Point<i32> {
+        Point(self.sprite_width, self.sprite_height)
+    }
+    /// Where a rectangle of a standard sized sheet is in this sheet's image.
+    fn to_source(&self, rect: &Rect) -> Rect {
+        let standard = Point(SpriteSheet::STANDARD_WIDTH, SpriteSheet::STANDARD_HEIGHT);
+        let scale = |point: Point<i32>| {
+            (point * self.sprite_dimensions())
+                .checked_div(standard)
+                .unwrap_or(point)
+        };
+        Rect {
+            top_left: scale(rect.top_left),
+            dimensions: scale(rect.dimensions),
+        }
     }
     pub fn get_image(&self) -> &Image {
         &self.image
     }
+    /// A broken image is also `complete`, but has no width.
+    pub fn is_loaded(&self) -> bool {
+        self.image.complete() && self.image.natural_width() > 0
+    }
     pub fn draw(&self, context: &Context2D, sprite_index: Point<u8>, offset: Point<f64>) {
         let sx = f64::from(sprite_index.x()) * f64::from(self.sprite_width);
         let sy = f64::from(sprite_index.y()) * f64::from(self.sprite_height);
@@ -45,12 +62,13 @@ impl SpriteSheet {
                 height,
                 offset.x(),
                 offset.y(),
-                width,
-                height,
+                f64::from(SpriteSheet::STANDARD_WIDTH),
+                f64::from(SpriteSheet::STANDARD_HEIGHT),
             )
             .unwrap();
     }
     pub fn draw_with_rect(&self, context: &Context2D, source: &Rect, dest: &Rect) {
+        let source = self.to_source(source);
         context
             .draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
                 &self.image,
@@ -75,6 +93,7 @@ impl SpriteSheet {
         let dest_width = source.dimensions.x() * dest_height / source.dimensions.y();
         let dest_left = dest_centre.x() - (dest_width / 2);
         let dest_top = dest_centre.y() - (dest_height / 2);
+        let source = self.to_source(source);
 
         context
             .draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
@@ -65,6 +65,16 @@ impl<T> StateStack<T> {
         self.time_direction = TimeDirection::Forward;
     }
 
+    #[cfg(debug_assertions)]
+    pub fn check_invariants(&self) {
+        if let TimeDirection::Backward = self.time_direction {
+            assert!(
+                !self.state_stack.is_empty(),
+                "StateStack is going backwards in time with no previous states"
+            );
+        }
+    }
+
     /// This returns a reference to the last state, That is, the last current
     /// state before this one.
     ///
@@ -77,6 +87,16 @@ impl<T> StateStack<T> {
         }
     }
 
+    /// The states from the oldest up to and including the current state.
+    /// States that have been undone are left out.
+    pub fn history(&self) -> Vec<&T> {
+        let mut history: Vec<&T> = self.state_stack.iter().collect();
+        if let TimeDirection::Forward = self.time_direction {
+            history.push(&self.stack_top);
+        }
+        history
+    }
+
     /// returns a reference to the current state.
     pub fn current_state(&self) -> &T {
         match self.time_direction {
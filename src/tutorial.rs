@@ -1,6 +1,6 @@
 use crate::component::{NextScene, Object, Rect};
 use crate::point::Point;
-use crate::{component, Assets, Context2D, KeyboardState};
+use crate::{component, util, Assets, Context2D, KeyboardState};
 
 #[derive(Clone, Debug)]
 pub struct Tutorial {
@@ -24,6 +24,10 @@ impl Tutorial {
         self.cursor += 1;
         self.text_cursor = 0;
     }
+    fn previous_screen(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+        self.text_cursor = 0;
+    }
     fn current_cursor(&self) -> usize {
         if self.cursor >= self.screens.len() {
             self.screens.len() - 1
@@ -35,6 +39,9 @@ impl Tutorial {
     fn current_text(&self) -> &'static str {
         self.screens[self.current_cursor()].text
     }
+    fn current_lines(&self) -> Vec<&'static str> {
+        util::word_wrap(self.current_text(), Self::MAX_LINE_WIDTH)
+    }
     fn current_image(&self) -> &Rect {
         &self.screens[self.current_cursor()].image
     }
@@ -43,7 +50,8 @@ impl Tutorial {
     }
 
     fn is_screen_finished(&self) -> bool {
-        self.text_cursor >= self.current_text().len()
+        let total: usize = self.current_lines().iter().map(|line| line.len()).sum();
+        self.text_cursor >= total
     }
     fn finish_screen(&mut self) {
         self.text_cursor = usize::max_value();
@@ -56,6 +64,7 @@ impl Tutorial {
     }
 
     const TEXT_SPEED: f64 = 30.0;
+    const MAX_LINE_WIDTH: usize = 41;
 
     const BG_IMG_RECT: Rect = Rect::new(Point(0, 0), Point(64, 32));
     const BOUNDING_RECT: Rect = crate::level::cow_level::CowLevel::BOUNDING_RECT;
@@ -83,12 +92,20 @@ impl Tutorial {
 }
 impl component::Component for Tutorial {
     type DrawArgs = ();
-    fn step(&mut self, dt: f64, keyboard_state: &KeyboardState) -> NextScene {
+    fn animate(&mut self, dt: f64) {
         self.animation_time += dt;
         if self.animation_time > Self::TEXT_SPEED {
             self.animation_time = 0.0;
             self.text_cursor = self.text_cursor.saturating_add(1);
         }
+    }
+    fn step(&mut self, _dt: f64, keyboard_state: &KeyboardState) -> NextScene {
+        if keyboard_state.is_pressed("Escape") {
+            return NextScene::Jump(self.destination, Object::Null);
+        }
+        if keyboard_state.is_pressed("Backspace") || keyboard_state.is_pressed("ArrowLeft") {
+            self.previous_screen();
+        }
 
         if keyboard_state.is_pressed("Space") {
             if self.is_screen_finished() {
@@ -135,26 +152,23 @@ impl component::Component for Tutorial {
         );
 
         context.set_font("11px KongText");
-        let black = wasm_bindgen::JsValue::from_str("black");
-        context.set_fill_style(&black);
+        let draw_text = |text: &str, x: f64, y: f64| {
+            util::draw_text_outlined(context, text, x, y, "black", "white");
+        };
 
         let mut baseline = Self::TOP_MARGIN;
         let mut chars_left_to_print = self.text_cursor;
 
-        for line in self.current_text().lines() {
+        for line in self.current_lines() {
             if chars_left_to_print == 0 {
                 break;
             }
 
             if chars_left_to_print >= line.len() {
-                context
-                    .fill_text(line, Self::LEFT_MARGIN, baseline)
-                    .unwrap();
+                draw_text(line, Self::LEFT_MARGIN, baseline);
                 chars_left_to_print -= line.len();
             } else {
-                context
-                    .fill_text(&line[(0..chars_left_to_print)], Self::LEFT_MARGIN, baseline)
-                    .unwrap();
+                draw_text(&line[0..chars_left_to_print], Self::LEFT_MARGIN, baseline);
                 chars_left_to_print = 0;
             };
 
@@ -162,9 +176,7 @@ impl component::Component for Tutorial {
         }
 
         if self.is_screen_finished() {
-            context
-                .fill_text("press SPACE", Self::RIGHT_TEXT, Self::BOTTOM_TEXT)
-                .unwrap();
+            draw_text("press SPACE", Self::RIGHT_TEXT, Self::BOTTOM_TEXT);
         }
     }
 }
@@ -11,6 +11,21 @@ pub fn with_saved_context<F: FnOnce()>(context: &Context2D, func: F) {
     context.restore();
 }
 
+/// Draws text with an outline around each letter, so it can be read over
+/// any background. The font and alignment are left as they are.
+pub fn draw_text_outlined(context: &Context2D, text: &str, x: f64, y: f64, fill: &str, stroke: &str) {
+    with_saved_context(context, || {
+        context.set_line_width(OUTLINE_WIDTH);
+        context.set_line_join("round");
+        context.set_stroke_style_str(stroke);
+        context.stroke_text(text, x, y).unwrap();
+
+        context.set_fill_style_str(fill);
+        context.fill_text(text, x, y).unwrap();
+    });
+}
+const OUTLINE_WIDTH: f64 = 3.0;
+
 pub fn clamp(value: f64, lower: f64, upper: f64) -> f64 {
     assert!(lower < upper);
     if value > upper {
@@ -22,7 +37,115 @@ pub fn clamp(value: f64, lower: f64, upper: f64) -> f64 {
     value
 }
 
+/// Breaks text into lines of at most `width` characters without splitting
+/// words. Newlines are always kept, and a word longer than `width` gets a line
+/// of its own.
+pub fn word_wrap(text: &str, width: usize) -> Vec<&str> {
+    let mut lines = Vec::new();
+
+    for hard_line in text.lines() {
+        let mut line_start = 0;
+        let mut line_end = 0;
+        let mut word_start = 0;
+
+        for word in hard_line.split(' ') {
+            let word_end = word_start + word.len();
+            if !word.is_empty() {
+                let is_too_long = hard_line[line_start..word_end].chars().count() > width;
+                if line_end > line_start && is_too_long {
+                    lines.push(&hard_line[line_start..line_end]);
+                    line_start = word_start;
+                }
+                line_end = word_end;
+            }
+            word_start = word_end + 1;
+        }
+        lines.push(&hard_line[line_start..line_end]);
+    }
+    lines
+}
+
+/// A small xorshift random number generator. It is not suitable for anything
+/// that needs to be unpredictable, but the same seed always gives the same
+/// numbers.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+impl Rng {
+    // xorshift never leaves zero, so a zero seed is swapped for this.
+    const ZERO_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+    pub fn new(seed: u64) -> Self {
+        Rng(if seed == 0 { Self::ZERO_SEED } else { seed })
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    /// A number from `0` up to but not including `bound`.
+    pub fn below(&mut self, bound: usize) -> usize {
+        assert!(bound > 0);
+        (self.next_u64() % bound as u64) as usize
+    }
+}
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The local storage key used for `key` by the given profile. The default
+/// profile uses bare keys, so saves from before profiles still load.
+pub fn profile_key(profile: &str, key: &str) -> String {
+    if profile == DEFAULT_PROFILE {
+        key.to_owned()
+    } else {
+        format!("{}/{}", profile, key)
+    }
+}
+
 pub fn get_storage () -> web_sys::Storage {
     let window = web_sys::window().unwrap();
     window.local_storage().unwrap().unwrap()
-}
\ No newline at end of file
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes as base64 text, with padding.
+pub fn base64_encode(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (index, byte)| group | u32::from(*byte) << (16 - index * 8));
+        for index in 0..4 {
+            if index <= chunk.len() {
+                let sextet = (group >> (18 - index * 6)) & 0x3F;
+                text.push(char::from(BASE64_ALPHABET[sextet as usize]));
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// Decodes base64 text, or gives `None` if it isn't valid base64.
+pub fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=').as_bytes();
+    if text.len() % 4 == 1 {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.chunks(4) {
+        let mut group = 0u32;
+        for (index, character) in chunk.iter().enumerate() {
+            let sextet = BASE64_ALPHABET.iter().position(|c| c == character)?;
+            group |= (sextet as u32) << (18 - index * 6);
+        }
+        for index in 0..chunk.len() - 1 {
+            bytes.push((group >> (16 - index * 8)) as u8);
+        }
+    }
+    Some(bytes)
+}